@@ -0,0 +1,139 @@
+use std::time::Duration;
+use crate::scene::Camera;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FullscreenMode {
+    Windowed,
+    Borderless,
+    Exclusive
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VsyncMode {
+    On,
+    Adaptive,
+    Mailbox,
+    Off
+}
+
+impl VsyncMode {
+    pub fn select(&self, available: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let preferences: &[wgpu::PresentMode] = match self {
+            VsyncMode::On => &[wgpu::PresentMode::Fifo],
+            VsyncMode::Adaptive => &[wgpu::PresentMode::FifoRelaxed, wgpu::PresentMode::Fifo],
+            VsyncMode::Mailbox => &[wgpu::PresentMode::Mailbox, wgpu::PresentMode::Fifo],
+            VsyncMode::Off => &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Fifo]
+        };
+
+        // Fifo is the only mode every surface has to support.
+        preferences.iter()
+            .copied()
+            .find(|mode| available.contains(mode))
+            .unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+#[derive(Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    // Window sizes are in logical pixels, an 800x600 window takes 1600x1200 pixels at a scale factor of 2.
+    pub width: u32,
+    pub height: u32,
+    pub min_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    pub decorations: bool,
+    pub fullscreen: FullscreenMode,
+    pub icon: Option<String>,
+    pub vsync: VsyncMode,
+    pub max_frame_latency: u32,
+    pub camera: Camera,
+    pub clear_color: Option<wgpu::Color>,
+    // Fraction of the window resolution the scene is drawn at, then scaled to fit.
+    pub render_scale: f32
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "Fuji Engine".to_string(),
+            width: 800,
+            height: 600,
+            min_size: None,
+            resizable: true,
+            decorations: true,
+            fullscreen: FullscreenMode::Windowed,
+            icon: None,
+            vsync: VsyncMode::On,
+            max_frame_latency: 2,
+            camera: Camera::default(),
+            clear_color: None,
+            render_scale: 1.0
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RendererConfig {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub clear_color: wgpu::Color,
+    // Draws the scene in floating point so bloom and tonemapping see light above 1.0.
+    pub hdr: bool
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            clear_color: wgpu::Color {
+                r: 0.0,
+                g: 0.0,
+                b: 1.0,
+                a: 1.0
+            },
+            hdr: true
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    Poll,
+    Wait,
+    WaitUntil
+}
+
+#[derive(Clone)]
+pub struct LoopConfig {
+    pub mode: LoopMode,
+    pub target_fps: Option<f64>,
+    pub spin_threshold: Duration
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        LoopConfig {
+            mode: LoopMode::Poll,
+            target_fps: None,
+            spin_threshold: Duration::from_millis(2)
+        }
+    }
+}
+
+impl LoopConfig {
+    pub fn game(target_fps: f64) -> Self {
+        LoopConfig {
+            mode: LoopMode::WaitUntil,
+            target_fps: Some(target_fps),
+            ..Default::default()
+        }
+    }
+
+    pub fn tool() -> Self {
+        LoopConfig {
+            mode: LoopMode::Wait,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+use winit::event_loop::ControlFlow;
+
+use super::config::LoopMode;
+
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    spin_threshold: Duration,
+    next_frame: Option<Instant>
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        Self::new(None, Duration::from_millis(2))
+    }
+}
+
+impl FrameLimiter {
+    pub fn new(target_fps: Option<f64>, spin_threshold: Duration) -> Self {
+        FrameLimiter {
+            frame_time: target_fps.map(frame_time),
+            spin_threshold,
+            next_frame: None
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<f64> {
+        self.frame_time.map(|frame_time| 1.0 / frame_time.as_secs_f64())
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: Option<f64>) {
+        self.frame_time = target_fps.map(frame_time);
+        self.next_frame = None;
+    }
+
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.next_frame
+    }
+
+    // Sleeps for most of the remaining frame time, then spins the last stretch
+    // because sleep alone overshoots by up to a scheduler tick.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return
+        };
+
+        let deadline = self.next_frame.unwrap_or_else(Instant::now);
+        let now = Instant::now();
+        if deadline > now {
+            let remaining = deadline - now;
+            if remaining > self.spin_threshold {
+                std::thread::sleep(remaining - self.spin_threshold);
+            }
+            while Instant::now() < deadline {
+                std::thread::yield_now();
+            }
+        }
+
+        self.next_frame = Some(next_deadline(deadline, Instant::now(), frame_time));
+    }
+
+    pub fn control_flow(&self, mode: LoopMode) -> ControlFlow {
+        match mode {
+            LoopMode::Poll => ControlFlow::Poll,
+            LoopMode::Wait => ControlFlow::Wait,
+            LoopMode::WaitUntil => match self.next_frame {
+                // Wake a little early and let wait() spin to the exact deadline.
+                Some(next_frame) => ControlFlow::WaitUntil(next_frame.checked_sub(self.spin_threshold).unwrap_or(next_frame)),
+                None if self.frame_time.is_some() => ControlFlow::Poll,
+                None => ControlFlow::Wait
+            }
+        }
+    }
+}
+
+// A frame that ran long resyncs the schedule instead of bursting to catch up.
+fn next_deadline(deadline: Instant, now: Instant, frame_time: Duration) -> Instant {
+    if now > deadline + frame_time {
+        now + frame_time
+    } else {
+        deadline + frame_time
+    }
+}
+
+fn frame_time(target_fps: f64) -> Duration {
+    assert!(target_fps > 0.0, "Target frame rate must be greater than zero");
+    Duration::from_secs_f64(1.0 / target_fps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn on_time_frames_keep_a_fixed_cadence() {
+        let start = Instant::now();
+        assert_eq!(next_deadline(start, start, FRAME), start + FRAME);
+        // Waking a little late doesn't push the following frames back.
+        assert_eq!(next_deadline(start, start + Duration::from_millis(3), FRAME), start + FRAME);
+    }
+
+    #[test]
+    fn a_short_overshoot_is_caught_up() {
+        let start = Instant::now();
+        let late = start + Duration::from_millis(9);
+        assert_eq!(next_deadline(start, late, FRAME), start + FRAME);
+        assert_eq!(next_deadline(start, start + FRAME, FRAME), start + FRAME);
+    }
+
+    #[test]
+    fn a_long_frame_resyncs_instead_of_bursting() {
+        let start = Instant::now();
+        let late = start + Duration::from_millis(35);
+        assert_eq!(next_deadline(start, late, FRAME), late + FRAME);
+    }
+
+    #[test]
+    fn target_rate_round_trips() {
+        let mut limiter = FrameLimiter::new(Some(50.0), Duration::from_millis(2));
+        assert!((limiter.target_fps().unwrap() - 50.0).abs() < 1e-9);
+        assert!(matches!(limiter.control_flow(LoopMode::WaitUntil), ControlFlow::Poll));
+
+        limiter.set_target_fps(None);
+        assert_eq!(limiter.target_fps(), None);
+        assert!(matches!(limiter.control_flow(LoopMode::WaitUntil), ControlFlow::Wait));
+        // Unlimited frames never wait.
+        limiter.wait();
+        assert_eq!(limiter.next_frame(), None);
+    }
+}
@@ -0,0 +1,180 @@
+pub mod config;
+pub mod plugin;
+pub mod window;
+pub mod time;
+pub mod frame_limiter;
+
+pub use config::{WindowConfig, RendererConfig, FullscreenMode, VsyncMode, LoopMode, LoopConfig};
+pub use plugin::{Plugin, RenderPlugin, InputPlugin, DefaultPlugins};
+#[cfg(feature = "audio")]
+pub use plugin::AudioPlugin;
+pub use window::{WindowControl, WindowRequest, Windows};
+pub use time::Time;
+pub use frame_limiter::FrameLimiter;
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::{App, GraphicState, PostProcess, run_with};
+use crate::ecs::{World, Schedule, System, Resource};
+use crate::input::{ActionMap, Actions, GamepadBackend, InputRecorder, InputReplay};
+
+pub struct AppBuilder {
+    app: App<'static>,
+    plugins: Vec<String>
+}
+
+impl Default for AppBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AppBuilder {
+    pub fn new() -> Self {
+        let mut app = App::empty();
+        app.world.insert_resource(WindowControl::default());
+        app.world.insert_resource(Windows::default());
+        app.world.insert_resource(Time::default());
+
+        AppBuilder {
+            app,
+            plugins: Vec::new()
+        }
+    }
+
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        if self.has_plugin(plugin.name()) {
+            warn!("Plugin {} was already added", plugin.name());
+            return self;
+        }
+
+        self.plugins.push(plugin.name().to_string());
+        plugin.build(self);
+        self
+    }
+
+    pub fn has_plugin(&self, name: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin == name)
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, system: S) -> &mut Self {
+        self.app.add_system(system);
+        self
+    }
+
+    pub fn add_system_to_stage<S: System + 'static>(&mut self, stage: &str, system: S) -> &mut Self {
+        self.app.add_system_to_stage(stage, system);
+        self
+    }
+
+    pub fn add_fixed_system<S: System + 'static>(&mut self, system: S) -> &mut Self {
+        self.app.fixed_schedule.add_system(system);
+        self
+    }
+
+    pub fn add_fixed_system_to_stage<S: System + 'static>(&mut self, stage: &str, system: S) -> &mut Self {
+        self.app.fixed_schedule.add_system_to_stage(stage, system);
+        self
+    }
+
+    pub fn with_fixed_timestep(&mut self, fixed_delta: Duration) -> &mut Self {
+        self.app.world.resource_mut::<Time>().set_fixed_delta(fixed_delta);
+        self
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> &mut Self {
+        self.app.world.insert_resource(resource);
+        self
+    }
+
+    pub fn add_setup<F: FnOnce(&mut GraphicState<'static>, &mut World) + 'static>(&mut self, setup: F) -> &mut Self {
+        self.app.setups.push(Box::new(setup));
+        self
+    }
+
+    pub fn with_window(&mut self, config: WindowConfig) -> &mut Self {
+        self.app.window_config = config;
+        self
+    }
+
+    pub fn add_window(&mut self, config: WindowConfig) -> &mut Self {
+        self.app.extra_windows.push(config);
+        self
+    }
+
+    pub fn with_loop(&mut self, config: LoopConfig) -> &mut Self {
+        if config.mode == LoopMode::WaitUntil && config.target_fps.is_none() {
+            warn!("LoopMode::WaitUntil without a target frame rate only redraws on events");
+        }
+        self.app.loop_config = config;
+        self
+    }
+
+    pub fn with_actions(&mut self, map: ActionMap) -> &mut Self {
+        self.app.world.insert_resource(Actions::new(map));
+        self
+    }
+
+    pub fn with_post_process(&mut self, post_process: PostProcess) -> &mut Self {
+        self.app.world.insert_resource(post_process);
+        self
+    }
+
+    pub fn with_gamepad_backend<B: GamepadBackend + 'static>(&mut self, backend: B) -> &mut Self {
+        self.app.gamepad_backend = Some(Box::new(backend));
+        self
+    }
+
+    pub fn has_gamepad_backend(&self) -> bool {
+        self.app.gamepad_backend.is_some()
+    }
+
+    pub fn record_input<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.app.input_recorder = Some(InputRecorder::new());
+        self.app.recording_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn replay_input(&mut self, replay: InputReplay) -> &mut Self {
+        self.app.input_replay = Some(replay);
+        self
+    }
+
+    pub fn with_renderer(&mut self, config: RendererConfig) -> &mut Self {
+        self.app.renderer_config = config;
+        self
+    }
+
+    pub fn enable_renderer(&mut self) -> &mut Self {
+        self.app.render_enabled = true;
+        self
+    }
+
+    pub fn has_renderer(&self) -> bool {
+        self.app.render_enabled
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    pub fn schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.app.schedule
+    }
+
+    pub fn fixed_schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.app.fixed_schedule
+    }
+
+    pub fn build(&mut self) -> App<'static> {
+        self.plugins.clear();
+        std::mem::replace(&mut self.app, App::empty())
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        run_with(self.build())
+    }
+}
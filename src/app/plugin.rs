@@ -0,0 +1,92 @@
+use super::AppBuilder;
+use super::config::RendererConfig;
+use crate::input::{Input, Gamepads};
+#[cfg(feature = "gamepad")]
+use tracing::warn;
+
+pub trait Plugin {
+    fn name(&self) -> &str;
+    fn build(&self, app: &mut AppBuilder);
+}
+
+#[derive(Default)]
+pub struct RenderPlugin {
+    pub config: Option<RendererConfig>
+}
+
+impl RenderPlugin {
+    pub fn with_config(config: RendererConfig) -> Self {
+        RenderPlugin {
+            config: Some(config)
+        }
+    }
+}
+
+impl Plugin for RenderPlugin {
+    fn name(&self) -> &str {
+        "render"
+    }
+
+    fn build(&self, app: &mut AppBuilder) {
+        if let Some(config) = &self.config {
+            app.with_renderer(config.clone());
+        }
+        app.enable_renderer();
+    }
+}
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn name(&self) -> &str {
+        "input"
+    }
+
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Input::default());
+        app.insert_resource(Gamepads::default());
+
+        // A backend set before the plugin, such as a virtual one for tests, is kept.
+        #[cfg(feature = "gamepad")]
+        if !app.has_gamepad_backend() {
+            match crate::input::GilrsBackend::new() {
+                Ok(backend) => {
+                    app.with_gamepad_backend(backend);
+                },
+                Err(e) => {
+                    warn!("Gamepads are unavailable: {:#}", e);
+                    app.with_gamepad_backend(crate::input::NullGamepadBackend);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+pub struct AudioPlugin;
+
+#[cfg(feature = "audio")]
+impl Plugin for AudioPlugin {
+    fn name(&self) -> &str {
+        "audio"
+    }
+
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(crate::audio::Audio::new());
+    }
+}
+
+pub struct DefaultPlugins;
+
+impl Plugin for DefaultPlugins {
+    fn name(&self) -> &str {
+        "default_plugins"
+    }
+
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(RenderPlugin::default());
+        app.add_plugin(InputPlugin);
+        #[cfg(feature = "audio")]
+        app.add_plugin(AudioPlugin);
+    }
+}
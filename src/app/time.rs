@@ -0,0 +1,293 @@
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub struct Time {
+    startup: Instant,
+    last_update: Option<Instant>,
+    raw_delta: Duration,
+    delta: Duration,
+    elapsed: Duration,
+    frame_count: u64,
+    time_scale: f32,
+    paused: bool,
+    fixed_delta: Duration,
+    fixed_elapsed: Duration,
+    fixed_tick_count: u64,
+    accumulator: Duration,
+    max_delta: Duration,
+    max_fixed_ticks: u32,
+    in_fixed_tick: bool,
+    alpha: f32
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / 60.0))
+    }
+}
+
+impl Time {
+    pub fn new(fixed_delta: Duration) -> Self {
+        assert!(!fixed_delta.is_zero(), "Fixed timestep must be greater than zero");
+
+        Time {
+            startup: Instant::now(),
+            last_update: None,
+            raw_delta: Duration::ZERO,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            frame_count: 0,
+            time_scale: 1.0,
+            paused: false,
+            fixed_delta,
+            fixed_elapsed: Duration::ZERO,
+            fixed_tick_count: 0,
+            accumulator: Duration::ZERO,
+            max_delta: Duration::from_millis(250),
+            max_fixed_ticks: 8,
+            in_fixed_tick: false,
+            alpha: 0.0
+        }
+    }
+
+    // Inside a fixed tick delta and elapsed follow the fixed clock.
+    pub fn delta(&self) -> Duration {
+        if self.in_fixed_tick {
+            self.fixed_delta
+        } else {
+            self.delta
+        }
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta().as_secs_f32()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        if self.in_fixed_tick {
+            self.fixed_elapsed
+        } else {
+            self.elapsed
+        }
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed().as_secs_f32()
+    }
+
+    pub fn raw_delta(&self) -> Duration {
+        self.raw_delta
+    }
+
+    pub fn since_startup(&self) -> Duration {
+        self.last_update.unwrap_or(self.startup) - self.startup
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn fixed_delta(&self) -> Duration {
+        self.fixed_delta
+    }
+
+    pub fn fixed_tick_count(&self) -> u64 {
+        self.fixed_tick_count
+    }
+
+    pub fn is_fixed_tick(&self) -> bool {
+        self.in_fixed_tick
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn set_fixed_delta(&mut self, fixed_delta: Duration) {
+        assert!(!fixed_delta.is_zero(), "Fixed timestep must be greater than zero");
+        self.fixed_delta = fixed_delta;
+    }
+
+    pub fn set_fixed_rate(&mut self, hz: f64) {
+        self.set_fixed_delta(Duration::from_secs_f64(1.0 / hz));
+    }
+
+    pub fn set_max_delta(&mut self, max_delta: Duration) {
+        self.max_delta = max_delta;
+    }
+
+    pub fn set_max_fixed_ticks(&mut self, max_fixed_ticks: u32) {
+        self.max_fixed_ticks = max_fixed_ticks.max(1);
+    }
+
+    // Starts a frame and returns how many fixed ticks it has to run.
+    pub(crate) fn advance(&mut self, now: Instant) -> u32 {
+        let raw_delta = self.last_update.map(|last| now - last).unwrap_or(Duration::ZERO);
+        self.advance_by(now, raw_delta)
+    }
+
+    // Same as advance but with a given frame time, replays use it to reproduce the recorded frames.
+    pub(crate) fn advance_by(&mut self, now: Instant, raw_delta: Duration) -> u32 {
+        self.raw_delta = raw_delta;
+        self.last_update = Some(now);
+        self.frame_count += 1;
+
+        self.delta = if self.paused {
+            Duration::ZERO
+        } else {
+            self.raw_delta.min(self.max_delta).mul_f32(self.time_scale)
+        };
+        self.elapsed += self.delta;
+        self.accumulator += self.delta;
+
+        let fixed_nanos = self.fixed_delta.as_nanos();
+        let mut ticks = (self.accumulator.as_nanos() / fixed_nanos) as u32;
+
+        // Spiral of death: when the ticks cannot keep up, drop the backlog instead of growing it.
+        if ticks > self.max_fixed_ticks {
+            warn!("Fixed update is {} ticks behind, skipping {}", ticks, ticks - self.max_fixed_ticks);
+            ticks = self.max_fixed_ticks;
+        }
+        self.accumulator = Duration::from_nanos((self.accumulator.as_nanos() % fixed_nanos) as u64);
+        self.alpha = (self.accumulator.as_secs_f64() / self.fixed_delta.as_secs_f64()) as f32;
+
+        ticks
+    }
+
+    pub(crate) fn begin_fixed_tick(&mut self) {
+        self.in_fixed_tick = true;
+        self.fixed_elapsed += self.fixed_delta;
+        self.fixed_tick_count += 1;
+    }
+
+    pub(crate) fn end_fixed_tick(&mut self) {
+        self.in_fixed_tick = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Scaling goes through f32 seconds, so scaled durations are only close to exact.
+    fn assert_close(actual: Duration, expected: Duration) {
+        assert!((actual.as_secs_f64() - expected.as_secs_f64()).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+    }
+
+    fn started() -> (Time, Instant) {
+        let mut time = Time::new(ms(10));
+        let start = Instant::now();
+        assert_eq!(time.advance(start), 0);
+        (time, start)
+    }
+
+    #[test]
+    fn accumulates_fixed_ticks_and_keeps_the_remainder() {
+        let (mut time, start) = started();
+
+        assert_eq!(time.advance(start + ms(25)), 2);
+        assert_close(time.delta(), ms(25));
+        assert!((time.alpha() - 0.5).abs() < 1e-4);
+
+        // The 5ms left over count towards the next frame.
+        assert_eq!(time.advance(start + ms(30)), 1);
+        assert!(time.alpha().abs() < 1e-4);
+        assert_close(time.elapsed(), ms(30));
+        assert_eq!(time.frame_count(), 3);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let (mut time, start) = started();
+        time.set_max_delta(ms(50));
+        time.set_max_fixed_ticks(3);
+
+        assert_eq!(time.advance(start + ms(1000)), 3);
+        assert_eq!(time.raw_delta(), ms(1000));
+        assert_close(time.delta(), ms(50));
+        // The backlog beyond the tick limit is dropped rather than carried over.
+        assert!(time.alpha() < 1.0);
+        assert_eq!(time.advance(start + ms(1000)), 0);
+    }
+
+    #[test]
+    fn pause_and_time_scale() {
+        let (mut time, start) = started();
+
+        time.pause();
+        assert_eq!(time.advance(start + ms(40)), 0);
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.elapsed(), Duration::ZERO);
+
+        time.resume();
+        time.set_time_scale(0.5);
+        assert_eq!(time.advance(start + ms(80)), 2);
+        assert_close(time.delta(), ms(20));
+
+        time.set_time_scale(-1.0);
+        assert_eq!(time.time_scale(), 0.0);
+    }
+
+    #[test]
+    fn fixed_ticks_see_the_fixed_clock() {
+        let (mut time, start) = started();
+        let ticks = time.advance(start + ms(35));
+
+        for _ in 0..ticks {
+            time.begin_fixed_tick();
+            assert!(time.is_fixed_tick());
+            assert_close(time.delta(), ms(10));
+            time.end_fixed_tick();
+        }
+        assert_eq!(time.fixed_tick_count(), 3);
+        assert_close(time.delta(), ms(35));
+
+        time.begin_fixed_tick();
+        assert_close(time.elapsed(), ms(40));
+        time.end_fixed_tick();
+        assert_close(time.elapsed(), ms(35));
+    }
+
+    #[test]
+    fn advance_by_ignores_the_wall_clock() {
+        let (mut time, start) = started();
+        assert_eq!(time.advance_by(start, ms(20)), 2);
+        assert_eq!(time.raw_delta(), ms(20));
+        assert_eq!(time.advance_by(start + ms(500), ms(10)), 1);
+        assert_close(time.elapsed(), ms(30));
+    }
+
+    #[test]
+    #[should_panic(expected = "greater than zero")]
+    fn zero_fixed_delta_panics() {
+        Time::new(Duration::ZERO);
+    }
+}
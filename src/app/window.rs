@@ -0,0 +1,250 @@
+use tracing::warn;
+use winit::{
+    dpi::LogicalSize,
+    event_loop::ActiveEventLoop,
+    monitor::MonitorHandle,
+    window::{Fullscreen, Icon, Window, WindowAttributes, WindowId}
+};
+
+use super::config::{FullscreenMode, VsyncMode, WindowConfig};
+use crate::scene::{Camera, Viewport};
+
+pub enum WindowRequest {
+    SetTitle(String),
+    // Sizes are in logical pixels, scaled by the monitor's scale factor like Viewport::logical_size.
+    SetSize(u32, u32),
+    SetMinSize(Option<(u32, u32)>),
+    SetResizable(bool),
+    SetDecorations(bool),
+    SetFullscreen(FullscreenMode),
+    ToggleFullscreen,
+    SetVsync(VsyncMode),
+    SetMaxFrameLatency(u32),
+    SetCamera(Camera),
+    SetRenderScale(f32),
+    Redraw,
+    Open(WindowConfig),
+    Close
+}
+
+// Requests without a target window go to the primary window.
+#[derive(Default)]
+pub struct WindowControl {
+    requests: Vec<(Option<WindowId>, WindowRequest)>
+}
+
+impl WindowControl {
+    pub fn request(&mut self, request: WindowRequest) {
+        self.requests.push((None, request));
+    }
+
+    pub fn request_for(&mut self, window: WindowId, request: WindowRequest) {
+        self.requests.push((Some(window), request));
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.request(WindowRequest::SetTitle(title.to_string()));
+    }
+
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        self.request(WindowRequest::SetSize(width, height));
+    }
+
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) {
+        self.request(WindowRequest::SetFullscreen(mode));
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        self.request(WindowRequest::ToggleFullscreen);
+    }
+
+    pub fn set_vsync(&mut self, vsync: VsyncMode) {
+        self.request(WindowRequest::SetVsync(vsync));
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.request(WindowRequest::SetCamera(camera));
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.request(WindowRequest::SetRenderScale(render_scale));
+    }
+
+    pub fn request_redraw(&mut self) {
+        self.request(WindowRequest::Redraw);
+    }
+
+    pub fn open(&mut self, config: WindowConfig) {
+        self.request(WindowRequest::Open(config));
+    }
+
+    pub fn close(&mut self, window: WindowId) {
+        self.request_for(window, WindowRequest::Close);
+    }
+
+    pub fn drain(&mut self) -> Vec<(Option<WindowId>, WindowRequest)> {
+        std::mem::take(&mut self.requests)
+    }
+}
+
+struct WindowInfo {
+    id: WindowId,
+    title: String,
+    viewport: Viewport
+}
+
+#[derive(Default)]
+pub struct Windows {
+    primary: Option<WindowId>,
+    open: Vec<WindowInfo>
+}
+
+impl Windows {
+    pub fn primary(&self) -> Option<WindowId> {
+        self.primary
+    }
+
+    pub fn is_primary(&self, window: WindowId) -> bool {
+        self.primary == Some(window)
+    }
+
+    pub fn contains(&self, window: WindowId) -> bool {
+        self.open.iter().any(|info| info.id == window)
+    }
+
+    pub fn find(&self, title: &str) -> Option<WindowId> {
+        self.open.iter().find(|info| info.title == title).map(|info| info.id)
+    }
+
+    pub fn index_of(&self, window: WindowId) -> Option<usize> {
+        self.open.iter().position(|info| info.id == window)
+    }
+
+    pub fn nth(&self, index: usize) -> Option<WindowId> {
+        self.open.get(index).map(|info| info.id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.open.iter().map(|info| info.id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open.is_empty()
+    }
+
+    pub fn viewport(&self, window: WindowId) -> Option<Viewport> {
+        self.get(window).map(|info| info.viewport)
+    }
+
+    pub fn physical_size(&self, window: WindowId) -> Option<(u32, u32)> {
+        self.viewport(window).map(|viewport| viewport.physical_size)
+    }
+
+    pub fn logical_size(&self, window: WindowId) -> Option<(f32, f32)> {
+        self.viewport(window).map(|viewport| viewport.logical_size())
+    }
+
+    pub fn scale_factor(&self, window: WindowId) -> f64 {
+        self.viewport(window).map(|viewport| viewport.scale_factor).unwrap_or(1.0)
+    }
+
+    fn get(&self, window: WindowId) -> Option<&WindowInfo> {
+        self.open.iter().find(|info| info.id == window)
+    }
+
+    fn get_mut(&mut self, window: WindowId) -> Option<&mut WindowInfo> {
+        self.open.iter_mut().find(|info| info.id == window)
+    }
+
+    pub(crate) fn set_primary(&mut self, window: WindowId) {
+        self.primary = Some(window);
+    }
+
+    pub(crate) fn insert(&mut self, window: WindowId, title: &str, viewport: Viewport) {
+        self.open.push(WindowInfo {
+            id: window,
+            title: title.to_string(),
+            viewport
+        });
+    }
+
+    pub(crate) fn set_title(&mut self, window: WindowId, title: &str) {
+        if let Some(info) = self.get_mut(window) {
+            info.title = title.to_string();
+        }
+    }
+
+    pub(crate) fn set_physical_size(&mut self, window: WindowId, physical_size: (u32, u32)) {
+        if let Some(info) = self.get_mut(window) {
+            info.viewport.physical_size = physical_size;
+        }
+    }
+
+    pub(crate) fn set_scale_factor(&mut self, window: WindowId, scale_factor: f64) {
+        if let Some(info) = self.get_mut(window) {
+            info.viewport.scale_factor = scale_factor;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, window: WindowId) {
+        self.open.retain(|info| info.id != window);
+        if self.primary == Some(window) {
+            self.primary = None;
+        }
+    }
+}
+
+pub fn load_icon(filename: &str) -> Option<Icon> {
+    let bytes = match std::fs::read(format!("../img/{}", filename)) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read window icon {}: {}", filename, e);
+            return None;
+        }
+    };
+
+    let image = image::load_from_memory(&bytes).ok()?.to_rgba8();
+    let (width, height) = image.dimensions();
+    Icon::from_rgba(image.into_raw(), width, height).ok()
+}
+
+pub fn fullscreen(mode: FullscreenMode, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+    match mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        FullscreenMode::Exclusive => {
+            let video_mode = monitor?.video_modes().max_by_key(|mode| {
+                (mode.size().width * mode.size().height, mode.refresh_rate_millihertz())
+            });
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    warn!("No exclusive video mode available, falling back to borderless fullscreen");
+                    Some(Fullscreen::Borderless(None))
+                }
+            }
+        }
+    }
+}
+
+pub fn window_attributes(config: &WindowConfig, event_loop: &ActiveEventLoop) -> WindowAttributes {
+    let mut attributes = Window::default_attributes()
+        .with_title(config.title.as_str())
+        .with_inner_size(LogicalSize::new(config.width, config.height))
+        .with_resizable(config.resizable)
+        .with_decorations(config.decorations)
+        .with_fullscreen(fullscreen(config.fullscreen, event_loop.primary_monitor()));
+
+    if let Some((width, height)) = config.min_size {
+        attributes = attributes.with_min_inner_size(LogicalSize::new(width, height));
+    }
+    if let Some(icon) = &config.icon {
+        attributes = attributes.with_window_icon(load_icon(icon));
+    }
+
+    attributes
+}
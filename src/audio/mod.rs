@@ -0,0 +1,5 @@
+pub mod sound;
+pub mod player;
+
+pub use sound::Sound;
+pub use player::Audio;
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::sync::mpsc;
+use std::thread;
+use tracing::{error, warn};
+
+use super::sound::Sound;
+
+// The output stream can't leave the thread that opened it, so that thread keeps it
+// alive until the resource is dropped and only the mixer is shared.
+struct Output {
+    mixer: rodio::mixer::Mixer,
+    _keep_alive: mpsc::Sender<()>
+}
+
+impl Output {
+    fn open() -> Result<Self> {
+        let (mixer_sender, mixer_receiver) = mpsc::channel();
+        let (keep_alive, dropped) = mpsc::channel::<()>();
+
+        thread::Builder::new()
+            .name("fuji-audio".to_string())
+            .spawn(move || {
+                let mut stream = match rodio::OutputStreamBuilder::open_default_stream() {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = mixer_sender.send(Err(e));
+                        return;
+                    }
+                };
+                stream.log_on_drop(false);
+                let _ = mixer_sender.send(Ok(stream.mixer().clone()));
+                let _ = dropped.recv();
+            })?;
+
+        let mixer = mixer_receiver.recv()??;
+        Ok(Output {
+            mixer,
+            _keep_alive: keep_alive
+        })
+    }
+}
+
+// World resource installed by the AudioPlugin. Without an output device every call is a no-op.
+pub struct Audio {
+    output: Option<Output>,
+    sinks: Vec<rodio::Sink>,
+    volume: f32
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        let output = match Output::open() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                warn!("No audio output, sounds will not play: {:#}", e);
+                None
+            }
+        };
+
+        Audio {
+            output,
+            sinks: Vec::new(),
+            volume: 1.0
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.output.is_some()
+    }
+
+    pub fn play(&mut self, sound: &Sound) {
+        self.play_source(sound, false);
+    }
+
+    // Keeps repeating until stop_all is called.
+    pub fn play_looped(&mut self, sound: &Sound) {
+        self.play_source(sound, true);
+    }
+
+    fn play_source(&mut self, sound: &Sound, looped: bool) {
+        let Some(output) = self.output.as_ref() else {
+            return;
+        };
+
+        self.sinks.retain(|sink| !sink.empty());
+        let sink = rodio::Sink::connect_new(&output.mixer);
+        sink.set_volume(self.volume);
+        let appended = if looped {
+            sound.looped_decoder().map(|decoder| sink.append(decoder))
+        } else {
+            sound.decoder().map(|decoder| sink.append(decoder))
+        };
+
+        match appended {
+            Ok(()) => self.sinks.push(sink),
+            Err(e) => error!("Cannot play sound: {:#}", e)
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        for sink in self.sinks.drain(..) {
+            sink.stop();
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    // Applies to the sounds already playing as well.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0);
+        for sink in self.sinks.iter() {
+            sink.set_volume(self.volume);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+// An encoded sound file kept in memory, decoded again every time it plays.
+#[derive(Clone)]
+pub struct Sound {
+    bytes: Arc<[u8]>
+}
+
+impl Sound {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).with_context(|| format!("Failed to read sound {}", path.display()))?;
+        Self::from_bytes(bytes).with_context(|| format!("Failed to decode sound {}", path.display()))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let sound = Sound {
+            bytes: bytes.into()
+        };
+        // Fails here rather than every time the sound is played.
+        sound.decoder()?;
+        Ok(sound)
+    }
+
+    pub(crate) fn decoder(&self) -> Result<rodio::Decoder<Cursor<Arc<[u8]>>>> {
+        Ok(rodio::Decoder::new(Cursor::new(self.bytes.clone()))?)
+    }
+
+    pub(crate) fn looped_decoder(&self) -> Result<rodio::decoder::LoopedDecoder<Cursor<Arc<[u8]>>>> {
+        Ok(rodio::Decoder::new_looped(Cursor::new(self.bytes.clone()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A mono 16 bit WAV file holding the given samples.
+    fn wav(samples: &[i16]) -> Vec<u8> {
+        let data_size = (samples.len() * 2) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&8000u32.to_le_bytes());
+        bytes.extend_from_slice(&16000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn decodes_every_time_it_plays() {
+        let sound = Sound::from_bytes(wav(&[0, 1000, -1000, 0])).unwrap();
+        assert_eq!(sound.decoder().unwrap().count(), 4);
+        assert_eq!(sound.decoder().unwrap().count(), 4);
+        assert_eq!(sound.looped_decoder().unwrap().take(10).count(), 10);
+    }
+
+    #[test]
+    fn rejects_data_that_is_not_a_sound() {
+        assert!(Sound::from_bytes(b"not a sound file".to_vec()).is_err());
+        let error = Sound::from_file("missing.wav").err().unwrap();
+        assert!(error.to_string().contains("missing.wav"));
+    }
+}
@@ -0,0 +1,110 @@
+use std::any::{TypeId, type_name};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Component,
+    Resource
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    kind: Kind,
+    id: TypeId,
+    name: &'static str
+}
+
+impl Slot {
+    fn of<T: 'static>(kind: Kind) -> Self {
+        Slot {
+            kind,
+            id: TypeId::of::<T>(),
+            name: type_name::<T>()
+        }
+    }
+
+    fn same(&self, other: &Slot) -> bool {
+        self.kind == other.kind && self.id == other.id
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: Vec<Slot>,
+    writes: Vec<Slot>
+}
+
+impl Access {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(list: &mut Vec<Slot>, slot: Slot) {
+        if !list.iter().any(|s| s.same(&slot)) {
+            list.push(slot);
+        }
+    }
+
+    pub fn add_read<T: 'static>(&mut self) {
+        Self::push(&mut self.reads, Slot::of::<T>(Kind::Component));
+    }
+
+    pub fn add_write<T: 'static>(&mut self) {
+        Self::push(&mut self.writes, Slot::of::<T>(Kind::Component));
+    }
+
+    pub fn add_resource_read<R: 'static>(&mut self) {
+        Self::push(&mut self.reads, Slot::of::<R>(Kind::Resource));
+    }
+
+    pub fn add_resource_write<R: 'static>(&mut self) {
+        Self::push(&mut self.writes, Slot::of::<R>(Kind::Resource));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty() && self.writes.is_empty()
+    }
+
+    pub fn touches_component<T: 'static>(&self) -> bool {
+        let slot = Slot::of::<T>(Kind::Component);
+        self.reads.iter().chain(self.writes.iter()).any(|s| s.same(&slot))
+    }
+
+    pub fn extend(&mut self, other: &Access) {
+        for slot in other.reads.iter() {
+            Self::push(&mut self.reads, *slot);
+        }
+        for slot in other.writes.iter() {
+            Self::push(&mut self.writes, *slot);
+        }
+    }
+
+    // Names of everything one side writes while the other side touches it.
+    pub fn conflicts(&self, other: &Access) -> Vec<&'static str> {
+        let mut names = Vec::new();
+
+        for write in self.writes.iter() {
+            if other.reads.iter().chain(other.writes.iter()).any(|s| s.same(write)) {
+                names.push(write.name);
+            }
+        }
+        for write in other.writes.iter() {
+            if self.reads.iter().any(|s| s.same(write)) && !names.contains(&write.name) {
+                names.push(write.name);
+            }
+        }
+
+        names
+    }
+
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        self.conflicts(other).is_empty()
+    }
+
+    pub fn read_names(&self) -> Vec<&'static str> {
+        self.reads.iter().map(|slot| slot.name).collect()
+    }
+
+    pub fn write_names(&self) -> Vec<&'static str> {
+        self.writes.iter().map(|slot| slot.name).collect()
+    }
+}
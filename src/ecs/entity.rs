@@ -0,0 +1,97 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32
+}
+
+impl Entity {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Default)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    count: usize
+}
+
+impl Entities {
+    pub fn allocate(&mut self) -> Entity {
+        self.count += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity { index, generation: self.generations[index as usize] }
+            },
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity { index: (self.generations.len() - 1) as u32, generation: 0 }
+            }
+        }
+    }
+
+    pub fn free(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] += 1;
+        self.free.push(entity.index);
+        self.count -= 1;
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        index < self.alive.len() && self.alive[index] && self.generations[index] == entity.generation
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(|(index, _)| Entity { index: index as u32, generation: self.generations[index] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_indices_are_reused_with_a_new_generation() {
+        let mut entities = Entities::default();
+        let a = entities.allocate();
+        let b = entities.allocate();
+        assert_eq!(entities.len(), 2);
+
+        assert!(entities.free(a));
+        assert!(!entities.free(a));
+        assert!(!entities.is_alive(a));
+        assert_eq!(entities.len(), 1);
+
+        let c = entities.allocate();
+        assert_eq!(c.index(), a.index());
+        assert_eq!(c.generation(), a.generation() + 1);
+        assert!(entities.is_alive(c));
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![c, b]);
+    }
+}
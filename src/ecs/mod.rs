@@ -0,0 +1,16 @@
+pub mod entity;
+pub mod storage;
+pub mod access;
+pub mod world;
+pub mod query;
+pub mod system;
+pub mod schedule;
+pub mod task_pool;
+
+pub use entity::Entity;
+pub use access::Access;
+pub use world::{World, Component, Resource, Bundle, Commands, Ref, RefMut, Res, ResMut};
+pub use query::{Query, QueryParam, QueryFilter, With, Without};
+pub use system::{System, FunctionSystem, system};
+pub use schedule::Schedule;
+pub use task_pool::TaskPool;
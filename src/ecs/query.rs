@@ -0,0 +1,331 @@
+use std::any::type_name;
+use std::marker::PhantomData;
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use super::access::Access;
+use super::entity::Entity;
+use super::storage::SparseSet;
+use super::world::{World, Component};
+
+pub trait QueryParam {
+    type Guard<'w>;
+    type Item<'g>;
+
+    fn lock(world: &World) -> Self::Guard<'_>;
+    fn contains(guard: &Self::Guard<'_>, entity: Entity) -> bool;
+    fn fetch<'g>(guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>>;
+    fn access(access: &mut Access);
+}
+
+impl QueryParam for Entity {
+    type Guard<'w> = ();
+    type Item<'g> = Entity;
+
+    fn lock(_world: &World) -> Self::Guard<'_> {}
+
+    fn contains(_guard: &Self::Guard<'_>, _entity: Entity) -> bool {
+        true
+    }
+
+    fn fetch<'g>(_guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>> {
+        Some(entity)
+    }
+
+    fn access(_access: &mut Access) {}
+}
+
+impl<T: Component> QueryParam for &T {
+    type Guard<'w> = Option<RwLockReadGuard<'w, SparseSet<T>>>;
+    type Item<'g> = &'g T;
+
+    fn lock(world: &World) -> Self::Guard<'_> {
+        world.storage::<T>().map(|storage| storage.read().unwrap())
+    }
+
+    fn contains(guard: &Self::Guard<'_>, entity: Entity) -> bool {
+        guard.as_ref().map(|storage| storage.contains(entity)).unwrap_or(false)
+    }
+
+    fn fetch<'g>(guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>> {
+        guard.as_ref()?.get(entity)
+    }
+
+    fn access(access: &mut Access) {
+        assert!(!access.touches_component::<T>(), "Query accesses {} more than once", type_name::<T>());
+        access.add_read::<T>();
+    }
+}
+
+impl<T: Component> QueryParam for &mut T {
+    type Guard<'w> = Option<RwLockWriteGuard<'w, SparseSet<T>>>;
+    type Item<'g> = &'g mut T;
+
+    fn lock(world: &World) -> Self::Guard<'_> {
+        world.storage::<T>().map(|storage| storage.write().unwrap())
+    }
+
+    fn contains(guard: &Self::Guard<'_>, entity: Entity) -> bool {
+        guard.as_ref().map(|storage| storage.contains(entity)).unwrap_or(false)
+    }
+
+    fn fetch<'g>(guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>> {
+        guard.as_mut()?.get_mut(entity)
+    }
+
+    fn access(access: &mut Access) {
+        assert!(!access.touches_component::<T>(), "Query accesses {} more than once", type_name::<T>());
+        access.add_write::<T>();
+    }
+}
+
+impl<T: Component> QueryParam for Option<&T> {
+    type Guard<'w> = Option<RwLockReadGuard<'w, SparseSet<T>>>;
+    type Item<'g> = Option<&'g T>;
+
+    fn lock(world: &World) -> Self::Guard<'_> {
+        world.storage::<T>().map(|storage| storage.read().unwrap())
+    }
+
+    fn contains(_guard: &Self::Guard<'_>, _entity: Entity) -> bool {
+        true
+    }
+
+    fn fetch<'g>(guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>> {
+        Some(guard.as_ref().and_then(|storage| storage.get(entity)))
+    }
+
+    fn access(access: &mut Access) {
+        assert!(!access.touches_component::<T>(), "Query accesses {} more than once", type_name::<T>());
+        access.add_read::<T>();
+    }
+}
+
+macro_rules! impl_query_param {
+    ($($name: ident), +) => {
+        impl<$($name: QueryParam), +> QueryParam for ($($name,)+) {
+            type Guard<'w> = ($($name::Guard<'w>,)+);
+            type Item<'g> = ($($name::Item<'g>,)+);
+
+            fn lock(world: &World) -> Self::Guard<'_> {
+                ($($name::lock(world),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn contains(guard: &Self::Guard<'_>, entity: Entity) -> bool {
+                let ($($name,)+) = guard;
+                $($name::contains($name, entity))&&+
+            }
+
+            #[allow(non_snake_case)]
+            fn fetch<'g>(guard: &'g mut Self::Guard<'_>, entity: Entity) -> Option<Self::Item<'g>> {
+                let ($($name,)+) = guard;
+                Some(($($name::fetch($name, entity)?,)+))
+            }
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+        }
+    }
+}
+
+impl_query_param!(A);
+impl_query_param!(A, B);
+impl_query_param!(A, B, C);
+impl_query_param!(A, B, C, D);
+impl_query_param!(A, B, C, D, E);
+impl_query_param!(A, B, C, D, E, F);
+impl_query_param!(A, B, C, D, E, F, G);
+impl_query_param!(A, B, C, D, E, F, G, H);
+
+pub trait QueryFilter {
+    fn matches(world: &World, entity: Entity) -> bool;
+    fn access(access: &mut Access);
+}
+
+impl QueryFilter for () {
+    fn matches(_world: &World, _entity: Entity) -> bool {
+        true
+    }
+
+    fn access(_access: &mut Access) {}
+}
+
+pub struct With<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for With<T> {
+    fn matches(world: &World, entity: Entity) -> bool {
+        world.has::<T>(entity)
+    }
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+}
+
+pub struct Without<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Without<T> {
+    fn matches(world: &World, entity: Entity) -> bool {
+        !world.has::<T>(entity)
+    }
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+}
+
+macro_rules! impl_query_filter {
+    ($($name: ident), +) => {
+        impl<$($name: QueryFilter), +> QueryFilter for ($($name,)+) {
+            fn matches(world: &World, entity: Entity) -> bool {
+                $($name::matches(world, entity))&&+
+            }
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+        }
+    }
+}
+
+impl_query_filter!(A);
+impl_query_filter!(A, B);
+impl_query_filter!(A, B, C);
+impl_query_filter!(A, B, C, D);
+
+pub struct Query<'w, Q: QueryParam, F: QueryFilter = ()> {
+    guard: Q::Guard<'w>,
+    entities: Vec<Entity>,
+    filter: PhantomData<F>
+}
+
+impl<'w, Q: QueryParam, F: QueryFilter> Query<'w, Q, F> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        // Panics early when the same component is requested twice instead of deadlocking.
+        Q::access(&mut Access::new());
+
+        // Filters take their own short-lived locks, so they run before the query locks anything.
+        let mut entities: Vec<Entity> = world.entities().iter()
+            .filter(|entity| F::matches(world, *entity))
+            .collect();
+
+        let guard = Q::lock(world);
+        entities.retain(|entity| Q::contains(&guard, *entity));
+
+        Query {
+            guard,
+            entities,
+            filter: PhantomData
+        }
+    }
+
+    pub fn access() -> Access {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+        access
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if !self.entities.contains(&entity) {
+            return None;
+        }
+        Q::fetch(&mut self.guard, entity)
+    }
+
+    pub fn single(&mut self) -> Option<Q::Item<'_>> {
+        let entity = *self.entities.first()?;
+        Q::fetch(&mut self.guard, entity)
+    }
+
+    pub fn for_each<C: FnMut(Entity, Q::Item<'_>)>(&mut self, mut visit: C) {
+        for i in 0..self.entities.len() {
+            let entity = self.entities[i];
+            if let Some(item) = Q::fetch(&mut self.guard, entity) {
+                visit(entity, item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position(i32);
+    struct Velocity(i32);
+    struct Frozen;
+
+    fn world() -> (World, [Entity; 3]) {
+        let mut world = World::new();
+        let moving = world.spawn_with((Position(0), Velocity(2)));
+        let frozen = world.spawn_with((Position(10), Velocity(5), Frozen));
+        let still = world.spawn_with((Position(20),));
+        (world, [moving, frozen, still])
+    }
+
+    #[test]
+    fn matches_entities_with_every_component() {
+        let (world, [moving, frozen, still]) = world();
+
+        let mut query = world.query::<(&mut Position, &Velocity)>();
+        assert_eq!(query.entities(), &[moving, frozen]);
+        query.for_each(|_, (position, velocity)| position.0 += velocity.0);
+        assert!(query.get(still).is_none());
+        drop(query);
+
+        assert_eq!(world.get::<Position>(moving).unwrap().0, 2);
+        assert_eq!(world.get::<Position>(frozen).unwrap().0, 15);
+        assert_eq!(world.get::<Position>(still).unwrap().0, 20);
+    }
+
+    #[test]
+    fn optional_components_and_filters() {
+        let (world, [moving, frozen, still]) = world();
+
+        let mut query = world.query::<(Entity, Option<&Velocity>)>();
+        assert_eq!(query.len(), 3);
+        assert_eq!(query.get(still).map(|(_, velocity)| velocity.is_none()), Some(true));
+        drop(query);
+
+        let query = world.query_filtered::<&Position, Without<Frozen>>();
+        assert_eq!(query.entities(), &[moving, still]);
+        drop(query);
+
+        let mut query = world.query_filtered::<Entity, (With<Velocity>, With<Frozen>)>();
+        assert_eq!(query.single(), Some(frozen));
+    }
+
+    #[test]
+    fn unknown_component_matches_nothing() {
+        let world = World::new();
+        assert!(world.query::<&Position>().is_empty());
+    }
+
+    #[test]
+    fn access_lists_reads_and_writes() {
+        let access = Query::<(&mut Position, Option<&Velocity>), Without<Frozen>>::access();
+        assert_eq!(access.write_names(), vec![type_name::<Position>()]);
+        assert_eq!(access.read_names(), vec![type_name::<Velocity>(), type_name::<Frozen>()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than once")]
+    fn same_component_twice_panics() {
+        let (world, _) = world();
+        world.query::<(&Position, &mut Position)>();
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use tracing::warn;
+
+use super::system::System;
+use super::task_pool::TaskPool;
+use super::world::World;
+
+pub const FIRST: &str = "first";
+pub const PRE_UPDATE: &str = "pre_update";
+pub const UPDATE: &str = "update";
+pub const POST_UPDATE: &str = "post_update";
+pub const LAST: &str = "last";
+
+struct Stage {
+    name: String,
+    systems: Vec<Box<dyn System>>,
+    batches: Vec<Vec<usize>>,
+    dirty: bool
+}
+
+impl Stage {
+    fn new(name: &str) -> Self {
+        Stage {
+            name: name.to_string(),
+            systems: Vec::new(),
+            batches: Vec::new(),
+            dirty: false
+        }
+    }
+
+    fn prepare(&mut self) {
+        if self.dirty {
+            self.batches = build_batches(&self.name, &self.systems);
+            self.dirty = false;
+        }
+    }
+}
+
+pub struct Schedule {
+    stages: Vec<Stage>,
+    task_pool: Option<TaskPool>,
+    parallel: bool
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            stages: [FIRST, PRE_UPDATE, UPDATE, POST_UPDATE, LAST].iter().map(|name| Stage::new(name)).collect(),
+            task_pool: None,
+            parallel: true
+        }
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn stage_index(&self, name: &str) -> usize {
+        self.stages.iter()
+            .position(|stage| stage.name == name)
+            .unwrap_or_else(|| panic!("Stage {} does not exist", name))
+    }
+
+    pub fn has_stage(&self, name: &str) -> bool {
+        self.stages.iter().any(|stage| stage.name == name)
+    }
+
+    pub fn add_stage(&mut self, name: &str) {
+        assert!(!self.has_stage(name), "Stage {} already exists", name);
+        self.stages.push(Stage::new(name));
+    }
+
+    pub fn add_stage_before(&mut self, target: &str, name: &str) {
+        assert!(!self.has_stage(name), "Stage {} already exists", name);
+        let index = self.stage_index(target);
+        self.stages.insert(index, Stage::new(name));
+    }
+
+    pub fn add_stage_after(&mut self, target: &str, name: &str) {
+        assert!(!self.has_stage(name), "Stage {} already exists", name);
+        let index = self.stage_index(target);
+        self.stages.insert(index + 1, Stage::new(name));
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, system: S) {
+        self.add_system_to_stage(UPDATE, system);
+    }
+
+    pub fn add_system_to_stage<S: System + 'static>(&mut self, stage: &str, system: S) {
+        let index = self.stage_index(stage);
+        let stage = &mut self.stages[index];
+        stage.systems.push(Box::new(system));
+        stage.dirty = true;
+    }
+
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.task_pool = Some(TaskPool::new(threads));
+    }
+
+    pub fn system_names(&self) -> Vec<&str> {
+        self.stages.iter()
+            .flat_map(|stage| stage.systems.iter().map(|system| system.name()))
+            .collect()
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        if self.parallel && self.task_pool.is_none() {
+            self.task_pool = Some(TaskPool::default());
+        }
+
+        for stage in self.stages.iter_mut() {
+            stage.prepare();
+
+            for batch in stage.batches.iter() {
+                let pool = self.task_pool.as_ref().filter(|_| self.parallel && batch.len() > 1);
+                let shared: &World = world;
+
+                match pool {
+                    Some(pool) => {
+                        let jobs: Vec<Box<dyn FnOnce() + Send + '_>> = stage.systems.iter_mut()
+                            .enumerate()
+                            .filter(|(i, _)| batch.contains(i))
+                            .map(|(_, system)| Box::new(move || system.run(shared)) as Box<dyn FnOnce() + Send + '_>)
+                            .collect();
+                        pool.scope(jobs);
+                    },
+                    None => {
+                        for i in batch.iter() {
+                            stage.systems[*i].run(shared);
+                        }
+                    }
+                }
+            }
+
+            world.apply_commands();
+        }
+    }
+
+    pub fn dump(&mut self) -> String {
+        let mut out = String::new();
+
+        for stage in self.stages.iter_mut() {
+            stage.prepare();
+            let _ = writeln!(out, "Stage \"{}\" ({} systems, {} batches)", stage.name, stage.systems.len(), stage.batches.len());
+
+            for (i, batch) in stage.batches.iter().enumerate() {
+                let _ = writeln!(out, "  Batch {}", i);
+                for index in batch.iter() {
+                    let system = &stage.systems[*index];
+                    let access = system.access();
+                    let _ = writeln!(out, "    {} reads: [{}] writes: [{}]",
+                        system.name(),
+                        access.read_names().join(", "),
+                        access.write_names().join(", "));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn build_batches(stage: &str, systems: &[Box<dyn System>]) -> Vec<Vec<usize>> {
+    let count = systems.len();
+    let mut edges: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); count];
+
+    let find = |name: &str| -> Vec<usize> {
+        let found: Vec<usize> = systems.iter()
+            .enumerate()
+            .filter(|(_, system)| system.name() == name)
+            .map(|(i, _)| i)
+            .collect();
+        if found.is_empty() {
+            warn!("Ordering constraint in stage {} refers to unknown system {}", stage, name);
+        }
+        found
+    };
+
+    for (i, system) in systems.iter().enumerate() {
+        for name in system.before() {
+            for j in find(name) {
+                edges[i].insert(j);
+            }
+        }
+        for name in system.after() {
+            for j in find(name) {
+                edges[j].insert(i);
+            }
+        }
+    }
+
+    // Explicit constraints first, ties broken by insertion order so the result is deterministic.
+    let order = topological_order(stage, systems, &edges);
+    let mut position = vec![0; count];
+    for (p, i) in order.iter().enumerate() {
+        position[*i] = p;
+    }
+
+    // Conflicting systems never share a batch; they keep the explicit order, or insertion order otherwise.
+    for i in 0..count {
+        for j in (i + 1)..count {
+            let a = systems[i].access();
+            let b = systems[j].access();
+            if a.is_empty() || b.is_empty() || !a.is_compatible(b) {
+                if position[i] < position[j] {
+                    edges[i].insert(j);
+                } else {
+                    edges[j].insert(i);
+                }
+            }
+        }
+    }
+
+    let mut level = vec![0usize; count];
+    for i in order.iter() {
+        for j in edges[*i].iter() {
+            level[*j] = level[*j].max(level[*i] + 1);
+        }
+    }
+
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    for i in order {
+        if batches.len() <= level[i] {
+            batches.resize(level[i] + 1, Vec::new());
+        }
+        batches[level[i]].push(i);
+    }
+    batches
+}
+
+fn topological_order(stage: &str, systems: &[Box<dyn System>], edges: &[BTreeSet<usize>]) -> Vec<usize> {
+    let count = systems.len();
+    let mut incoming = vec![0usize; count];
+    for targets in edges.iter() {
+        for j in targets.iter() {
+            incoming[*j] += 1;
+        }
+    }
+
+    let mut ready: BTreeSet<usize> = (0..count).filter(|i| incoming[*i] == 0).collect();
+    let mut order = Vec::with_capacity(count);
+
+    while let Some(i) = ready.pop_first() {
+        order.push(i);
+        for j in edges[i].iter() {
+            incoming[*j] -= 1;
+            if incoming[*j] == 0 {
+                ready.insert(*j);
+            }
+        }
+    }
+
+    if order.len() != count {
+        let cycle: Vec<&str> = (0..count)
+            .filter(|i| incoming[*i] > 0)
+            .map(|i| systems[i].name())
+            .collect();
+        panic!("Ordering constraints in stage {} form a cycle between: {}", stage, cycle.join(", "));
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::system;
+    use std::sync::{Arc, Mutex};
+
+    struct Position;
+    struct Velocity;
+    struct Log(Vec<&'static str>);
+
+    fn logging(name: &'static str) -> crate::ecs::FunctionSystem<impl FnMut(&World) + Send + Sync + 'static> {
+        system(name, move |world: &World| world.resource_mut::<Log>().0.push(name)).writes_resource::<Log>()
+    }
+
+    fn batch_names(schedule: &mut Schedule, stage: &str) -> Vec<Vec<String>> {
+        let index = schedule.stage_index(stage);
+        let stage = &mut schedule.stages[index];
+        stage.prepare();
+        stage.batches.iter()
+            .map(|batch| batch.iter().map(|i| stage.systems[*i].name().to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn compatible_systems_share_a_batch() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("read_a", |_| {}).reads::<Position>());
+        schedule.add_system(system("read_b", |_| {}).reads::<Position>().writes::<Velocity>());
+        schedule.add_system(system("write", |_| {}).writes::<Position>());
+        schedule.add_system(system("read_c", |_| {}).reads::<Velocity>());
+
+        assert_eq!(batch_names(&mut schedule, UPDATE), vec![
+            vec!["read_a".to_string(), "read_b".to_string()],
+            vec!["write".to_string(), "read_c".to_string()]
+        ]);
+    }
+
+    #[test]
+    fn systems_without_access_run_alone() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("a", |_| {}).reads::<Position>());
+        schedule.add_system(system("exclusive", |_| {}));
+        schedule.add_system(system("b", |_| {}).reads::<Position>());
+
+        assert_eq!(batch_names(&mut schedule, UPDATE).len(), 3);
+    }
+
+    #[test]
+    fn explicit_ordering_wins_over_insertion_order() {
+        let mut world = World::new();
+        world.insert_resource(Log(Vec::new()));
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(logging("third").after("second"));
+        schedule.add_system(logging("second"));
+        schedule.add_system(logging("first").before("second"));
+        schedule.add_system_to_stage(FIRST, logging("stage"));
+        schedule.run(&mut world);
+
+        assert_eq!(world.resource::<Log>().0, vec!["stage", "first", "second", "third"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "form a cycle")]
+    fn ordering_cycle_panics() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(system("a", |_| {}).before("b"));
+        schedule.add_system(system("b", |_| {}).before("a"));
+        schedule.dump();
+    }
+
+    #[test]
+    fn parallel_run_reaches_every_system_and_applies_commands() {
+        let mut world = World::new();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        let mut schedule = Schedule::new();
+        schedule.set_threads(4);
+        for name in ["a", "b", "c", "d"] {
+            let ran = Arc::clone(&ran);
+            schedule.add_system(system(name, move |world: &World| {
+                ran.lock().unwrap().push(name);
+                world.commands().spawn((Position,));
+            }).reads::<Velocity>());
+        }
+        schedule.add_system_to_stage(POST_UPDATE, system("count", |world: &World| {
+            world.commands().insert_resource(Log(vec!["counted"; world.query::<&Position>().len()]));
+        }).reads::<Position>());
+        schedule.run(&mut world);
+
+        let mut ran = ran.lock().unwrap().clone();
+        ran.sort();
+        assert_eq!(ran, vec!["a", "b", "c", "d"]);
+        assert_eq!(world.resource::<Log>().0.len(), 4);
+    }
+}
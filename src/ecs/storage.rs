@@ -0,0 +1,175 @@
+use std::any::Any;
+use std::sync::RwLock;
+use super::entity::Entity;
+
+pub struct SparseSet<T> {
+    sparse: Vec<Option<usize>>,
+    dense: Vec<T>,
+    entities: Vec<Entity>
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        SparseSet {
+            sparse: Vec::new(),
+            dense: Vec::new(),
+            entities: Vec::new()
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let index = (*self.sparse.get(entity.index())?)?;
+        if self.entities[index] == entity {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+        if let Some(index) = self.dense_index(entity) {
+            return Some(std::mem::replace(&mut self.dense[index], value));
+        }
+
+        // A stale slot from a previous generation is dropped here as well.
+        if let Some(index) = self.sparse.get(entity.index()).copied().flatten() {
+            self.swap_remove(index);
+        }
+
+        if self.sparse.len() <= entity.index() {
+            self.sparse.resize(entity.index() + 1, None);
+        }
+        self.sparse[entity.index()] = Some(self.dense.len());
+        self.dense.push(value);
+        self.entities.push(entity);
+        None
+    }
+
+    fn swap_remove(&mut self, index: usize) -> T {
+        let entity = self.entities[index];
+        self.sparse[entity.index()] = None;
+
+        let value = self.dense.swap_remove(index);
+        self.entities.swap_remove(index);
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index()] = Some(index);
+        }
+        value
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let index = self.dense_index(entity)?;
+        Some(self.swap_remove(index))
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.dense_index(entity).map(|index| &self.dense[index])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.dense_index(entity).map(|index| &mut self.dense[index])
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(self.dense.iter())
+    }
+}
+
+pub trait AnyStorage: Send + Sync {
+    fn remove_entity(&self, entity: Entity);
+    fn contains(&self, entity: Entity) -> bool;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Send + Sync + 'static> AnyStorage for RwLock<SparseSet<T>> {
+    fn remove_entity(&self, entity: Entity) {
+        self.write().unwrap().remove(entity);
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.read().unwrap().contains(entity)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::entity::Entities;
+
+    #[test]
+    fn insert_get_and_replace() {
+        let mut entities = Entities::default();
+        let a = entities.allocate();
+        let b = entities.allocate();
+
+        let mut set = SparseSet::default();
+        assert_eq!(set.insert(a, 1), None);
+        assert_eq!(set.insert(b, 2), None);
+        assert_eq!(set.insert(a, 3), Some(1));
+
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.get(a), Some(&3));
+        assert_eq!(set.get(b), Some(&2));
+    }
+
+    #[test]
+    fn remove_keeps_the_moved_entity_reachable() {
+        let mut entities = Entities::default();
+        let a = entities.allocate();
+        let b = entities.allocate();
+        let c = entities.allocate();
+
+        let mut set = SparseSet::default();
+        set.insert(a, 'a');
+        set.insert(b, 'b');
+        set.insert(c, 'c');
+
+        assert_eq!(set.remove(a), Some('a'));
+        assert_eq!(set.remove(a), None);
+        assert!(!set.contains(a));
+        assert_eq!(set.get(c), Some(&'c'));
+        assert_eq!(set.get(b), Some(&'b'));
+        assert_eq!(set.entities().len(), 2);
+    }
+
+    #[test]
+    fn stale_generation_is_not_found() {
+        let mut entities = Entities::default();
+        let old = entities.allocate();
+        let mut set = SparseSet::default();
+        set.insert(old, 1);
+
+        entities.free(old);
+        let new = entities.allocate();
+        assert_eq!(new.index(), old.index());
+        assert!(!set.contains(new));
+
+        // Inserting for the new generation drops the value left over from the old one.
+        set.insert(new, 2);
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.get(old), None);
+        assert_eq!(set.get_mut(new), Some(&mut 2));
+    }
+}
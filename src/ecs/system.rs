@@ -0,0 +1,94 @@
+use super::access::Access;
+use super::query::{Query, QueryParam, QueryFilter};
+use super::world::{World, Component, Resource};
+
+pub trait System: Send + Sync {
+    fn name(&self) -> &str;
+    fn access(&self) -> &Access;
+    fn run(&mut self, world: &World);
+
+    fn before(&self) -> &[String] {
+        &[]
+    }
+
+    fn after(&self) -> &[String] {
+        &[]
+    }
+}
+
+pub struct FunctionSystem<F> {
+    name: String,
+    access: Access,
+    before: Vec<String>,
+    after: Vec<String>,
+    func: F
+}
+
+pub fn system<F: FnMut(&World) + Send + Sync + 'static>(name: &str, func: F) -> FunctionSystem<F> {
+    FunctionSystem {
+        name: name.to_string(),
+        access: Access::new(),
+        before: Vec::new(),
+        after: Vec::new(),
+        func
+    }
+}
+
+impl<F> FunctionSystem<F> {
+    pub fn reads<T: Component>(mut self) -> Self {
+        self.access.add_read::<T>();
+        self
+    }
+
+    pub fn writes<T: Component>(mut self) -> Self {
+        self.access.add_write::<T>();
+        self
+    }
+
+    pub fn reads_resource<R: Resource>(mut self) -> Self {
+        self.access.add_resource_read::<R>();
+        self
+    }
+
+    pub fn writes_resource<R: Resource>(mut self) -> Self {
+        self.access.add_resource_write::<R>();
+        self
+    }
+
+    pub fn queries<Q: QueryParam, Fl: QueryFilter>(mut self) -> Self {
+        self.access.extend(&Query::<Q, Fl>::access());
+        self
+    }
+
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(name.to_string());
+        self
+    }
+
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+}
+
+impl<F: FnMut(&World) + Send + Sync + 'static> System for FunctionSystem<F> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    fn run(&mut self, world: &World) {
+        (self.func)(world);
+    }
+
+    fn before(&self) -> &[String] {
+        &self.before
+    }
+
+    fn after(&self) -> &[String] {
+        &self.after
+    }
+}
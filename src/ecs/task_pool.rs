@@ -0,0 +1,194 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct TaskPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>
+}
+
+struct Latch {
+    remaining: Mutex<usize>,
+    done: Condvar,
+    panicked: Mutex<Option<Box<dyn std::any::Any + Send>>>
+}
+
+impl TaskPool {
+    pub fn new(threads: usize) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads)
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(format!("fuji-worker-{}", i))
+                    .spawn(move || loop {
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break
+                        }
+                    })
+                    .expect("Failed to spawn worker thread")
+            })
+            .collect();
+
+        TaskPool {
+            sender: Some(sender),
+            workers
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Runs every job on the pool and blocks until all of them have finished,
+    // which is what makes lending non-'static borrows to the workers sound.
+    pub fn scope<'s>(&self, jobs: Vec<Box<dyn FnOnce() + Send + 's>>) {
+        let latch = Arc::new(Latch {
+            remaining: Mutex::new(0),
+            done: Condvar::new(),
+            panicked: Mutex::new(None)
+        });
+
+        // Waits when dropped, so a panic while handing out jobs still outlasts the ones already sent.
+        let wait = Wait(&latch);
+
+        for job in jobs {
+            let job: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 's>, Job>(job) };
+            let shared = Arc::clone(&latch);
+
+            *latch.remaining.lock().unwrap() += 1;
+            let sent = self.sender.as_ref().unwrap().send(Box::new(move || {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    shared.panicked.lock().unwrap().get_or_insert(payload);
+                }
+
+                let mut remaining = shared.remaining.lock().unwrap();
+                *remaining -= 1;
+                if *remaining == 0 {
+                    shared.done.notify_all();
+                }
+            }));
+
+            if let Err(unsent) = sent {
+                drop(unsent);
+                *latch.remaining.lock().unwrap() -= 1;
+                panic!("Task pool workers have shut down");
+            }
+        }
+
+        drop(wait);
+
+        let panicked = latch.panicked.lock().unwrap().take();
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+struct Wait<'a>(&'a Latch);
+
+impl Drop for Wait<'_> {
+    fn drop(&mut self) {
+        let mut remaining = self.0.remaining.lock().unwrap();
+        while *remaining > 0 {
+            remaining = self.0.done.wait(remaining).unwrap();
+        }
+    }
+}
+
+impl Default for TaskPool {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        Self::new(threads)
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn scope_runs_borrowing_jobs() {
+        let pool = TaskPool::new(3);
+        let counter = AtomicUsize::new(0);
+        let mut results = vec![0; 8];
+
+        let jobs: Vec<Box<dyn FnOnce() + Send + '_>> = results.iter_mut()
+            .enumerate()
+            .map(|(i, slot)| {
+                let counter = &counter;
+                Box::new(move || {
+                    *slot = i * 2;
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }) as Box<dyn FnOnce() + Send + '_>
+            })
+            .collect();
+        pool.scope(jobs);
+
+        assert_eq!(counter.load(Ordering::SeqCst), 8);
+        assert_eq!(results, vec![0, 2, 4, 6, 8, 10, 12, 14]);
+    }
+
+    #[test]
+    fn panic_is_raised_after_every_job_finished() {
+        let pool = TaskPool::new(2);
+        let counter = AtomicUsize::new(0);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let jobs: Vec<Box<dyn FnOnce() + Send + '_>> = (0..6)
+                .map(|i| {
+                    let counter = &counter;
+                    Box::new(move || {
+                        if i == 1 {
+                            panic!("job failed");
+                        }
+                        thread::sleep(std::time::Duration::from_millis(5));
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    }) as Box<dyn FnOnce() + Send + '_>
+                })
+                .collect();
+            pool.scope(jobs);
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(counter.load(Ordering::SeqCst), 5);
+
+        // The pool keeps working after a job panicked.
+        pool.scope(vec![Box::new(|| { counter.fetch_add(1, Ordering::SeqCst); })]);
+        assert_eq!(counter.load(Ordering::SeqCst), 6);
+    }
+
+    #[test]
+    fn send_failure_panics_without_hanging() {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        drop(receiver);
+        let pool = TaskPool {
+            sender: Some(sender),
+            workers: Vec::new()
+        };
+
+        let ran = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(vec![Box::new(|| { ran.fetch_add(1, Ordering::SeqCst); })]);
+        }));
+        assert!(result.is_err());
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,376 @@
+use std::any::{Any, TypeId, type_name};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::entity::{Entity, Entities};
+use super::storage::{AnyStorage, SparseSet};
+use super::query::{Query, QueryParam, QueryFilter};
+
+pub trait Component: Send + Sync + 'static {}
+impl<T: Send + Sync + 'static> Component for T {}
+
+pub trait Resource: Send + Sync + 'static {}
+impl<T: Send + Sync + 'static> Resource for T {}
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+#[derive(Default)]
+pub struct World {
+    entities: Entities,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    resources: HashMap<TypeId, RwLock<Box<dyn Any + Send + Sync>>>,
+    commands: Mutex<Vec<Command>>
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.allocate()
+    }
+
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        bundle.insert_into(self, entity);
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.free(entity) {
+            return false;
+        }
+
+        for storage in self.storages.values() {
+            storage.remove_entity(entity);
+        }
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RwLock::new(SparseSet::<T>::default())));
+        self.storage::<T>().unwrap().write().unwrap().insert(entity, component);
+        true
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        self.storage::<T>()?.write().unwrap().remove(entity)
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .map(|storage| storage.contains(entity))
+            .unwrap_or(false)
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let guard = self.storage::<T>()?.read().unwrap();
+        if guard.contains(entity) {
+            Some(Ref { guard, entity })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut<T: Component>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        let guard = self.storage::<T>()?.write().unwrap();
+        if guard.contains(entity) {
+            Some(RefMut { guard, entity })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn storage<T: Component>(&self) -> Option<&RwLock<SparseSet<T>>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref::<RwLock<SparseSet<T>>>())
+    }
+
+    pub fn query<Q: QueryParam>(&self) -> Query<'_, Q, ()> {
+        Query::new(self)
+    }
+
+    pub fn query_filtered<Q: QueryParam, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        Query::new(self)
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) {
+        self.resources.insert(TypeId::of::<R>(), RwLock::new(Box::new(resource)));
+    }
+
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        let resource = self.resources.remove(&TypeId::of::<R>())?;
+        resource.into_inner().unwrap().downcast::<R>().ok().map(|boxed| *boxed)
+    }
+
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get_resource<R: Resource>(&self) -> Option<Res<'_, R>> {
+        let guard = self.resources.get(&TypeId::of::<R>())?.read().unwrap();
+        Some(Res { guard, marker: std::marker::PhantomData })
+    }
+
+    pub fn get_resource_mut<R: Resource>(&self) -> Option<ResMut<'_, R>> {
+        let guard = self.resources.get(&TypeId::of::<R>())?.write().unwrap();
+        Some(ResMut { guard, marker: std::marker::PhantomData })
+    }
+
+    pub fn resource<R: Resource>(&self) -> Res<'_, R> {
+        self.get_resource::<R>()
+            .unwrap_or_else(|| panic!("Resource {} does not exist", type_name::<R>()))
+    }
+
+    pub fn resource_mut<R: Resource>(&self) -> ResMut<'_, R> {
+        self.get_resource_mut::<R>()
+            .unwrap_or_else(|| panic!("Resource {} does not exist", type_name::<R>()))
+    }
+
+    pub fn commands(&self) -> Commands<'_> {
+        Commands {
+            queue: &self.commands
+        }
+    }
+
+    pub fn apply_commands(&mut self) {
+        let commands = std::mem::take(self.commands.get_mut().unwrap());
+        for command in commands {
+            command(self);
+        }
+    }
+}
+
+pub struct Ref<'a, T: Component> {
+    guard: RwLockReadGuard<'a, SparseSet<T>>,
+    entity: Entity
+}
+
+impl<T: Component> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.get(self.entity).unwrap()
+    }
+}
+
+pub struct RefMut<'a, T: Component> {
+    guard: RwLockWriteGuard<'a, SparseSet<T>>,
+    entity: Entity
+}
+
+impl<T: Component> Deref for RefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.get(self.entity).unwrap()
+    }
+}
+
+impl<T: Component> DerefMut for RefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.get_mut(self.entity).unwrap()
+    }
+}
+
+pub struct Res<'a, R: Resource> {
+    guard: RwLockReadGuard<'a, Box<dyn Any + Send + Sync>>,
+    marker: std::marker::PhantomData<R>
+}
+
+impl<R: Resource> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.guard.downcast_ref::<R>().unwrap()
+    }
+}
+
+pub struct ResMut<'a, R: Resource> {
+    guard: RwLockWriteGuard<'a, Box<dyn Any + Send + Sync>>,
+    marker: std::marker::PhantomData<R>
+}
+
+impl<R: Resource> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.guard.downcast_ref::<R>().unwrap()
+    }
+}
+
+impl<R: Resource> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.guard.downcast_mut::<R>().unwrap()
+    }
+}
+
+pub struct Commands<'w> {
+    queue: &'w Mutex<Vec<Command>>
+}
+
+impl Commands<'_> {
+    pub fn add<C: FnOnce(&mut World) + Send + 'static>(&self, command: C) {
+        self.queue.lock().unwrap().push(Box::new(command));
+    }
+
+    pub fn spawn<B: Bundle>(&self, bundle: B) {
+        self.add(move |world| {
+            world.spawn_with(bundle);
+        });
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert<T: Component>(&self, entity: Entity, component: T) {
+        self.add(move |world| {
+            world.insert(entity, component);
+        });
+    }
+
+    pub fn remove<T: Component>(&self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    pub fn insert_resource<R: Resource>(&self, resource: R) {
+        self.add(move |world| world.insert_resource(resource));
+    }
+}
+
+pub trait Bundle: Send + 'static {
+    fn insert_into(self, world: &mut World, entity: Entity);
+}
+
+macro_rules! impl_bundle {
+    ($($name: ident), +) => {
+        impl<$($name: Component), +> Bundle for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn insert_into(self, world: &mut World, entity: Entity) {
+                let ($($name,)+) = self;
+                $(world.insert(entity, $name);)+
+            }
+        }
+    }
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    #[test]
+    fn components_follow_the_entity() {
+        let mut world = World::new();
+        let entity = world.spawn_with((Position(1), Name("a")));
+
+        assert!(world.has::<Position>(entity));
+        assert_eq!(*world.get::<Name>(entity).unwrap(), Name("a"));
+        world.get_mut::<Position>(entity).unwrap().0 += 1;
+        assert_eq!(*world.get::<Position>(entity).unwrap(), Position(2));
+
+        assert_eq!(world.remove::<Name>(entity), Some(Name("a")));
+        assert!(!world.has::<Name>(entity));
+        assert!(world.get::<Score>(entity).is_none());
+    }
+
+    #[test]
+    fn despawn_drops_components_and_ignores_stale_entities() {
+        let mut world = World::new();
+        let entity = world.spawn_with((Position(1),));
+        assert!(world.despawn(entity));
+        assert!(!world.despawn(entity));
+        assert!(!world.is_alive(entity));
+        assert!(!world.has::<Position>(entity));
+
+        // The slot gets reused, the old handle must not see the new entity's components.
+        let reused = world.spawn_with((Position(5),));
+        assert_eq!(reused.index(), entity.index());
+        assert!(world.get::<Position>(entity).is_none());
+        assert!(!world.insert(entity, Name("stale")));
+        assert!(!world.has::<Name>(reused));
+    }
+
+    #[test]
+    fn resources() {
+        let mut world = World::new();
+        assert!(world.get_resource::<Score>().is_none());
+
+        world.insert_resource(Score(1));
+        assert!(world.contains_resource::<Score>());
+        world.resource_mut::<Score>().0 += 2;
+        assert_eq!(*world.resource::<Score>(), Score(3));
+
+        world.insert_resource(Score(10));
+        assert_eq!(world.remove_resource::<Score>(), Some(Score(10)));
+        assert!(!world.contains_resource::<Score>());
+        assert!(world.get_resource_mut::<Score>().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn missing_resource_panics() {
+        World::new().resource::<Score>();
+    }
+
+    #[test]
+    fn commands_wait_for_apply() {
+        let mut world = World::new();
+        let entity = world.spawn();
+
+        world.commands().insert(entity, Position(1));
+        world.commands().spawn((Name("b"),));
+        world.commands().insert_resource(Score(4));
+        assert!(!world.has::<Position>(entity));
+        assert_eq!(world.entities().len(), 1);
+
+        world.apply_commands();
+        assert!(world.has::<Position>(entity));
+        assert_eq!(world.entities().len(), 2);
+        assert_eq!(*world.resource::<Score>(), Score(4));
+
+        world.commands().despawn(entity);
+        world.apply_commands();
+        assert!(!world.is_alive(entity));
+    }
+}
@@ -0,0 +1,485 @@
+use anyhow::{anyhow, bail, Context, Result};
+use glm::Vec2;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use super::binding::{Axis2Binding, AxisBinding, Binding};
+use super::buttons::ButtonInput;
+use super::gamepad::Gamepads;
+use super::state::Input;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisSettings {
+    pub dead_zone: f32,
+    pub sensitivity: f32,
+    pub invert: bool
+}
+
+impl Default for AxisSettings {
+    fn default() -> Self {
+        AxisSettings {
+            dead_zone: 0.15,
+            sensitivity: 1.0,
+            invert: false
+        }
+    }
+}
+
+impl AxisSettings {
+    // Rescales what is left outside the dead zone so values still reach 1.
+    pub fn apply_dead_zone(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        if magnitude <= self.dead_zone || self.dead_zone >= 1.0 {
+            return 0.0;
+        }
+        value.signum() * ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0)
+    }
+
+    pub fn apply_radial_dead_zone(&self, value: Vec2) -> Vec2 {
+        let length = (value.x * value.x + value.y * value.y).sqrt();
+        if length <= self.dead_zone || self.dead_zone >= 1.0 {
+            return Vec2::new(0.0, 0.0);
+        }
+        let scale = ((length - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0) / length;
+        Vec2::new(value.x * scale, value.y * scale)
+    }
+
+    fn output(&self, value: f32) -> f32 {
+        let value = value * self.sensitivity;
+        if self.invert { -value } else { value }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ActionMap {
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, (Vec<AxisBinding>, AxisSettings)>,
+    axes_2d: BTreeMap<String, (Vec<Axis2Binding>, AxisSettings)>
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind_action(&mut self, name: &str, binding: Binding) -> &mut Self {
+        let bindings = self.actions.entry(name.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+        self
+    }
+
+    pub fn bind_axis(&mut self, name: &str, binding: AxisBinding) -> &mut Self {
+        self.axes.entry(name.to_string()).or_default().0.push(binding);
+        self
+    }
+
+    pub fn bind_axis_2d(&mut self, name: &str, binding: Axis2Binding) -> &mut Self {
+        self.axes_2d.entry(name.to_string()).or_default().0.push(binding);
+        self
+    }
+
+    pub fn set_axis_settings(&mut self, name: &str, settings: AxisSettings) -> &mut Self {
+        self.axes.entry(name.to_string()).or_default().1 = settings;
+        self
+    }
+
+    pub fn set_axis_2d_settings(&mut self, name: &str, settings: AxisSettings) -> &mut Self {
+        self.axes_2d.entry(name.to_string()).or_default().1 = settings;
+        self
+    }
+
+    // Replaces one binding of an action, or adds it when the slot does not exist yet.
+    pub fn rebind_action(&mut self, name: &str, slot: usize, binding: Binding) {
+        let bindings = self.actions.entry(name.to_string()).or_default();
+        bindings.retain(|existing| *existing != binding);
+        if slot < bindings.len() {
+            bindings[slot] = binding;
+        } else {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn rebind_axis(&mut self, name: &str, slot: usize, binding: AxisBinding) {
+        let bindings = &mut self.axes.entry(name.to_string()).or_default().0;
+        if slot < bindings.len() {
+            bindings[slot] = binding;
+        } else {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn rebind_axis_2d(&mut self, name: &str, slot: usize, binding: Axis2Binding) {
+        let bindings = &mut self.axes_2d.entry(name.to_string()).or_default().0;
+        if slot < bindings.len() {
+            bindings[slot] = binding;
+        } else {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_action(&mut self, name: &str, binding: Binding) {
+        if let Some(bindings) = self.actions.get_mut(name) {
+            bindings.retain(|existing| *existing != binding);
+        }
+    }
+
+    pub fn clear_action(&mut self, name: &str) {
+        if let Some(bindings) = self.actions.get_mut(name) {
+            bindings.clear();
+        }
+    }
+
+    pub fn action_bindings(&self, name: &str) -> &[Binding] {
+        self.actions.get(name).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn axis_bindings(&self, name: &str) -> &[AxisBinding] {
+        self.axes.get(name).map(|(bindings, _)| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn axis_2d_bindings(&self, name: &str) -> &[Axis2Binding] {
+        self.axes_2d.get(name).map(|(bindings, _)| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn axis_settings(&self, name: &str) -> Option<AxisSettings> {
+        self.axes.get(name)
+            .map(|(_, settings)| *settings)
+            .or_else(|| self.axes_2d.get(name).map(|(_, settings)| *settings))
+    }
+
+    pub fn action_names(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(|name| name.as_str())
+    }
+
+    pub fn axis_names(&self) -> impl Iterator<Item = &str> {
+        self.axes.keys().map(|name| name.as_str())
+    }
+
+    pub fn axis_2d_names(&self) -> impl Iterator<Item = &str> {
+        self.axes_2d.keys().map(|name| name.as_str())
+    }
+
+    // Keeps the bindings of `self` and only fills in names the defaults have but `self` lacks,
+    // so new actions still get bound when an older bindings file is loaded.
+    pub fn merge_defaults(&mut self, defaults: &ActionMap) {
+        for (name, bindings) in defaults.actions.iter() {
+            self.actions.entry(name.clone()).or_insert_with(|| bindings.clone());
+        }
+        for (name, axis) in defaults.axes.iter() {
+            self.axes.entry(name.clone()).or_insert_with(|| axis.clone());
+        }
+        for (name, axis) in defaults.axes_2d.iter() {
+            self.axes_2d.entry(name.clone()).or_insert_with(|| axis.clone());
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bindings from {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse bindings in {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_config())
+            .with_context(|| format!("Failed to write bindings to {}", path.display()))
+    }
+
+    // One entry per line: `<action|axis|axis2> <name> [option=value ...] = <binding> ...`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut map = ActionMap::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            map.parse_line(line).with_context(|| format!("Line {}: {}", number + 1, line))?;
+        }
+
+        Ok(map)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        // Options are written as `key=value`, only the separator has a space before the `=`.
+        let (head, bindings) = line.split_once(" =").ok_or_else(|| anyhow!("Expected ` = ` after the name"))?;
+
+        let mut head = head.split_whitespace();
+        let kind = head.next().ok_or_else(|| anyhow!("Missing entry kind"))?;
+        let name = head.next().ok_or_else(|| anyhow!("Missing name"))?;
+
+        let mut settings = AxisSettings::default();
+        for option in head {
+            let (key, value) = option.split_once('=').ok_or_else(|| anyhow!("Option {} has no value", option))?;
+            match key {
+                "dead_zone" => settings.dead_zone = value.parse().with_context(|| format!("Invalid dead zone {}", value))?,
+                "sensitivity" => settings.sensitivity = value.parse().with_context(|| format!("Invalid sensitivity {}", value))?,
+                "invert" => settings.invert = value.parse().with_context(|| format!("Invalid invert flag {}", value))?,
+                _ => bail!("Unknown option {}", key)
+            }
+        }
+
+        let bindings = bindings.split_whitespace();
+        match kind {
+            "action" => {
+                self.actions.entry(name.to_string()).or_default();
+                for binding in bindings {
+                    self.bind_action(name, binding.parse()?);
+                }
+            },
+            "axis" => {
+                self.set_axis_settings(name, settings);
+                for binding in bindings {
+                    self.bind_axis(name, binding.parse()?);
+                }
+            },
+            "axis2" => {
+                self.set_axis_2d_settings(name, settings);
+                for binding in bindings {
+                    self.bind_axis_2d(name, binding.parse()?);
+                }
+            },
+            _ => bail!("Unknown entry kind {}", kind)
+        }
+
+        Ok(())
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut out = String::from("# Fuji input bindings\n");
+
+        for (name, bindings) in self.actions.iter() {
+            let _ = write!(out, "action {} =", name);
+            for binding in bindings {
+                let _ = write!(out, " {}", binding);
+            }
+            out.push('\n');
+        }
+
+        for (name, (bindings, settings)) in self.axes.iter() {
+            let _ = write!(out, "axis {} dead_zone={} sensitivity={} invert={} =", name, settings.dead_zone, settings.sensitivity, settings.invert);
+            for binding in bindings {
+                let _ = write!(out, " {}", binding);
+            }
+            out.push('\n');
+        }
+
+        for (name, (bindings, settings)) in self.axes_2d.iter() {
+            let _ = write!(out, "axis2 {} dead_zone={} sensitivity={} invert={} =", name, settings.dead_zone, settings.sensitivity, settings.invert);
+            for binding in bindings {
+                let _ = write!(out, " {}", binding);
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+pub struct Actions {
+    map: ActionMap,
+    pressed: ButtonInput<String>,
+    values: HashMap<String, f32>,
+    values_2d: HashMap<String, Vec2>
+}
+
+impl Actions {
+    pub fn new(map: ActionMap) -> Self {
+        Actions {
+            map,
+            pressed: ButtonInput::default(),
+            values: HashMap::new(),
+            values_2d: HashMap::new()
+        }
+    }
+
+    pub fn map(&self) -> &ActionMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut ActionMap {
+        &mut self.map
+    }
+
+    pub fn set_map(&mut self, map: ActionMap) {
+        self.map = map;
+        self.pressed.release_all();
+    }
+
+    pub fn pressed(&self, action: &str) -> bool {
+        self.pressed.pressed(&action.to_string())
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.pressed.just_pressed(&action.to_string())
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.pressed.just_released(&action.to_string())
+    }
+
+    pub fn value(&self, axis: &str) -> f32 {
+        self.values.get(axis).copied().unwrap_or(0.0)
+    }
+
+    pub fn value_2d(&self, axis: &str) -> Vec2 {
+        self.values_2d.get(axis).copied().unwrap_or(Vec2::new(0.0, 0.0))
+    }
+
+    pub fn update(&mut self, input: &Input, gamepads: &Gamepads) {
+        self.pressed.clear();
+
+        // A press and release that both happened since the last update still count, the action
+        // goes down and up again within the frame.
+        for (name, bindings) in self.map.actions.iter() {
+            let held = bindings.iter().any(|binding| binding.pressed(input, gamepads));
+            let tapped = bindings.iter().any(|binding| binding.just_pressed(input, gamepads));
+            let released = bindings.iter().any(|binding| binding.just_released(input, gamepads));
+
+            if !held || (tapped && released) {
+                self.pressed.release(name.clone());
+            }
+            if held || tapped {
+                self.pressed.press(name.clone());
+            }
+            if !held {
+                self.pressed.release(name.clone());
+            }
+        }
+
+        // Several bindings on one axis do not add up, the strongest one wins.
+        for (name, (bindings, settings)) in self.map.axes.iter() {
+            let value = bindings.iter()
+                .map(|binding| {
+                    let value = binding.value(input, gamepads);
+                    if binding.is_analog() { settings.apply_dead_zone(value) } else { value }
+                })
+                .fold(0.0, |strongest: f32, value| if value.abs() > strongest.abs() { value } else { strongest });
+            self.values.insert(name.clone(), settings.output(value));
+        }
+
+        for (name, (bindings, settings)) in self.map.axes_2d.iter() {
+            let value = bindings.iter()
+                .map(|binding| {
+                    let value = binding.value(input, gamepads);
+                    if binding.is_analog() { settings.apply_radial_dead_zone(value) } else { value }
+                })
+                .fold(Vec2::new(0.0, 0.0), |strongest, value| {
+                    if value.x * value.x + value.y * value.y > strongest.x * strongest.x + strongest.y * strongest.y { value } else { strongest }
+                });
+            self.values_2d.insert(name.clone(), Vec2::new(settings.output(value.x), settings.output(value.y)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::event::InputEvent;
+    use winit::event::MouseButton;
+    use winit::keyboard::{Key, KeyCode, NamedKey};
+
+    fn key_event(key: KeyCode, pressed: bool) -> InputEvent {
+        let logical = Key::Named(NamedKey::Space);
+        if pressed {
+            InputEvent::KeyPressed { key: Some(key), logical, text: None }
+        } else {
+            InputEvent::KeyReleased { key: Some(key), logical }
+        }
+    }
+
+    fn jump_actions() -> Actions {
+        let mut map = ActionMap::new();
+        map.bind_action("jump", Binding::Key(KeyCode::Space));
+        map.bind_action("jump", Binding::Mouse(MouseButton::Left));
+        Actions::new(map)
+    }
+
+    // Updates the actions the way a frame does, then clears the per frame input.
+    fn frame(actions: &mut Actions, input: &mut Input, events: &[InputEvent]) {
+        for event in events {
+            input.apply(event);
+        }
+        actions.update(input, &Gamepads::default());
+        input.end_frame();
+    }
+
+    #[test]
+    fn hold_and_release() {
+        let mut actions = jump_actions();
+        let mut input = Input::default();
+
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, true)]);
+        assert!(actions.pressed("jump") && actions.just_pressed("jump"));
+
+        frame(&mut actions, &mut input, &[]);
+        assert!(actions.pressed("jump") && !actions.just_pressed("jump"));
+
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, false)]);
+        assert!(!actions.pressed("jump") && actions.just_released("jump"));
+    }
+
+    #[test]
+    fn tap_between_updates_is_not_lost() {
+        let mut actions = jump_actions();
+        let mut input = Input::default();
+
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, true), key_event(KeyCode::Space, false)]);
+        assert!(actions.just_pressed("jump"));
+        assert!(actions.just_released("jump"));
+        assert!(!actions.pressed("jump"));
+
+        frame(&mut actions, &mut input, &[]);
+        assert!(!actions.just_pressed("jump") && !actions.just_released("jump"));
+    }
+
+    #[test]
+    fn quick_repress_fires_again() {
+        let mut actions = jump_actions();
+        let mut input = Input::default();
+
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, true)]);
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, false), key_event(KeyCode::Space, true)]);
+        assert!(actions.pressed("jump"));
+        assert!(actions.just_pressed("jump"));
+        assert!(actions.just_released("jump"));
+    }
+
+    #[test]
+    fn second_binding_keeps_the_action_held() {
+        let mut actions = jump_actions();
+        let mut input = Input::default();
+
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, true), InputEvent::MousePressed(MouseButton::Left)]);
+        frame(&mut actions, &mut input, &[key_event(KeyCode::Space, false)]);
+        assert!(actions.pressed("jump"));
+        assert!(!actions.just_released("jump"));
+    }
+
+    #[test]
+    fn axis_settings_and_config() {
+        let settings = AxisSettings { dead_zone: 0.2, sensitivity: 2.0, invert: true };
+        assert_eq!(settings.apply_dead_zone(0.1), 0.0);
+        assert!((settings.apply_dead_zone(0.6) - 0.5).abs() < 1e-6);
+        assert_eq!(settings.output(0.5), -1.0);
+
+        let mut map = ActionMap::new();
+        map.bind_axis("move", AxisBinding::wasd_x()).set_axis_settings("move", settings);
+        map.bind_axis_2d("look", Axis2Binding::Mouse);
+        let parsed = ActionMap::parse(&map.to_config()).unwrap();
+        assert_eq!(parsed.axis_bindings("move"), map.axis_bindings("move"));
+        assert_eq!(parsed.axis_settings("move"), Some(settings));
+        assert_eq!(parsed.axis_2d_bindings("look"), &[Axis2Binding::Mouse]);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = ActionMap::parse("action jump = key:Space\naction fire = key:Nope").err().unwrap();
+        assert!(format!("{:#}", error).contains("Line 2"));
+    }
+}
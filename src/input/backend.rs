@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected { id: GamepadId, name: String, supports_rumble: bool },
+    Disconnected(GamepadId),
+    ButtonPressed(GamepadId, GamepadButton),
+    ButtonReleased(GamepadId, GamepadButton),
+    AxisChanged(GamepadId, GamepadAxis, f32)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rumble {
+    pub strong: f32,
+    pub weak: f32,
+    pub duration: Duration
+}
+
+impl Rumble {
+    pub fn new(strong: f32, weak: f32, duration: Duration) -> Self {
+        Rumble {
+            strong: strong.clamp(0.0, 1.0),
+            weak: weak.clamp(0.0, 1.0),
+            duration
+        }
+    }
+}
+
+pub trait GamepadBackend: Send {
+    fn name(&self) -> &str;
+
+    // Appends everything that happened since the last poll, in order.
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>);
+
+    // Returns false when the pad is gone or cannot rumble.
+    fn rumble(&mut self, _id: GamepadId, _rumble: Rumble) -> bool {
+        false
+    }
+}
+
+// Stands in when no controller support is available, no pads ever connect.
+#[derive(Default)]
+pub struct NullGamepadBackend;
+
+impl GamepadBackend for NullGamepadBackend {
+    fn name(&self) -> &str {
+        "null"
+    }
+
+    fn poll(&mut self, _events: &mut Vec<GamepadEvent>) {}
+}
+
+#[derive(Default)]
+struct VirtualState {
+    next_id: usize,
+    events: Vec<GamepadEvent>,
+    rumble_support: HashMap<GamepadId, bool>,
+    rumbles: HashMap<GamepadId, Vec<Rumble>>
+}
+
+// In-memory pads for tests and machines without controllers. Clones share the same pads,
+// so one clone can be handed to the app while another drives it.
+#[derive(Clone, Default)]
+pub struct VirtualGamepadBackend {
+    state: Arc<Mutex<VirtualState>>
+}
+
+impl VirtualGamepadBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connect(&self, name: &str) -> VirtualGamepad {
+        self.connect_with_rumble(name, true)
+    }
+
+    pub fn connect_with_rumble(&self, name: &str, supports_rumble: bool) -> VirtualGamepad {
+        let mut state = self.state.lock().unwrap();
+        let id = GamepadId(state.next_id);
+        state.next_id += 1;
+        state.rumble_support.insert(id, supports_rumble);
+        state.events.push(GamepadEvent::Connected { id, name: name.to_string(), supports_rumble });
+
+        VirtualGamepad {
+            id,
+            state: Arc::clone(&self.state)
+        }
+    }
+}
+
+impl GamepadBackend for VirtualGamepadBackend {
+    fn name(&self) -> &str {
+        "virtual"
+    }
+
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        events.append(&mut self.state.lock().unwrap().events);
+    }
+
+    fn rumble(&mut self, id: GamepadId, rumble: Rumble) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !state.rumble_support.get(&id).copied().unwrap_or(false) {
+            return false;
+        }
+        state.rumbles.entry(id).or_default().push(rumble);
+        true
+    }
+}
+
+pub struct VirtualGamepad {
+    id: GamepadId,
+    state: Arc<Mutex<VirtualState>>
+}
+
+impl VirtualGamepad {
+    pub fn id(&self) -> GamepadId {
+        self.id
+    }
+
+    fn push(&self, event: GamepadEvent) {
+        self.state.lock().unwrap().events.push(event);
+    }
+
+    pub fn press(&self, button: GamepadButton) {
+        self.push(GamepadEvent::ButtonPressed(self.id, button));
+    }
+
+    pub fn release(&self, button: GamepadButton) {
+        self.push(GamepadEvent::ButtonReleased(self.id, button));
+    }
+
+    pub fn set_axis(&self, axis: GamepadAxis, value: f32) {
+        self.push(GamepadEvent::AxisChanged(self.id, axis, value));
+    }
+
+    pub fn rumbles(&self) -> Vec<Rumble> {
+        self.state.lock().unwrap().rumbles.get(&self.id).cloned().unwrap_or_default()
+    }
+
+    pub fn disconnect(self) {
+        let mut state = self.state.lock().unwrap();
+        state.rumble_support.remove(&self.id);
+        state.events.push(GamepadEvent::Disconnected(self.id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Gamepads;
+
+    // What the app does with the backend at the start of a frame.
+    fn poll(backend: &mut VirtualGamepadBackend, gamepads: &mut Gamepads) {
+        let mut events = Vec::new();
+        backend.poll(&mut events);
+        for event in events.iter() {
+            gamepads.handle_event(event);
+        }
+        gamepads.flush_rumble(backend);
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let mut backend = VirtualGamepadBackend::new();
+        let mut gamepads = Gamepads::default();
+        let first = backend.connect("first");
+        let second = backend.connect("second");
+        poll(&mut backend, &mut gamepads);
+        assert_eq!(gamepads.len(), 2);
+        assert_eq!(gamepads.just_connected(), &[first.id(), second.id()]);
+        assert_eq!(gamepads.get(first.id()).unwrap().name, "first");
+
+        gamepads.end_frame();
+        let id = first.id();
+        first.disconnect();
+        poll(&mut backend, &mut gamepads);
+        assert!(gamepads.just_connected().is_empty());
+        assert_eq!(gamepads.just_disconnected(), &[id]);
+        assert!(gamepads.get(id).is_none());
+        assert_eq!(gamepads.len(), 1);
+    }
+
+    #[test]
+    fn just_pressed_lasts_one_frame() {
+        let mut backend = VirtualGamepadBackend::new();
+        let mut gamepads = Gamepads::default();
+        let pad = backend.connect("pad");
+        pad.press(GamepadButton::South);
+        poll(&mut backend, &mut gamepads);
+        assert!(gamepads.any_pressed(GamepadButton::South));
+        assert!(gamepads.any_just_pressed(GamepadButton::South));
+
+        gamepads.end_frame();
+        poll(&mut backend, &mut gamepads);
+        assert!(gamepads.any_pressed(GamepadButton::South));
+        assert!(!gamepads.any_just_pressed(GamepadButton::South));
+
+        pad.release(GamepadButton::South);
+        poll(&mut backend, &mut gamepads);
+        assert!(!gamepads.any_pressed(GamepadButton::South));
+        assert!(gamepads.any_just_released(GamepadButton::South));
+        gamepads.end_frame();
+        assert!(!gamepads.any_just_released(GamepadButton::South));
+    }
+
+    #[test]
+    fn axes_are_clamped() {
+        let mut backend = VirtualGamepadBackend::new();
+        let mut gamepads = Gamepads::default();
+        let pad = backend.connect("pad");
+        pad.set_axis(GamepadAxis::LeftStickX, 3.0);
+        pad.set_axis(GamepadAxis::LeftStickY, -2.5);
+        pad.set_axis(GamepadAxis::RightTrigger, 0.25);
+        poll(&mut backend, &mut gamepads);
+        assert_eq!(gamepads.axis(GamepadAxis::LeftStickX), 1.0);
+        assert_eq!(gamepads.axis(GamepadAxis::LeftStickY), -1.0);
+        assert_eq!(gamepads.axis(GamepadAxis::RightTrigger), 0.25);
+    }
+
+    #[test]
+    fn null_backend_has_no_pads() {
+        let mut backend = NullGamepadBackend;
+        let mut events = Vec::new();
+        backend.poll(&mut events);
+        assert!(events.is_empty());
+        assert!(!backend.rumble(GamepadId(0), Rumble::new(1.0, 1.0, Duration::from_millis(10))));
+    }
+
+    #[test]
+    fn rumble_needs_support() {
+        let mut backend = VirtualGamepadBackend::new();
+        let mut gamepads = Gamepads::default();
+        let shaker = backend.connect_with_rumble("shaker", true);
+        let still = backend.connect_with_rumble("still", false);
+        poll(&mut backend, &mut gamepads);
+
+        let rumble = Rumble::new(2.0, 0.5, Duration::from_millis(100));
+        assert!(gamepads.rumble(shaker.id(), rumble));
+        assert!(!gamepads.rumble(still.id(), rumble));
+        assert!(!gamepads.rumble(GamepadId(99), rumble));
+        poll(&mut backend, &mut gamepads);
+        assert_eq!(shaker.rumbles(), vec![Rumble { strong: 1.0, weak: 0.5, duration: Duration::from_millis(100) }]);
+        assert!(still.rumbles().is_empty());
+        assert!(!backend.rumble(still.id(), rumble));
+    }
+}
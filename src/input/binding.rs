@@ -0,0 +1,379 @@
+use anyhow::{anyhow, bail, Result};
+use glm::Vec2;
+use std::fmt;
+use std::str::FromStr;
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+use super::gamepad::{GamepadAxis, GamepadButton, GamepadStick, Gamepads};
+use super::state::Input;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    GamepadButton(GamepadButton)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisBinding {
+    Buttons { negative: Binding, positive: Binding },
+    GamepadAxis(GamepadAxis),
+    MouseX,
+    MouseY,
+    Scroll
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Axis2Binding {
+    Buttons { up: Binding, down: Binding, left: Binding, right: Binding },
+    GamepadStick(GamepadStick),
+    Mouse
+}
+
+impl Binding {
+    pub fn pressed(&self, input: &Input, gamepads: &Gamepads) -> bool {
+        match self {
+            Binding::Key(key) => input.key_pressed(*key),
+            Binding::Mouse(button) => input.mouse_pressed(*button),
+            Binding::GamepadButton(button) => gamepads.any_pressed(*button)
+        }
+    }
+
+    pub fn just_pressed(&self, input: &Input, gamepads: &Gamepads) -> bool {
+        match self {
+            Binding::Key(key) => input.key_just_pressed(*key),
+            Binding::Mouse(button) => input.mouse_just_pressed(*button),
+            Binding::GamepadButton(button) => gamepads.any_just_pressed(*button)
+        }
+    }
+
+    pub fn just_released(&self, input: &Input, gamepads: &Gamepads) -> bool {
+        match self {
+            Binding::Key(key) => input.key_just_released(*key),
+            Binding::Mouse(button) => input.mouse_just_released(*button),
+            Binding::GamepadButton(button) => gamepads.any_just_released(*button)
+        }
+    }
+
+    // The first binding pressed this frame, for "press a key" rebinding prompts.
+    pub fn capture(input: &Input, gamepads: &Gamepads) -> Option<Binding> {
+        if let Some(key) = input.keys().get_just_pressed().next() {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = input.mouse_buttons().get_just_pressed().next() {
+            return Some(Binding::Mouse(*button));
+        }
+        GamepadButton::ALL.iter()
+            .find(|button| gamepads.any_just_pressed(**button))
+            .map(|button| Binding::GamepadButton(*button))
+    }
+}
+
+impl AxisBinding {
+    pub fn wasd_x() -> Self {
+        AxisBinding::Buttons { negative: Binding::Key(KeyCode::KeyA), positive: Binding::Key(KeyCode::KeyD) }
+    }
+
+    pub fn wasd_y() -> Self {
+        AxisBinding::Buttons { negative: Binding::Key(KeyCode::KeyS), positive: Binding::Key(KeyCode::KeyW) }
+    }
+
+    // Raw value before dead zone and sensitivity; mouse and scroll are not clamped.
+    pub fn value(&self, input: &Input, gamepads: &Gamepads) -> f32 {
+        match self {
+            AxisBinding::Buttons { negative, positive } => {
+                let mut value = 0.0;
+                if negative.pressed(input, gamepads) {
+                    value -= 1.0;
+                }
+                if positive.pressed(input, gamepads) {
+                    value += 1.0;
+                }
+                value
+            },
+            AxisBinding::GamepadAxis(axis) => gamepads.axis(*axis),
+            AxisBinding::MouseX => input.mouse_delta().x,
+            AxisBinding::MouseY => input.mouse_delta().y,
+            AxisBinding::Scroll => input.scroll_lines().y
+        }
+    }
+
+    pub fn is_analog(&self) -> bool {
+        matches!(self, AxisBinding::GamepadAxis(_))
+    }
+}
+
+impl Axis2Binding {
+    pub fn wasd() -> Self {
+        Axis2Binding::Buttons {
+            up: Binding::Key(KeyCode::KeyW),
+            down: Binding::Key(KeyCode::KeyS),
+            left: Binding::Key(KeyCode::KeyA),
+            right: Binding::Key(KeyCode::KeyD)
+        }
+    }
+
+    pub fn arrows() -> Self {
+        Axis2Binding::Buttons {
+            up: Binding::Key(KeyCode::ArrowUp),
+            down: Binding::Key(KeyCode::ArrowDown),
+            left: Binding::Key(KeyCode::ArrowLeft),
+            right: Binding::Key(KeyCode::ArrowRight)
+        }
+    }
+
+    pub fn value(&self, input: &Input, gamepads: &Gamepads) -> Vec2 {
+        match self {
+            Axis2Binding::Buttons { up, down, left, right } => {
+                let x = AxisBinding::Buttons { negative: *left, positive: *right }.value(input, gamepads);
+                let y = AxisBinding::Buttons { negative: *down, positive: *up }.value(input, gamepads);
+                let length = (x * x + y * y).sqrt();
+
+                // Diagonals would otherwise be faster than straight movement.
+                if length > 1.0 {
+                    Vec2::new(x / length, y / length)
+                } else {
+                    Vec2::new(x, y)
+                }
+            },
+            Axis2Binding::GamepadStick(stick) => {
+                let (x, y) = stick.axes();
+                Vec2::new(gamepads.axis(x), gamepads.axis(y))
+            },
+            Axis2Binding::Mouse => input.mouse_delta()
+        }
+    }
+
+    pub fn is_analog(&self) -> bool {
+        matches!(self, Axis2Binding::GamepadStick(_))
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "key:{:?}", key),
+            Binding::Mouse(MouseButton::Other(index)) => write!(f, "mouse:{}", index),
+            Binding::Mouse(button) => write!(f, "mouse:{:?}", button),
+            Binding::GamepadButton(button) => write!(f, "button:{:?}", button)
+        }
+    }
+}
+
+impl fmt::Display for AxisBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AxisBinding::Buttons { negative, positive } => write!(f, "buttons:{},{}", negative, positive),
+            AxisBinding::GamepadAxis(axis) => write!(f, "axis:{:?}", axis),
+            AxisBinding::MouseX => write!(f, "mouse_x"),
+            AxisBinding::MouseY => write!(f, "mouse_y"),
+            AxisBinding::Scroll => write!(f, "scroll")
+        }
+    }
+}
+
+impl fmt::Display for Axis2Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Axis2Binding::Buttons { up, down, left, right } => write!(f, "buttons:{},{},{},{}", up, down, left, right),
+            Axis2Binding::GamepadStick(stick) => write!(f, "stick:{:?}", stick),
+            Axis2Binding::Mouse => write!(f, "mouse")
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, name) = s.split_once(':').ok_or_else(|| anyhow!("Binding {} is missing a kind", s))?;
+        match kind {
+            "key" => key_code_from_name(name)
+                .map(Binding::Key)
+                .ok_or_else(|| anyhow!("Unknown key {}", name)),
+            "mouse" => mouse_button_from_name(name)
+                .map(Binding::Mouse)
+                .ok_or_else(|| anyhow!("Unknown mouse button {}", name)),
+            "button" => GamepadButton::from_name(name)
+                .map(Binding::GamepadButton)
+                .ok_or_else(|| anyhow!("Unknown gamepad button {}", name)),
+            _ => bail!("Unknown binding kind {}", kind)
+        }
+    }
+}
+
+impl FromStr for AxisBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mouse_x" => return Ok(AxisBinding::MouseX),
+            "mouse_y" => return Ok(AxisBinding::MouseY),
+            "scroll" => return Ok(AxisBinding::Scroll),
+            _ => ()
+        }
+
+        let (kind, rest) = s.split_once(':').ok_or_else(|| anyhow!("Unknown axis binding {}", s))?;
+        match kind {
+            "buttons" => {
+                let buttons = parse_buttons(rest, 2)?;
+                Ok(AxisBinding::Buttons { negative: buttons[0], positive: buttons[1] })
+            },
+            "axis" => GamepadAxis::from_name(rest)
+                .map(AxisBinding::GamepadAxis)
+                .ok_or_else(|| anyhow!("Unknown gamepad axis {}", rest)),
+            _ => bail!("Unknown axis binding kind {}", kind)
+        }
+    }
+}
+
+impl FromStr for Axis2Binding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "mouse" {
+            return Ok(Axis2Binding::Mouse);
+        }
+
+        let (kind, rest) = s.split_once(':').ok_or_else(|| anyhow!("Unknown 2D axis binding {}", s))?;
+        match kind {
+            "buttons" => {
+                let buttons = parse_buttons(rest, 4)?;
+                Ok(Axis2Binding::Buttons { up: buttons[0], down: buttons[1], left: buttons[2], right: buttons[3] })
+            },
+            "stick" => GamepadStick::from_name(rest)
+                .map(Axis2Binding::GamepadStick)
+                .ok_or_else(|| anyhow!("Unknown gamepad stick {}", rest)),
+            _ => bail!("Unknown 2D axis binding kind {}", kind)
+        }
+    }
+}
+
+fn parse_buttons(s: &str, count: usize) -> Result<Vec<Binding>> {
+    let buttons = s.split(',').map(Binding::from_str).collect::<Result<Vec<Binding>>>()?;
+    if buttons.len() != count {
+        bail!("Expected {} buttons in {}, found {}", count, s, buttons.len());
+    }
+    Ok(buttons)
+}
+
+fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Right" => Some(MouseButton::Right),
+        "Middle" => Some(MouseButton::Middle),
+        "Back" => Some(MouseButton::Back),
+        "Forward" => Some(MouseButton::Forward),
+        _ => name.parse().ok().map(MouseButton::Other)
+    }
+}
+
+// Every key winit knows, named as it prints them.
+const KEY_CODES: &[KeyCode] = &[
+    KeyCode::Backquote, KeyCode::Backslash, KeyCode::BracketLeft, KeyCode::BracketRight, KeyCode::Comma,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5,
+    KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9, KeyCode::Equal,
+    KeyCode::IntlBackslash, KeyCode::IntlRo, KeyCode::IntlYen, KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC,
+    KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ,
+    KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ,
+    KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ, KeyCode::Minus, KeyCode::Period, KeyCode::Quote, KeyCode::Semicolon,
+    KeyCode::Slash, KeyCode::AltLeft, KeyCode::AltRight, KeyCode::Backspace, KeyCode::CapsLock,
+    KeyCode::ContextMenu, KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::Enter, KeyCode::SuperLeft,
+    KeyCode::SuperRight, KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::Space, KeyCode::Tab,
+    KeyCode::Convert, KeyCode::KanaMode, KeyCode::Lang1, KeyCode::Lang2, KeyCode::Lang3, KeyCode::Lang4,
+    KeyCode::Lang5, KeyCode::NonConvert, KeyCode::Delete, KeyCode::End, KeyCode::Help, KeyCode::Home,
+    KeyCode::Insert, KeyCode::PageDown, KeyCode::PageUp, KeyCode::ArrowDown, KeyCode::ArrowLeft,
+    KeyCode::ArrowRight, KeyCode::ArrowUp, KeyCode::NumLock, KeyCode::Numpad0, KeyCode::Numpad1,
+    KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4, KeyCode::Numpad5, KeyCode::Numpad6,
+    KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9, KeyCode::NumpadAdd, KeyCode::NumpadBackspace,
+    KeyCode::NumpadClear, KeyCode::NumpadClearEntry, KeyCode::NumpadComma, KeyCode::NumpadDecimal,
+    KeyCode::NumpadDivide, KeyCode::NumpadEnter, KeyCode::NumpadEqual, KeyCode::NumpadHash,
+    KeyCode::NumpadMemoryAdd, KeyCode::NumpadMemoryClear, KeyCode::NumpadMemoryRecall,
+    KeyCode::NumpadMemoryStore, KeyCode::NumpadMemorySubtract, KeyCode::NumpadMultiply,
+    KeyCode::NumpadParenLeft, KeyCode::NumpadParenRight, KeyCode::NumpadStar, KeyCode::NumpadSubtract,
+    KeyCode::Escape, KeyCode::Fn, KeyCode::FnLock, KeyCode::PrintScreen, KeyCode::ScrollLock, KeyCode::Pause,
+    KeyCode::BrowserBack, KeyCode::BrowserFavorites, KeyCode::BrowserForward, KeyCode::BrowserHome,
+    KeyCode::BrowserRefresh, KeyCode::BrowserSearch, KeyCode::BrowserStop, KeyCode::Eject,
+    KeyCode::LaunchApp1, KeyCode::LaunchApp2, KeyCode::LaunchMail, KeyCode::MediaPlayPause,
+    KeyCode::MediaSelect, KeyCode::MediaStop, KeyCode::MediaTrackNext, KeyCode::MediaTrackPrevious,
+    KeyCode::Power, KeyCode::Sleep, KeyCode::AudioVolumeDown, KeyCode::AudioVolumeMute,
+    KeyCode::AudioVolumeUp, KeyCode::WakeUp, KeyCode::Meta, KeyCode::Hyper, KeyCode::Turbo, KeyCode::Abort,
+    KeyCode::Resume, KeyCode::Suspend, KeyCode::Again, KeyCode::Copy, KeyCode::Cut, KeyCode::Find,
+    KeyCode::Open, KeyCode::Paste, KeyCode::Props, KeyCode::Select, KeyCode::Undo, KeyCode::Hiragana,
+    KeyCode::Katakana, KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12, KeyCode::F13,
+    KeyCode::F14, KeyCode::F15, KeyCode::F16, KeyCode::F17, KeyCode::F18, KeyCode::F19, KeyCode::F20,
+    KeyCode::F21, KeyCode::F22, KeyCode::F23, KeyCode::F24, KeyCode::F25, KeyCode::F26, KeyCode::F27,
+    KeyCode::F28, KeyCode::F29, KeyCode::F30, KeyCode::F31, KeyCode::F32, KeyCode::F33, KeyCode::F34,
+    KeyCode::F35
+];
+
+pub fn key_code_from_name(name: &str) -> Option<KeyCode> {
+    KEY_CODES.iter().copied().find(|key| format!("{:?}", key) == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::action::ActionMap;
+    use crate::input::event::InputEvent;
+    use winit::keyboard::{Key, NamedKey};
+
+    fn press(input: &mut Input, key: KeyCode) {
+        input.apply(&InputEvent::KeyPressed { key: Some(key), logical: Key::Named(NamedKey::Space), text: None });
+    }
+
+    #[test]
+    fn every_key_has_a_name() {
+        for key in KEY_CODES {
+            assert_eq!(key_code_from_name(&format!("{:?}", key)), Some(*key));
+        }
+        assert_eq!(key_code_from_name("NotAKey"), None);
+    }
+
+    #[test]
+    fn captured_bindings_survive_a_config_round_trip() {
+        let gamepads = Gamepads::default();
+        let mut map = ActionMap::new();
+
+        for key in [KeyCode::IntlBackslash, KeyCode::NumLock, KeyCode::PrintScreen, KeyCode::F13, KeyCode::NumpadEqual, KeyCode::KeyW] {
+            let mut input = Input::default();
+            press(&mut input, key);
+            let binding = Binding::capture(&input, &gamepads).unwrap();
+            assert_eq!(binding, Binding::Key(key));
+            map.bind_action("jump", binding);
+        }
+        map.bind_action("fire", Binding::Mouse(MouseButton::Other(7)));
+        map.bind_action("fire", Binding::GamepadButton(GamepadButton::South));
+
+        let parsed = ActionMap::parse(&map.to_config()).unwrap();
+        assert_eq!(parsed.action_bindings("jump"), map.action_bindings("jump"));
+        assert_eq!(parsed.action_bindings("fire"), map.action_bindings("fire"));
+    }
+
+    #[test]
+    fn axis_bindings_round_trip() {
+        let bindings = [
+            AxisBinding::wasd_x(),
+            AxisBinding::GamepadAxis(GamepadAxis::LeftStickX),
+            AxisBinding::MouseY,
+            AxisBinding::Scroll
+        ];
+        for binding in bindings {
+            assert_eq!(binding.to_string().parse::<AxisBinding>().unwrap(), binding);
+        }
+
+        for binding in [Axis2Binding::arrows(), Axis2Binding::GamepadStick(GamepadStick::Right), Axis2Binding::Mouse] {
+            assert_eq!(binding.to_string().parse::<Axis2Binding>().unwrap(), binding);
+        }
+    }
+
+    #[test]
+    fn malformed_bindings_are_rejected() {
+        assert!("key".parse::<Binding>().is_err());
+        assert!("key:NotAKey".parse::<Binding>().is_err());
+        assert!("joystick:A".parse::<Binding>().is_err());
+        assert!("buttons:key:KeyA".parse::<AxisBinding>().is_err());
+    }
+}
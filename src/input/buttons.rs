@@ -0,0 +1,75 @@
+use std::collections::HashSet;
+use std::hash::Hash;
+
+pub struct ButtonInput<T: Clone + Eq + Hash> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>
+}
+
+impl<T: Clone + Eq + Hash> Default for ButtonInput<T> {
+    fn default() -> Self {
+        ButtonInput {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new()
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash> ButtonInput<T> {
+    pub fn press(&mut self, button: T) {
+        if self.pressed.insert(button.clone()) {
+            self.just_pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: T) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        for button in self.pressed.drain() {
+            self.just_released.insert(button);
+        }
+    }
+
+    pub fn pressed(&self, button: &T) -> bool {
+        self.pressed.contains(button)
+    }
+
+    pub fn any_pressed(&self, buttons: &[T]) -> bool {
+        buttons.iter().any(|button| self.pressed(button))
+    }
+
+    pub fn all_pressed(&self, buttons: &[T]) -> bool {
+        buttons.iter().all(|button| self.pressed(button))
+    }
+
+    pub fn just_pressed(&self, button: &T) -> bool {
+        self.just_pressed.contains(button)
+    }
+
+    pub fn just_released(&self, button: &T) -> bool {
+        self.just_released.contains(button)
+    }
+
+    pub fn get_pressed(&self) -> impl Iterator<Item = &T> {
+        self.pressed.iter()
+    }
+
+    pub fn get_just_pressed(&self) -> impl Iterator<Item = &T> {
+        self.just_pressed.iter()
+    }
+
+    pub fn get_just_released(&self) -> impl Iterator<Item = &T> {
+        self.just_released.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
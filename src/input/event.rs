@@ -0,0 +1,91 @@
+use glm::Vec2;
+use winit::{
+    event::{DeviceEvent, ElementState, Ime, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{Key, KeyCode, ModifiersState, PhysicalKey},
+    window::WindowId
+};
+
+// The subset of winit events the input state is built from, in a form that can be recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    KeyPressed { key: Option<KeyCode>, logical: Key, text: Option<String> },
+    KeyReleased { key: Option<KeyCode>, logical: Key },
+    MousePressed(MouseButton),
+    MouseReleased(MouseButton),
+    CursorMoved { window: WindowId, position: Vec2 },
+    CursorLeft(WindowId),
+    ScrollLines(Vec2),
+    ScrollPixels(Vec2),
+    MouseMotion(Vec2),
+    Text(String),
+    Modifiers(ModifiersState),
+    ScaleFactorChanged(WindowId, f64),
+    Focused(WindowId, bool)
+}
+
+impl InputEvent {
+    pub fn from_window_event(window: WindowId, event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let key = match event.physical_key {
+                    PhysicalKey::Code(code) => Some(code),
+                    PhysicalKey::Unidentified(_) => None
+                };
+                match event.state {
+                    ElementState::Pressed => Some(InputEvent::KeyPressed {
+                        key,
+                        logical: event.logical_key.clone(),
+                        text: event.text.as_ref().map(|text| text.to_string())
+                    }),
+                    ElementState::Released => Some(InputEvent::KeyReleased {
+                        key,
+                        logical: event.logical_key.clone()
+                    })
+                }
+            },
+            WindowEvent::Ime(Ime::Commit(text)) => Some(InputEvent::Text(text.clone())),
+            WindowEvent::ModifiersChanged(modifiers) => Some(InputEvent::Modifiers(modifiers.state())),
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => Some(InputEvent::MousePressed(*button)),
+            WindowEvent::MouseInput { state: ElementState::Released, button, .. } => Some(InputEvent::MouseReleased(*button)),
+            WindowEvent::CursorMoved { position, .. } => Some(InputEvent::CursorMoved {
+                window,
+                position: Vec2::new(position.x as f32, position.y as f32)
+            }),
+            WindowEvent::CursorLeft { .. } => Some(InputEvent::CursorLeft(window)),
+            WindowEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(x, y), .. } => Some(InputEvent::ScrollLines(Vec2::new(*x, *y))),
+            WindowEvent::MouseWheel { delta: MouseScrollDelta::PixelDelta(position), .. } => {
+                Some(InputEvent::ScrollPixels(Vec2::new(position.x as f32, position.y as f32)))
+            },
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => Some(InputEvent::ScaleFactorChanged(window, *scale_factor)),
+            WindowEvent::Focused(focused) => Some(InputEvent::Focused(window, *focused)),
+            _ => None
+        }
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta: (x, y) } => Some(InputEvent::MouseMotion(Vec2::new(*x as f32, *y as f32))),
+            _ => None
+        }
+    }
+
+    pub fn window(&self) -> Option<WindowId> {
+        match self {
+            InputEvent::CursorMoved { window, .. } => Some(*window),
+            InputEvent::CursorLeft(window) => Some(*window),
+            InputEvent::ScaleFactorChanged(window, _) => Some(*window),
+            InputEvent::Focused(window, _) => Some(*window),
+            _ => None
+        }
+    }
+
+    pub fn map_window<F: Fn(WindowId) -> WindowId>(self, map: F) -> Self {
+        match self {
+            InputEvent::CursorMoved { window, position } => InputEvent::CursorMoved { window: map(window), position },
+            InputEvent::CursorLeft(window) => InputEvent::CursorLeft(map(window)),
+            InputEvent::ScaleFactorChanged(window, scale_factor) => InputEvent::ScaleFactorChanged(map(window), scale_factor),
+            InputEvent::Focused(window, focused) => InputEvent::Focused(map(window), focused),
+            event => event
+        }
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use super::backend::{GamepadBackend, GamepadEvent, Rumble};
+
+use super::buttons::ButtonInput;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight
+}
+
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 17] = [
+        GamepadButton::South, GamepadButton::East, GamepadButton::North, GamepadButton::West,
+        GamepadButton::LeftBumper, GamepadButton::RightBumper, GamepadButton::LeftTrigger, GamepadButton::RightTrigger,
+        GamepadButton::Select, GamepadButton::Start, GamepadButton::Mode,
+        GamepadButton::LeftThumb, GamepadButton::RightThumb,
+        GamepadButton::DPadUp, GamepadButton::DPadDown, GamepadButton::DPadLeft, GamepadButton::DPadRight
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|button| format!("{:?}", button) == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftStickX, GamepadAxis::LeftStickY,
+        GamepadAxis::RightStickX, GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger, GamepadAxis::RightTrigger
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|axis| format!("{:?}", axis) == name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadStick {
+    Left,
+    Right
+}
+
+impl GamepadStick {
+    pub fn axes(&self) -> (GamepadAxis, GamepadAxis) {
+        match self {
+            GamepadStick::Left => (GamepadAxis::LeftStickX, GamepadAxis::LeftStickY),
+            GamepadStick::Right => (GamepadAxis::RightStickX, GamepadAxis::RightStickY)
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Left" => Some(GamepadStick::Left),
+            "Right" => Some(GamepadStick::Right),
+            _ => None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadId(pub usize);
+
+pub struct Gamepad {
+    pub name: String,
+    pub supports_rumble: bool,
+    buttons: ButtonInput<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>
+}
+
+impl Gamepad {
+    pub fn new(name: &str) -> Self {
+        Gamepad {
+            name: name.to_string(),
+            supports_rumble: false,
+            buttons: ButtonInput::default(),
+            axes: HashMap::new()
+        }
+    }
+
+    pub fn buttons(&self) -> &ButtonInput<GamepadButton> {
+        &self.buttons
+    }
+
+    pub fn pressed(&self, button: GamepadButton) -> bool {
+        self.buttons.pressed(&button)
+    }
+
+    pub fn just_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons.just_pressed(&button)
+    }
+
+    pub fn just_released(&self, button: GamepadButton) -> bool {
+        self.buttons.just_released(&button)
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    pub fn press(&mut self, button: GamepadButton) {
+        self.buttons.press(button);
+    }
+
+    pub fn release(&mut self, button: GamepadButton) {
+        self.buttons.release(button);
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes.insert(axis, value.clamp(-1.0, 1.0));
+    }
+}
+
+#[derive(Default)]
+pub struct Gamepads {
+    pads: Vec<(GamepadId, Gamepad)>,
+    connected: Vec<GamepadId>,
+    disconnected: Vec<GamepadId>,
+    rumble_requests: Vec<(GamepadId, Rumble)>
+}
+
+impl Gamepads {
+    pub fn handle_event(&mut self, event: &GamepadEvent) {
+        match event {
+            GamepadEvent::Connected { id, name, supports_rumble } => {
+                self.pads.retain(|(pad, _)| pad != id);
+                let mut gamepad = Gamepad::new(name);
+                gamepad.supports_rumble = *supports_rumble;
+                self.pads.push((*id, gamepad));
+                self.connected.push(*id);
+            },
+            GamepadEvent::Disconnected(id) => {
+                if let Some(index) = self.pads.iter().position(|(pad, _)| pad == id) {
+                    self.pads.remove(index);
+                    self.disconnected.push(*id);
+                }
+            },
+            GamepadEvent::ButtonPressed(id, button) => {
+                if let Some(gamepad) = self.get_mut(*id) {
+                    gamepad.press(*button);
+                }
+            },
+            GamepadEvent::ButtonReleased(id, button) => {
+                if let Some(gamepad) = self.get_mut(*id) {
+                    gamepad.release(*button);
+                }
+            },
+            GamepadEvent::AxisChanged(id, axis, value) => {
+                if let Some(gamepad) = self.get_mut(*id) {
+                    gamepad.set_axis(*axis, *value);
+                }
+            }
+        }
+    }
+
+    pub fn flush_rumble(&mut self, backend: &mut dyn GamepadBackend) {
+        for (id, rumble) in self.rumble_requests.drain(..) {
+            backend.rumble(id, rumble);
+        }
+    }
+
+    pub fn just_connected(&self) -> &[GamepadId] {
+        &self.connected
+    }
+
+    pub fn just_disconnected(&self) -> &[GamepadId] {
+        &self.disconnected
+    }
+
+    pub fn rumble(&mut self, id: GamepadId, rumble: Rumble) -> bool {
+        match self.get(id) {
+            Some(gamepad) if gamepad.supports_rumble => {
+                self.rumble_requests.push((id, rumble));
+                true
+            },
+            _ => false
+        }
+    }
+
+    pub fn get(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.pads.iter().find(|(pad, _)| *pad == id).map(|(_, gamepad)| gamepad)
+    }
+
+    pub fn get_mut(&mut self, id: GamepadId) -> Option<&mut Gamepad> {
+        self.pads.iter_mut().find(|(pad, _)| *pad == id).map(|(_, gamepad)| gamepad)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.pads.iter().map(|(id, gamepad)| (*id, gamepad))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pads.is_empty()
+    }
+
+    // Bindings are not tied to one pad, any connected pad can drive them.
+    pub fn any_pressed(&self, button: GamepadButton) -> bool {
+        self.pads.iter().any(|(_, gamepad)| gamepad.pressed(button))
+    }
+
+    pub fn any_just_pressed(&self, button: GamepadButton) -> bool {
+        self.pads.iter().any(|(_, gamepad)| gamepad.just_pressed(button))
+    }
+
+    pub fn any_just_released(&self, button: GamepadButton) -> bool {
+        self.pads.iter().any(|(_, gamepad)| gamepad.just_released(button))
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.pads.iter()
+            .map(|(_, gamepad)| gamepad.axis(axis))
+            .fold(0.0, |strongest, value| if value.abs() > strongest.abs() { value } else { strongest })
+    }
+
+    pub fn end_frame(&mut self) {
+        for (_, gamepad) in self.pads.iter_mut() {
+            gamepad.buttons.clear();
+        }
+        self.connected.clear();
+        self.disconnected.clear();
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{anyhow, Result};
+use gilrs::ff::{BaseEffect, BaseEffectType, EffectBuilder, Repeat, Replay, Ticks};
+use std::collections::HashMap;
+use std::time::Instant;
+use tracing::warn;
+
+use super::backend::{GamepadBackend, GamepadEvent, Rumble};
+use super::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+
+// Real controllers through gilrs, installed by the InputPlugin.
+pub struct GilrsBackend {
+    gilrs: gilrs::Gilrs,
+    ids: HashMap<GamepadId, gilrs::GamepadId>,
+    pending: Vec<GamepadEvent>,
+    // Effects stop when their handle is dropped, so they are kept until they have run out.
+    effects: Vec<(GamepadId, gilrs::ff::Effect, Instant)>
+}
+
+impl GilrsBackend {
+    pub fn new() -> Result<Self> {
+        let gilrs = gilrs::Gilrs::new().map_err(|e| anyhow!("Failed to start gamepad support: {}", e))?;
+        let mut backend = GilrsBackend {
+            gilrs,
+            ids: HashMap::new(),
+            pending: Vec::new(),
+            effects: Vec::new()
+        };
+
+        // Pads plugged in before startup never send a connected event.
+        let connected: Vec<gilrs::GamepadId> = backend.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            let event = backend.connected(id);
+            backend.pending.push(event);
+        }
+        Ok(backend)
+    }
+
+    fn connected(&mut self, id: gilrs::GamepadId) -> GamepadEvent {
+        let gamepad = self.gilrs.gamepad(id);
+        let ours = GamepadId(usize::from(id));
+        self.ids.insert(ours, id);
+        GamepadEvent::Connected { id: ours, name: gamepad.name().to_string(), supports_rumble: gamepad.is_ff_supported() }
+    }
+
+    fn translate(&mut self, id: gilrs::GamepadId, event: gilrs::EventType) -> Option<GamepadEvent> {
+        let ours = GamepadId(usize::from(id));
+        match event {
+            gilrs::EventType::Connected => Some(self.connected(id)),
+            gilrs::EventType::Disconnected => {
+                self.ids.remove(&ours);
+                self.effects.retain(|(pad, _, _)| *pad != ours);
+                Some(GamepadEvent::Disconnected(ours))
+            },
+            gilrs::EventType::ButtonPressed(button, _) => button_from_gilrs(button).map(|button| GamepadEvent::ButtonPressed(ours, button)),
+            gilrs::EventType::ButtonReleased(button, _) => button_from_gilrs(button).map(|button| GamepadEvent::ButtonReleased(ours, button)),
+            // Analog triggers report through their button, the digital press comes separately.
+            gilrs::EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => Some(GamepadEvent::AxisChanged(ours, GamepadAxis::LeftTrigger, value)),
+            gilrs::EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => Some(GamepadEvent::AxisChanged(ours, GamepadAxis::RightTrigger, value)),
+            gilrs::EventType::AxisChanged(axis, value, _) => axis_from_gilrs(axis).map(|axis| GamepadEvent::AxisChanged(ours, axis, value)),
+            _ => None
+        }
+    }
+}
+
+impl GamepadBackend for GilrsBackend {
+    fn name(&self) -> &str {
+        "gilrs"
+    }
+
+    fn poll(&mut self, events: &mut Vec<GamepadEvent>) {
+        events.append(&mut self.pending);
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            if let Some(event) = self.translate(id, event) {
+                events.push(event);
+            }
+        }
+
+        let now = Instant::now();
+        self.effects.retain(|(_, _, end)| *end > now);
+    }
+
+    fn rumble(&mut self, id: GamepadId, rumble: Rumble) -> bool {
+        let Some(gilrs_id) = self.ids.get(&id).copied() else {
+            return false;
+        };
+        if !self.gilrs.gamepad(gilrs_id).is_ff_supported() {
+            return false;
+        }
+
+        let ticks = Ticks::from_ms(rumble.duration.as_millis().min(u32::MAX as u128) as u32);
+        let scheduling = Replay { play_for: ticks, ..Default::default() };
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: (rumble.strong * u16::MAX as f32) as u16 },
+                scheduling,
+                ..Default::default()
+            })
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Weak { magnitude: (rumble.weak * u16::MAX as f32) as u16 },
+                scheduling,
+                ..Default::default()
+            })
+            .repeat(Repeat::For(ticks))
+            .gamepads(&[gilrs_id])
+            .finish(&mut self.gilrs);
+
+        let effect = match effect.and_then(|effect| effect.play().map(|_| effect)) {
+            Ok(effect) => effect,
+            Err(e) => {
+                warn!("Cannot rumble gamepad {:?}: {}", id, e);
+                return false;
+            }
+        };
+
+        // A new rumble replaces the one still running on the same pad.
+        self.effects.retain(|(pad, _, _)| *pad != id);
+        self.effects.push((id, effect, Instant::now() + rumble.duration));
+        true
+    }
+}
+
+fn button_from_gilrs(button: gilrs::Button) -> Option<GamepadButton> {
+    match button {
+        gilrs::Button::South => Some(GamepadButton::South),
+        gilrs::Button::East => Some(GamepadButton::East),
+        gilrs::Button::North => Some(GamepadButton::North),
+        gilrs::Button::West => Some(GamepadButton::West),
+        gilrs::Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+        gilrs::Button::RightTrigger => Some(GamepadButton::RightBumper),
+        gilrs::Button::LeftTrigger2 => Some(GamepadButton::LeftTrigger),
+        gilrs::Button::RightTrigger2 => Some(GamepadButton::RightTrigger),
+        gilrs::Button::Select => Some(GamepadButton::Select),
+        gilrs::Button::Start => Some(GamepadButton::Start),
+        gilrs::Button::Mode => Some(GamepadButton::Mode),
+        gilrs::Button::LeftThumb => Some(GamepadButton::LeftThumb),
+        gilrs::Button::RightThumb => Some(GamepadButton::RightThumb),
+        gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+        gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+        gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+        gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+        _ => None
+    }
+}
+
+fn axis_from_gilrs(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    match axis {
+        gilrs::Axis::LeftStickX => Some(GamepadAxis::LeftStickX),
+        gilrs::Axis::LeftStickY => Some(GamepadAxis::LeftStickY),
+        gilrs::Axis::RightStickX => Some(GamepadAxis::RightStickX),
+        gilrs::Axis::RightStickY => Some(GamepadAxis::RightStickY),
+        _ => None
+    }
+}
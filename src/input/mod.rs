@@ -0,0 +1,24 @@
+pub mod buttons;
+pub mod state;
+pub mod event;
+pub mod gamepad;
+pub mod backend;
+pub mod binding;
+pub mod action;
+pub mod recording;
+#[cfg(feature = "gamepad")]
+pub mod gilrs_backend;
+
+pub use buttons::ButtonInput;
+pub use state::Input;
+pub use event::InputEvent;
+pub use gamepad::{Gamepad, GamepadId, GamepadButton, GamepadAxis, GamepadStick, Gamepads};
+pub use backend::{GamepadBackend, GamepadEvent, Rumble, NullGamepadBackend, VirtualGamepadBackend, VirtualGamepad};
+pub use binding::{Binding, AxisBinding, Axis2Binding};
+pub use action::{ActionMap, Actions, AxisSettings};
+pub use recording::{InputRecording, InputRecorder, InputReplay, RecordedEvent, RecordedFrame};
+#[cfg(feature = "gamepad")]
+pub use gilrs_backend::GilrsBackend;
+
+pub use winit::event::MouseButton;
+pub use winit::keyboard::{Key, KeyCode, ModifiersState, NamedKey};
@@ -0,0 +1,445 @@
+use anyhow::{anyhow, bail, Context, Result};
+use glm::Vec2;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::Duration;
+use winit::{
+    event::MouseButton,
+    keyboard::{Key, ModifiersState, NamedKey, NativeKey},
+    window::WindowId
+};
+
+use super::backend::GamepadEvent;
+use super::binding::key_code_from_name;
+use super::event::InputEvent;
+use super::gamepad::{GamepadAxis, GamepadButton, GamepadId};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordedEvent {
+    Input(InputEvent),
+    Gamepad(GamepadEvent)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordedFrame {
+    pub delta: Duration,
+    pub events: Vec<RecordedEvent>
+}
+
+// Window ids are not stable between runs, so recorded events refer to windows by
+// their index in `Windows` wrapped in a WindowId.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputRecording {
+    pub frames: Vec<RecordedFrame>
+}
+
+impl InputRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input recording from {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse input recording in {}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("Failed to write input recording to {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut recording = InputRecording::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let parsed = if tokens[0] == "frame" {
+                parse_number::<u64>(&tokens, 1).map(|nanos| {
+                    recording.frames.push(RecordedFrame {
+                        delta: Duration::from_nanos(nanos),
+                        events: Vec::new()
+                    });
+                })
+            } else {
+                parse_event(&tokens).and_then(|event| {
+                    let frame = recording.frames.last_mut().ok_or_else(|| anyhow!("Event before the first frame"))?;
+                    frame.events.push(event);
+                    Ok(())
+                })
+            };
+            parsed.with_context(|| format!("Line {}: {}", number + 1, line))?;
+        }
+
+        Ok(recording)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("# Fuji input recording\n");
+
+        for frame in self.frames.iter() {
+            let _ = writeln!(out, "frame {}", frame.delta.as_nanos());
+            for event in frame.events.iter() {
+                let _ = writeln!(out, "{}", format_event(event));
+            }
+        }
+
+        out
+    }
+}
+
+#[derive(Default)]
+pub struct InputRecorder {
+    recording: InputRecording,
+    pending: Vec<RecordedEvent>
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: RecordedEvent) {
+        self.pending.push(event);
+    }
+
+    // Everything recorded since the previous frame was seen by the frame that took `delta`.
+    pub fn end_frame(&mut self, delta: Duration) {
+        self.recording.frames.push(RecordedFrame {
+            delta,
+            events: std::mem::take(&mut self.pending)
+        });
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    pub fn finish(self) -> InputRecording {
+        self.recording
+    }
+}
+
+pub struct InputReplay {
+    recording: InputRecording,
+    frame: usize,
+    exit_when_finished: bool
+}
+
+impl InputReplay {
+    pub fn new(recording: InputRecording) -> Self {
+        InputReplay {
+            recording,
+            frame: 0,
+            exit_when_finished: false
+        }
+    }
+
+    pub fn with_exit_when_finished(mut self, exit_when_finished: bool) -> Self {
+        self.exit_when_finished = exit_when_finished;
+        self
+    }
+
+    pub fn exit_when_finished(&self) -> bool {
+        self.exit_when_finished
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.recording.frames.len()
+    }
+
+    pub fn next_frame(&mut self) -> Option<&RecordedFrame> {
+        let frame = self.recording.frames.get(self.frame)?;
+        self.frame += 1;
+        Some(frame)
+    }
+}
+
+fn window_index(window: WindowId) -> u64 {
+    u64::from(window)
+}
+
+fn escape(text: &str) -> String {
+    if text.is_empty() {
+        return "\\0".to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ' ' => out.push_str("\\s"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c)
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> Result<String> {
+    if text == "\\0" {
+        return Ok(String::new());
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            other => bail!("Invalid escape \\{:?} in {}", other, text)
+        }
+    }
+    Ok(out)
+}
+
+fn format_mouse_button(button: MouseButton) -> String {
+    match button {
+        MouseButton::Other(index) => index.to_string(),
+        button => format!("{:?}", button)
+    }
+}
+
+fn parse_mouse_button(name: &str) -> Result<MouseButton> {
+    match name {
+        "Left" => Ok(MouseButton::Left),
+        "Right" => Ok(MouseButton::Right),
+        "Middle" => Ok(MouseButton::Middle),
+        "Back" => Ok(MouseButton::Back),
+        "Forward" => Ok(MouseButton::Forward),
+        _ => name.parse().map(MouseButton::Other).with_context(|| format!("Unknown mouse button {}", name))
+    }
+}
+
+// Named keys that survive a round trip, anything else replays as unidentified.
+const NAMED_KEYS: &[NamedKey] = &[
+    NamedKey::Enter, NamedKey::Tab, NamedKey::Space, NamedKey::Escape, NamedKey::Backspace, NamedKey::Delete,
+    NamedKey::Insert, NamedKey::Home, NamedKey::End, NamedKey::PageUp, NamedKey::PageDown,
+    NamedKey::ArrowUp, NamedKey::ArrowDown, NamedKey::ArrowLeft, NamedKey::ArrowRight,
+    NamedKey::Shift, NamedKey::Control, NamedKey::Alt, NamedKey::Super, NamedKey::CapsLock,
+    NamedKey::F1, NamedKey::F2, NamedKey::F3, NamedKey::F4, NamedKey::F5, NamedKey::F6,
+    NamedKey::F7, NamedKey::F8, NamedKey::F9, NamedKey::F10, NamedKey::F11, NamedKey::F12
+];
+
+fn format_logical_key(key: &Key) -> String {
+    match key {
+        Key::Character(text) => format!("char:{}", escape(text)),
+        Key::Named(named) => format!("named:{:?}", named),
+        _ => "unidentified".to_string()
+    }
+}
+
+fn parse_logical_key(text: &str) -> Result<Key> {
+    if let Some(character) = text.strip_prefix("char:") {
+        return Ok(Key::Character(unescape(character)?.into()));
+    }
+    if let Some(name) = text.strip_prefix("named:") {
+        return Ok(NAMED_KEYS.iter()
+            .find(|named| format!("{:?}", named) == name)
+            .map(|named| Key::Named(*named))
+            .unwrap_or(Key::Unidentified(NativeKey::Unidentified)));
+    }
+    Ok(Key::Unidentified(NativeKey::Unidentified))
+}
+
+fn format_event(event: &RecordedEvent) -> String {
+    match event {
+        RecordedEvent::Input(event) => match event {
+            InputEvent::KeyPressed { key, logical, text } => format!("key_down {} {} {}",
+                key.map(|key| format!("{:?}", key)).unwrap_or("-".to_string()),
+                format_logical_key(logical),
+                text.as_ref().map(|text| escape(text)).unwrap_or("-".to_string())),
+            InputEvent::KeyReleased { key, logical } => format!("key_up {} {}",
+                key.map(|key| format!("{:?}", key)).unwrap_or("-".to_string()),
+                format_logical_key(logical)),
+            InputEvent::MousePressed(button) => format!("mouse_down {}", format_mouse_button(*button)),
+            InputEvent::MouseReleased(button) => format!("mouse_up {}", format_mouse_button(*button)),
+            InputEvent::CursorMoved { window, position } => format!("cursor {} {} {}", window_index(*window), position.x, position.y),
+            InputEvent::CursorLeft(window) => format!("cursor_left {}", window_index(*window)),
+            InputEvent::ScrollLines(delta) => format!("scroll_lines {} {}", delta.x, delta.y),
+            InputEvent::ScrollPixels(delta) => format!("scroll_pixels {} {}", delta.x, delta.y),
+            InputEvent::MouseMotion(delta) => format!("motion {} {}", delta.x, delta.y),
+            InputEvent::Text(text) => format!("text {}", escape(text)),
+            InputEvent::Modifiers(modifiers) => format!("modifiers {}", modifiers.bits()),
+            InputEvent::ScaleFactorChanged(window, scale_factor) => format!("scale {} {}", window_index(*window), scale_factor),
+            InputEvent::Focused(window, focused) => format!("focus {} {}", window_index(*window), focused)
+        },
+        RecordedEvent::Gamepad(event) => match event {
+            GamepadEvent::Connected { id, name, supports_rumble } => format!("pad_connect {} {} {}", id.0, supports_rumble, escape(name)),
+            GamepadEvent::Disconnected(id) => format!("pad_disconnect {}", id.0),
+            GamepadEvent::ButtonPressed(id, button) => format!("pad_down {} {:?}", id.0, button),
+            GamepadEvent::ButtonReleased(id, button) => format!("pad_up {} {:?}", id.0, button),
+            GamepadEvent::AxisChanged(id, axis, value) => format!("pad_axis {} {:?} {}", id.0, axis, value)
+        }
+    }
+}
+
+fn token<'a>(tokens: &[&'a str], index: usize) -> Result<&'a str> {
+    tokens.get(index).copied().ok_or_else(|| anyhow!("Missing field {}", index))
+}
+
+fn parse_number<T: std::str::FromStr>(tokens: &[&str], index: usize) -> Result<T> {
+    let text = token(tokens, index)?;
+    text.parse().map_err(|_| anyhow!("Invalid number {}", text))
+}
+
+fn parse_vec2(tokens: &[&str], index: usize) -> Result<Vec2> {
+    Ok(Vec2::new(parse_number(tokens, index)?, parse_number(tokens, index + 1)?))
+}
+
+fn parse_window(tokens: &[&str], index: usize) -> Result<WindowId> {
+    Ok(WindowId::from(parse_number::<u64>(tokens, index)?))
+}
+
+fn parse_key(text: &str) -> Result<Option<winit::keyboard::KeyCode>> {
+    if text == "-" {
+        return Ok(None);
+    }
+    key_code_from_name(text).map(Some).ok_or_else(|| anyhow!("Unknown key {}", text))
+}
+
+fn parse_gamepad_button(text: &str) -> Result<GamepadButton> {
+    GamepadButton::from_name(text).ok_or_else(|| anyhow!("Unknown gamepad button {}", text))
+}
+
+fn parse_event(tokens: &[&str]) -> Result<RecordedEvent> {
+    let input = |event| Ok(RecordedEvent::Input(event));
+    let gamepad = |event| Ok(RecordedEvent::Gamepad(event));
+
+    match tokens[0] {
+        "key_down" => input(InputEvent::KeyPressed {
+            key: parse_key(token(tokens, 1)?)?,
+            logical: parse_logical_key(token(tokens, 2)?)?,
+            text: match token(tokens, 3)? {
+                "-" => None,
+                text => Some(unescape(text)?)
+            }
+        }),
+        "key_up" => input(InputEvent::KeyReleased {
+            key: parse_key(token(tokens, 1)?)?,
+            logical: parse_logical_key(token(tokens, 2)?)?
+        }),
+        "mouse_down" => input(InputEvent::MousePressed(parse_mouse_button(token(tokens, 1)?)?)),
+        "mouse_up" => input(InputEvent::MouseReleased(parse_mouse_button(token(tokens, 1)?)?)),
+        "cursor" => input(InputEvent::CursorMoved { window: parse_window(tokens, 1)?, position: parse_vec2(tokens, 2)? }),
+        "cursor_left" => input(InputEvent::CursorLeft(parse_window(tokens, 1)?)),
+        "scroll_lines" => input(InputEvent::ScrollLines(parse_vec2(tokens, 1)?)),
+        "scroll_pixels" => input(InputEvent::ScrollPixels(parse_vec2(tokens, 1)?)),
+        "motion" => input(InputEvent::MouseMotion(parse_vec2(tokens, 1)?)),
+        "text" => input(InputEvent::Text(unescape(token(tokens, 1)?)?)),
+        "modifiers" => input(InputEvent::Modifiers(ModifiersState::from_bits_truncate(parse_number(tokens, 1)?))),
+        "scale" => input(InputEvent::ScaleFactorChanged(parse_window(tokens, 1)?, parse_number(tokens, 2)?)),
+        "focus" => input(InputEvent::Focused(parse_window(tokens, 1)?, parse_number(tokens, 2)?)),
+        "pad_connect" => gamepad(GamepadEvent::Connected {
+            id: GamepadId(parse_number(tokens, 1)?),
+            supports_rumble: parse_number(tokens, 2)?,
+            name: unescape(token(tokens, 3)?)?
+        }),
+        "pad_disconnect" => gamepad(GamepadEvent::Disconnected(GamepadId(parse_number(tokens, 1)?))),
+        "pad_down" => gamepad(GamepadEvent::ButtonPressed(GamepadId(parse_number(tokens, 1)?), parse_gamepad_button(token(tokens, 2)?)?)),
+        "pad_up" => gamepad(GamepadEvent::ButtonReleased(GamepadId(parse_number(tokens, 1)?), parse_gamepad_button(token(tokens, 2)?)?)),
+        "pad_axis" => {
+            let name = token(tokens, 2)?;
+            let axis = GamepadAxis::from_name(name).ok_or_else(|| anyhow!("Unknown gamepad axis {}", name))?;
+            gamepad(GamepadEvent::AxisChanged(GamepadId(parse_number(tokens, 1)?), axis, parse_number(tokens, 3)?))
+        },
+        kind => bail!("Unknown event {}", kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::keyboard::KeyCode;
+
+    fn window(index: u64) -> WindowId {
+        WindowId::from(index)
+    }
+
+    #[test]
+    fn every_event_survives_a_text_round_trip() {
+        let input = |event| RecordedEvent::Input(event);
+        let gamepad = |event| RecordedEvent::Gamepad(event);
+        let recording = InputRecording {
+            frames: vec![
+                RecordedFrame {
+                    delta: Duration::from_nanos(16_666_667),
+                    events: vec![
+                        input(InputEvent::KeyPressed { key: Some(KeyCode::KeyA), logical: Key::Character("a".into()), text: Some("a".to_string()) }),
+                        input(InputEvent::KeyPressed { key: Some(KeyCode::Space), logical: Key::Named(NamedKey::Space), text: Some(" ".to_string()) }),
+                        input(InputEvent::KeyPressed { key: None, logical: Key::Unidentified(NativeKey::Unidentified), text: None }),
+                        input(InputEvent::KeyReleased { key: Some(KeyCode::IntlBackslash), logical: Key::Character("\\".into()) }),
+                        input(InputEvent::KeyReleased { key: Some(KeyCode::F12), logical: Key::Named(NamedKey::F12) }),
+                        input(InputEvent::MousePressed(MouseButton::Left)),
+                        input(InputEvent::MouseReleased(MouseButton::Other(7))),
+                        input(InputEvent::CursorMoved { window: window(1), position: Vec2::new(12.5, -3.25) }),
+                        input(InputEvent::CursorLeft(window(0))),
+                        input(InputEvent::ScrollLines(Vec2::new(0.0, -1.0))),
+                        input(InputEvent::ScrollPixels(Vec2::new(4.75, 0.1))),
+                        input(InputEvent::MouseMotion(Vec2::new(-0.5, 2.0)))
+                    ]
+                },
+                RecordedFrame {
+                    delta: Duration::ZERO,
+                    events: Vec::new()
+                },
+                RecordedFrame {
+                    delta: Duration::from_millis(33),
+                    events: vec![
+                        input(InputEvent::Text("a b\\c\n\r\td".to_string())),
+                        input(InputEvent::Text(String::new())),
+                        input(InputEvent::Text("\\s".to_string())),
+                        input(InputEvent::Modifiers(ModifiersState::SHIFT | ModifiersState::CONTROL)),
+                        input(InputEvent::ScaleFactorChanged(window(2), 1.25)),
+                        input(InputEvent::Focused(window(0), false)),
+                        gamepad(GamepadEvent::Connected { id: GamepadId(3), name: "Pad with spaces\\".to_string(), supports_rumble: true }),
+                        gamepad(GamepadEvent::ButtonPressed(GamepadId(3), GamepadButton::DPadLeft)),
+                        gamepad(GamepadEvent::ButtonReleased(GamepadId(3), GamepadButton::South)),
+                        gamepad(GamepadEvent::AxisChanged(GamepadId(3), GamepadAxis::RightTrigger, -0.375)),
+                        gamepad(GamepadEvent::Disconnected(GamepadId(3)))
+                    ]
+                }
+            ]
+        };
+
+        let text = recording.to_text();
+        assert_eq!(InputRecording::parse(&text).unwrap(), recording);
+    }
+
+    #[test]
+    fn malformed_recordings_are_rejected() {
+        assert!(InputRecording::parse("key_down KeyA unidentified -").is_err());
+        assert!(InputRecording::parse("frame 10\nkey_down NotAKey unidentified -").is_err());
+        assert!(InputRecording::parse("frame 10\ntext bad\\q").is_err());
+        let error = InputRecording::parse("frame 10\n\nbogus 1").err().unwrap();
+        assert!(format!("{:#}", error).contains("Line 3"));
+    }
+}
@@ -0,0 +1,311 @@
+use glm::Vec2;
+use std::collections::HashMap;
+use winit::{
+    event::{DeviceEvent, MouseButton, WindowEvent},
+    keyboard::{Key, KeyCode, ModifiersState},
+    window::WindowId
+};
+
+use super::buttons::ButtonInput;
+use super::event::InputEvent;
+
+pub struct Input {
+    keys: ButtonInput<KeyCode>,
+    logical_keys: ButtonInput<Key>,
+    mouse_buttons: ButtonInput<MouseButton>,
+    modifiers: ModifiersState,
+    cursor_window: Option<WindowId>,
+    cursor_position: Option<Vec2>,
+    scale_factors: HashMap<WindowId, f64>,
+    mouse_delta: Vec2,
+    scroll_lines: Vec2,
+    scroll_pixels: Vec2,
+    text: String,
+    focused: Option<WindowId>
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            keys: ButtonInput::default(),
+            logical_keys: ButtonInput::default(),
+            mouse_buttons: ButtonInput::default(),
+            modifiers: ModifiersState::empty(),
+            cursor_window: None,
+            cursor_position: None,
+            scale_factors: HashMap::new(),
+            mouse_delta: Vec2::new(0.0, 0.0),
+            scroll_lines: Vec2::new(0.0, 0.0),
+            scroll_pixels: Vec2::new(0.0, 0.0),
+            text: String::new(),
+            focused: None
+        }
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn keys(&self) -> &ButtonInput<KeyCode> {
+        &self.keys
+    }
+
+    pub fn logical_keys(&self) -> &ButtonInput<Key> {
+        &self.logical_keys
+    }
+
+    pub fn mouse_buttons(&self) -> &ButtonInput<MouseButton> {
+        &self.mouse_buttons
+    }
+
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        self.keys.pressed(&key)
+    }
+
+    pub fn key_just_pressed(&self, key: KeyCode) -> bool {
+        self.keys.just_pressed(&key)
+    }
+
+    pub fn key_just_released(&self, key: KeyCode) -> bool {
+        self.keys.just_released(&key)
+    }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed(&button)
+    }
+
+    pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_pressed(&button)
+    }
+
+    pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_released(&button)
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    pub fn cursor_window(&self) -> Option<WindowId> {
+        self.cursor_window
+    }
+
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor_position
+    }
+
+    pub fn cursor_position_logical(&self) -> Option<Vec2> {
+        let position = self.cursor_position?;
+        let scale_factor = self.scale_factor(self.cursor_window?) as f32;
+        Some(Vec2::new(position.x / scale_factor, position.y / scale_factor))
+    }
+
+    pub fn scale_factor(&self, window: WindowId) -> f64 {
+        self.scale_factors.get(&window).copied().unwrap_or(1.0)
+    }
+
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    pub fn scroll_lines(&self) -> Vec2 {
+        self.scroll_lines
+    }
+
+    pub fn scroll_pixels(&self) -> Vec2 {
+        self.scroll_pixels
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn focused(&self) -> Option<WindowId> {
+        self.focused
+    }
+
+    pub fn set_scale_factor(&mut self, window: WindowId, scale_factor: f64) {
+        self.scale_factors.insert(window, scale_factor);
+    }
+
+    pub fn remove_window(&mut self, window: WindowId) {
+        self.scale_factors.remove(&window);
+        if self.cursor_window == Some(window) {
+            self.cursor_window = None;
+            self.cursor_position = None;
+        }
+        if self.focused == Some(window) {
+            self.focused = None;
+        }
+    }
+
+    pub fn handle_window_event(&mut self, window: WindowId, event: &WindowEvent) {
+        if let Some(event) = InputEvent::from_window_event(window, event) {
+            self.apply(&event);
+        }
+    }
+
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let Some(event) = InputEvent::from_device_event(event) {
+            self.apply(&event);
+        }
+    }
+
+    pub fn apply(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::KeyPressed { key, logical, text } => {
+                if let Some(key) = key {
+                    self.keys.press(*key);
+                }
+                self.logical_keys.press(logical.clone());
+
+                if let Some(text) = text {
+                    self.text.extend(text.chars().filter(|c| !c.is_control()));
+                }
+            },
+
+            InputEvent::KeyReleased { key, logical } => {
+                if let Some(key) = key {
+                    self.keys.release(*key);
+                }
+                self.logical_keys.release(logical.clone());
+            },
+
+            InputEvent::Text(text) => {
+                self.text.push_str(text);
+            },
+
+            InputEvent::Modifiers(modifiers) => {
+                self.modifiers = *modifiers;
+            },
+
+            InputEvent::MousePressed(button) => self.mouse_buttons.press(*button),
+
+            InputEvent::MouseReleased(button) => self.mouse_buttons.release(*button),
+
+            InputEvent::CursorMoved { window, position } => {
+                self.cursor_window = Some(*window);
+                self.cursor_position = Some(*position);
+            },
+
+            InputEvent::CursorLeft(window) if self.cursor_window == Some(*window) => {
+                self.cursor_window = None;
+                self.cursor_position = None;
+            },
+
+            InputEvent::CursorLeft(_) => (),
+
+            InputEvent::ScrollLines(delta) => {
+                self.scroll_lines = self.scroll_lines + *delta;
+            },
+
+            InputEvent::ScrollPixels(delta) => {
+                self.scroll_pixels = self.scroll_pixels + *delta;
+            },
+
+            InputEvent::MouseMotion(delta) => {
+                self.mouse_delta = self.mouse_delta + *delta;
+            },
+
+            InputEvent::ScaleFactorChanged(window, scale_factor) => {
+                self.set_scale_factor(*window, *scale_factor);
+            },
+
+            InputEvent::Focused(window, focused) => {
+                if *focused {
+                    self.focused = Some(*window);
+                } else if self.focused == Some(*window) {
+                    // Releases never arrive for keys held while focus moves away.
+                    self.focused = None;
+                    self.keys.release_all();
+                    self.logical_keys.release_all();
+                    self.mouse_buttons.release_all();
+                }
+            }
+        }
+    }
+
+    // Called once the frame has seen the input, resets everything that is per frame.
+    pub fn end_frame(&mut self) {
+        self.keys.clear();
+        self.logical_keys.clear();
+        self.mouse_buttons.clear();
+        self.mouse_delta = Vec2::new(0.0, 0.0);
+        self.scroll_lines = Vec2::new(0.0, 0.0);
+        self.scroll_pixels = Vec2::new(0.0, 0.0);
+        self.text.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: KeyCode) -> InputEvent {
+        InputEvent::KeyPressed { key: Some(key), logical: Key::Character("a".into()), text: Some("a".to_string()) }
+    }
+
+    fn release(key: KeyCode) -> InputEvent {
+        InputEvent::KeyReleased { key: Some(key), logical: Key::Character("a".into()) }
+    }
+
+    #[test]
+    fn key_presses_last_until_released() {
+        let mut input = Input::new();
+        input.apply(&press(KeyCode::KeyA));
+        assert!(input.key_pressed(KeyCode::KeyA));
+        assert!(input.key_just_pressed(KeyCode::KeyA));
+        assert!(!input.key_just_released(KeyCode::KeyA));
+        assert_eq!(input.text(), "a");
+
+        // The next frame still holds the key but it is no longer new.
+        input.end_frame();
+        assert!(input.key_pressed(KeyCode::KeyA));
+        assert!(!input.key_just_pressed(KeyCode::KeyA));
+        assert_eq!(input.text(), "");
+
+        input.apply(&release(KeyCode::KeyA));
+        assert!(!input.key_pressed(KeyCode::KeyA));
+        assert!(input.key_just_released(KeyCode::KeyA));
+        input.end_frame();
+        assert!(!input.key_just_released(KeyCode::KeyA));
+    }
+
+    #[test]
+    fn mouse_buttons_and_deltas_reset_each_frame() {
+        let mut input = Input::new();
+        input.apply(&InputEvent::MousePressed(MouseButton::Left));
+        input.apply(&InputEvent::MouseMotion(Vec2::new(1.0, 2.0)));
+        input.apply(&InputEvent::MouseMotion(Vec2::new(0.5, -1.0)));
+        input.apply(&InputEvent::ScrollLines(Vec2::new(0.0, 1.0)));
+        assert!(input.mouse_just_pressed(MouseButton::Left));
+        assert_eq!(input.mouse_delta(), Vec2::new(1.5, 1.0));
+        assert_eq!(input.scroll_lines(), Vec2::new(0.0, 1.0));
+
+        input.end_frame();
+        assert!(input.mouse_pressed(MouseButton::Left));
+        assert!(!input.mouse_just_pressed(MouseButton::Left));
+        assert_eq!(input.mouse_delta(), Vec2::new(0.0, 0.0));
+        assert_eq!(input.scroll_lines(), Vec2::new(0.0, 0.0));
+
+        input.apply(&InputEvent::MouseReleased(MouseButton::Left));
+        assert!(input.mouse_just_released(MouseButton::Left));
+        assert!(!input.mouse_pressed(MouseButton::Left));
+    }
+
+    #[test]
+    fn losing_focus_releases_held_keys() {
+        let window = WindowId::from(0);
+        let mut input = Input::new();
+        input.apply(&InputEvent::Focused(window, true));
+        input.apply(&press(KeyCode::ShiftLeft));
+        input.end_frame();
+
+        input.apply(&InputEvent::Focused(window, false));
+        assert_eq!(input.focused(), None);
+        assert!(!input.key_pressed(KeyCode::ShiftLeft));
+        assert!(input.key_just_released(KeyCode::ShiftLeft));
+    }
+}
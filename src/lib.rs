@@ -1,9 +1,29 @@
 mod renderer_backend;
+pub mod scene;
+pub mod ecs;
+pub mod app;
+pub mod input;
+#[cfg(feature = "audio")]
+pub mod audio;
 
 use renderer_backend::*;
+use scene::{SceneGraph, Transform, PreviousTransform, Renderable, MeshHandle, MaterialHandle, RenderTargetHandle, Camera, Viewport, Light, AmbientLight};
+use ecs::{World, Schedule, System};
+use input::{Input, InputEvent, Gamepads, Actions, GamepadBackend, InputRecorder, InputReplay, RecordedEvent};
+use app::{WindowConfig, RendererConfig, AppBuilder, DefaultPlugins, FullscreenMode, VsyncMode, WindowControl, WindowRequest, Windows, Time, LoopConfig, LoopMode, FrameLimiter};
+
+pub use renderer_backend::mesh_builder::{Vertex, LitVertex};
+pub use renderer_backend::materials::{PbrMaterialDesc, PhongMaterialDesc};
+pub use renderer_backend::morph::{MorphTarget, MorphAnimation, MorphKeyframe};
+pub use renderer_backend::instancing::InstanceData;
+pub use renderer_backend::render_graph::{RenderGraph, RenderNode, FrameTargets, PassBuilder, PassDesc, PassResources, ResourceId, TextureDesc, TexturePool};
+pub use renderer_backend::texture::RenderTarget;
+pub use renderer_backend::shadow::ShadowSettings;
+pub use renderer_backend::environment::{Environment, EnvironmentMap};
+pub use renderer_backend::post_process::{PostProcess, CustomEffect, ColorLut, Bloom, Pixelate, ChromaticAberration, Tonemap, Tonemapper, ColorGrading, Fxaa, Vignette, FilmGrain, Gamma};
 
 use anyhow::{Context, Result};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use wgpu::util::DeviceExt;
 use winit::{
     event::*,
@@ -18,208 +38,1596 @@ use winit::{
         WindowAttributes
     },
     application::ApplicationHandler,
-    dpi::PhysicalSize
+    dpi::{LogicalSize, PhysicalSize}
 };
 use tokio::runtime::Runtime;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::PathBuf;
+
+// The scene is drawn into windows and render targets, one set per color format they use.
+struct ScenePipelines {
+    instanced: wgpu::RenderPipeline,
+    morph: wgpu::RenderPipeline,
+    phong: wgpu::RenderPipeline,
+    pbr: wgpu::RenderPipeline,
+    skybox: wgpu::RenderPipeline
+}
+
+// Recreated together with the device, the pipelines and everything bound to them.
+struct BindGroupLayouts {
+    material: wgpu::BindGroupLayout,
+    camera: wgpu::BindGroupLayout,
+    morph: wgpu::BindGroupLayout,
+    phong: wgpu::BindGroupLayout,
+    pbr: wgpu::BindGroupLayout,
+    lights: wgpu::BindGroupLayout,
+    view_lighting: wgpu::BindGroupLayout,
+    shadow_layer: wgpu::BindGroupLayout,
+    clusters: wgpu::BindGroupLayout,
+    skybox: wgpu::BindGroupLayout
+}
+
+impl BindGroupLayouts {
+    fn for_shading(&self, shading: materials::Shading) -> &wgpu::BindGroupLayout {
+        match shading {
+            materials::Shading::Unlit => &self.material,
+            materials::Shading::BlinnPhong => &self.phong,
+            materials::Shading::Pbr => &self.pbr
+        }
+    }
+}
 
 pub struct GraphicState<'lifetime_1> {
-    surface: wgpu::Surface<'lifetime_1>,
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    triangle_mesh: wgpu::Buffer,
-    quad_mesh: mesh_builder::Mesh,
-    triangle_material: materials::Material,
-    quad_material: materials::Material
+    power_preference: wgpu::PowerPreference,
+    device_lost: Arc<AtomicBool>,
+    surface_format: wgpu::TextureFormat,
+    scene_format: wgpu::TextureFormat,
+    hdr: bool,
+    surfaces: HashMap<WindowId, window_surface::WindowSurface<'lifetime_1>>,
+    meshes: Vec<mesh_builder::Mesh>,
+    materials: Vec<materials::Material>,
+    scene: SceneGraph,
+    scene_instances: instancing::InstanceBuffer,
+    scene_draws: Vec<(MeshHandle, MaterialHandle, Range<u32>)>,
+    layouts: BindGroupLayouts,
+    environment: environment::GpuEnvironment,
+    lights: lighting::LightBuffer,
+    shadow_casters: shadow::ShadowCasters,
+    shadow_pipelines: shadow::ShadowPipelines,
+    // None where compute shaders are missing, the lights are binned on the CPU there.
+    cluster_pipeline: Option<wgpu::ComputePipeline>,
+    scene_pipelines: HashMap<wgpu::TextureFormat, ScenePipelines>,
+    morph_meshes: Vec<(morph::MorphMesh, MaterialHandle)>,
+    upscale_pipeline: wgpu::RenderPipeline,
+    render_targets: Vec<camera::TargetCamera>,
+    instanced_batches: Vec<instancing::InstancedBatch>,
+    texture_pool: render_graph::TexturePool,
+    render_nodes: Vec<Box<dyn RenderNode>>,
+    post_process: post_process::PostProcessStack,
+    clear_color: wgpu::Color,
+    // Animation time for worlds without a Time resource.
+    start_time: Instant
 }
 
 impl<'lifetime_1> GraphicState<'lifetime_1> {
-    pub fn new(window: Arc<Window>) -> GraphicState<'lifetime_1> {
+    pub fn new(window: Arc<Window>, renderer_config: &RendererConfig, window_config: &WindowConfig) -> GraphicState<'lifetime_1> {
     let runtime = Runtime::new().unwrap();
     runtime.block_on(async {
 
-        let size = Arc::clone(&window).inner_size();
-
         let instance_descriptor = wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(), ..Default::default()
+            backends: renderer_config.backends, ..Default::default()
         };
         let instance = wgpu::Instance::new(instance_descriptor);
 
         let surface = instance.create_surface(Arc::clone(&window)).expect("Failed to create surface in wgpu");
 
-        let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false
-        };
-        let adapter = instance.request_adapter(&adapter_descriptor).await.unwrap();
+        let (adapter, device, queue) = request_device(&instance, renderer_config.power_preference, Some(&surface))
+            .await
+            .expect("Failed to create a graphics device");
 
-        let device_descriptor = wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
-            label: Some("Device"),
-            memory_hints: wgpu::MemoryHints::default()
-        };
-        let (device, queue) = adapter
-                .request_device(&device_descriptor, None)
-                .await
-                .unwrap();
-
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_format = surface_capabilities
-            .formats
-            .iter()
-            .copied()
-            .filter(|f| f.is_srgb())
-            .next()
-            .unwrap_or(surface_capabilities.formats[0]);
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_capabilities.present_modes[0],
-            alpha_mode: surface_capabilities.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2
-        };
-        surface.configure(&device, &config);
+        let device_lost = Arc::new(AtomicBool::new(false));
+        watch_device_loss(&device, &device_lost);
+
+        let surface_format = window_surface::preferred_format(&surface.get_capabilities(&adapter));
 
         let triangle_mesh = mesh_builder::make_triangle(&device);
         let quad_mesh = mesh_builder::make_quad(&device);
 
-        let material_bind_group_layout: wgpu::BindGroupLayout;
-        {
-            let mut builder = bind_group_layout::Builder::new(&device);
-            builder.add_material();
-            material_bind_group_layout = builder.build("Material bind group layout");
-        }
+        let layouts = create_bind_group_layouts(&device);
+        let mut scene_pipelines = HashMap::new();
+        let scene_format = if renderer_config.hdr { texture::HDR_FORMAT } else { surface_format };
+        scene_pipelines.insert(scene_format, create_scene_pipelines(&device, scene_format, &layouts));
+        let upscale_pipeline = create_upscale_pipeline(&device, surface_format, &layouts.material);
+        let environment = environment::GpuEnvironment::new(&device, &queue, &layouts.skybox);
+        let lights = lighting::LightBuffer::new(&device, &layouts.lights, &environment, 64);
+        let shadow_casters = shadow::ShadowCasters::default();
+        let shadow_pipelines = shadow::ShadowPipelines::new(&device, &layouts.shadow_layer,
+            shadow_casters.settings().depth_bias, shadow_casters.settings().slope_bias);
+        let cluster_pipeline = create_cluster_pipeline(&adapter, &device, &layouts);
 
-        let render_pipeline: wgpu::RenderPipeline;
-        {
-            let mut builder = pipeline::Builder::new(&device);
-            builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
-            builder.set_shader_module("shader.wgsl", "vs_main", "fs_main");
-            builder.set_pixel_format(config.format);
-            builder.add_bind_group_layout(&material_bind_group_layout);
-            render_pipeline = builder.build_pipeline("Render pipelne");
-        }
+        let quad_material = materials::Material::new("gambar.png", &device, &queue, &layouts.material)
+            .expect("Failed to load the default material");
+        let triangle_material = materials::Material::new("gambar.png", &device, &queue, &layouts.material)
+            .expect("Failed to load the default material");
+
+        let meshes = vec![quad_mesh, triangle_mesh];
+        let materials = vec![quad_material, triangle_material];
 
-        let quad_material = materials::Material::new("gambar.png", &device, &queue, &material_bind_group_layout);
-        let triangle_material = materials::Material::new("gambar.png", &device, &queue, &material_bind_group_layout);
+        let mut scene = SceneGraph::new();
+        let root = scene.root();
+        scene.add_renderable(root, "Quad", Transform::default(), Renderable::new(MeshHandle(0), MaterialHandle(0)));
+        scene.add_renderable(root, "Triangle", Transform::default(), Renderable::new(MeshHandle(1), MaterialHandle(1)));
+
+        let scene_instances = instancing::InstanceBuffer::new(&device, 64);
+
+        let primary = window_surface::WindowSurface::new(&window, surface, &adapter, &device,
+            surface_format, window_config, &layouts.camera)
+            .expect("Failed to configure the window surface");
+        let mut surfaces = HashMap::new();
+        surfaces.insert(window.id(), primary);
 
         Self {
-            surface,
+            instance,
+            adapter,
             device,
             queue,
-            config,
-            size,
-            render_pipeline,
-            triangle_mesh,
-            quad_mesh,
-            triangle_material,
-            quad_material
+            power_preference: renderer_config.power_preference,
+            device_lost,
+            surface_format,
+            scene_format,
+            hdr: renderer_config.hdr,
+            surfaces,
+            meshes,
+            materials,
+            scene,
+            scene_instances,
+            scene_draws: Vec::new(),
+            layouts,
+            environment,
+            lights,
+            shadow_casters,
+            shadow_pipelines,
+            cluster_pipeline,
+            scene_pipelines,
+            morph_meshes: Vec::new(),
+            upscale_pipeline,
+            render_targets: Vec::new(),
+            instanced_batches: Vec::new(),
+            texture_pool: render_graph::TexturePool::new(),
+            render_nodes: Vec::new(),
+            post_process: post_process::PostProcessStack::new(),
+            clear_color: renderer_config.clear_color,
+            start_time: Instant::now()
         }
     })
     }
 
-    fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+    pub fn add_window(&mut self, window: Arc<Window>, window_config: &WindowConfig) -> Result<()> {
+        let surface = self.instance.create_surface(Arc::clone(&window)).with_context(|| "Failed to create surface in wgpu")?;
+        let window_surface = window_surface::WindowSurface::new(&window, surface, &self.adapter, &self.device,
+            self.surface_format, window_config, &self.layouts.camera)?;
+        self.surfaces.insert(window.id(), window_surface);
+        Ok(())
+    }
+
+    pub fn remove_window(&mut self, id: WindowId) {
+        self.surfaces.remove(&id);
+    }
+
+    pub fn has_window(&self, id: WindowId) -> bool {
+        self.surfaces.contains_key(&id)
+    }
+
+    pub fn camera(&self, id: WindowId) -> Option<&Camera> {
+        self.surfaces.get(&id).map(|surface| &surface.camera)
+    }
+
+    pub fn set_camera(&mut self, id: WindowId, camera: Camera) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.camera = camera;
+        }
+    }
+
+    pub fn set_clear_color(&mut self, id: WindowId, clear_color: Option<wgpu::Color>) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.clear_color = clear_color;
+        }
+    }
+
+    pub fn add_mesh(&mut self, vertices: &[Vertex], indices: &[u16], label: &str) -> MeshHandle {
+        self.meshes.push(mesh_builder::make_mesh(&self.device, vertices, indices, label));
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, image_filename: &str) -> Result<MaterialHandle> {
+        self.materials.push(materials::Material::new(image_filename, &self.device, &self.queue, &self.layouts.material)?);
+        Ok(MaterialHandle(self.materials.len() - 1))
+    }
+
+    // Tangents are worked out from the texture coordinates when the vertices come without them.
+    pub fn add_lit_mesh(&mut self, vertices: &[LitVertex], indices: &[u16], label: &str) -> MeshHandle {
+        let mut vertices = vertices.to_vec();
+        if !vertices.iter().all(|vertex| vertex.has_tangent()) {
+            mesh_builder::compute_tangents(&mut vertices, indices);
+        }
+        self.meshes.push(mesh_builder::make_lit_mesh(&self.device, &vertices, indices, label));
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    // Only drawn on meshes added with add_lit_mesh.
+    pub fn add_pbr_material(&mut self, desc: &PbrMaterialDesc, label: &str) -> Result<MaterialHandle> {
+        self.materials.push(materials::Material::pbr(label, desc, &self.device, &self.queue, &self.layouts.pbr)?);
+        Ok(MaterialHandle(self.materials.len() - 1))
+    }
+
+    // Like add_pbr_material, only drawn on lit meshes.
+    pub fn add_phong_material(&mut self, desc: &PhongMaterialDesc, label: &str) -> Result<MaterialHandle> {
+        self.materials.push(materials::Material::phong(label, desc, &self.device, &self.queue, &self.layouts.phong)?);
+        Ok(MaterialHandle(self.materials.len() - 1))
+    }
+
+    pub fn scene(&self) -> &SceneGraph {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene
+    }
+
+    pub fn add_morph_mesh(&mut self, vertices: &[Vertex], indices: &[u16], normals: &[glm::Vec3],
+        targets: &[MorphTarget], material: MaterialHandle) -> usize {
+
+        let mesh = morph::MorphMesh::new(&self.device, &self.layouts.morph, vertices, indices, normals, targets);
+        self.morph_meshes.push((mesh, material));
+        self.morph_meshes.len() - 1
+    }
+
+    pub fn set_morph_weights(&mut self, index: usize, weights: &[f32]) {
+        self.morph_meshes[index].0.set_weights(weights);
+    }
+
+    pub fn set_morph_animation(&mut self, index: usize, animation: Option<MorphAnimation>) {
+        self.morph_meshes[index].0.set_animation(animation);
+    }
+
+    // The target starts out drawing with the given camera, add_target_material shows it on meshes.
+    pub fn add_render_target(&mut self, width: u32, height: u32, format: wgpu::TextureFormat, depth: bool, camera: Camera) -> RenderTargetHandle {
+        self.ensure_scene_pipelines(format);
+
+        let mut target = texture::RenderTarget::new(&self.device, width, height, format, "Render target");
+        if depth {
+            target = target.with_depth(&self.device);
+        }
+        self.render_targets.push(camera::TargetCamera::new(&self.device, &self.layouts.camera, target, camera));
+        RenderTargetHandle(self.render_targets.len() - 1)
+    }
+
+    pub fn render_target(&self, handle: RenderTargetHandle) -> &texture::RenderTarget {
+        &self.render_targets[handle.0].target
+    }
+
+    pub fn target_camera(&self, handle: RenderTargetHandle) -> &Camera {
+        &self.render_targets[handle.0].camera
+    }
+
+    pub fn set_target_camera(&mut self, handle: RenderTargetHandle, camera: Camera) {
+        self.render_targets[handle.0].camera = camera;
+    }
+
+    pub fn set_target_clear_color(&mut self, handle: RenderTargetHandle, clear_color: Option<wgpu::Color>) {
+        self.render_targets[handle.0].clear_color = clear_color;
+    }
+
+    pub fn set_target_enabled(&mut self, handle: RenderTargetHandle, enabled: bool) {
+        self.render_targets[handle.0].enabled = enabled;
+    }
+
+    // The old texture goes away, so every material showing the target is bound again.
+    pub fn resize_render_target(&mut self, handle: RenderTargetHandle, width: u32, height: u32) {
+        self.render_targets[handle.0].resize(&self.device, width, height);
+
+        let target = &self.render_targets[handle.0].target;
+        for material in self.materials.iter_mut().filter(|material| material.target() == Some(handle)) {
+            material.recreate(&self.device, &self.queue, self.layouts.for_shading(material.shading()), Some(target));
+        }
+    }
+
+    pub fn add_target_material(&mut self, handle: RenderTargetHandle) -> MaterialHandle {
+        let material = materials::Material::from_target("Render target material", handle, &self.render_targets[handle.0].target,
+            &self.device, &self.layouts.material);
+        self.materials.push(material);
+        MaterialHandle(self.materials.len() - 1)
+    }
+
+    fn ensure_scene_pipelines(&mut self, format: wgpu::TextureFormat) {
+        if !self.scene_pipelines.contains_key(&format) {
+            let pipelines = create_scene_pipelines(&self.device, format, &self.layouts);
+            self.scene_pipelines.insert(format, pipelines);
+        }
+    }
+
+    pub fn add_render_node<N: RenderNode + 'static>(&mut self, node: N) {
+        self.render_nodes.push(Box::new(node));
+    }
+
+    pub fn add_instanced_batch(&mut self, mesh: MeshHandle, material: MaterialHandle) -> usize {
+        self.instanced_batches.push(instancing::InstancedBatch::new(&self.device, mesh, material));
+        self.instanced_batches.len() - 1
+    }
+
+    pub fn set_instances(&mut self, index: usize, instances: &[InstanceData]) {
+        self.instanced_batches[index].set_instances(instances);
+    }
+
+    pub fn push_instance(&mut self, index: usize, instance: InstanceData) {
+        self.instanced_batches[index].push(instance);
+    }
+
+    pub fn set_vsync(&mut self, id: WindowId, vsync: VsyncMode) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.set_vsync(&self.device, vsync);
+        }
+    }
+
+    pub fn set_max_frame_latency(&mut self, id: WindowId, latency: u32) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.set_max_frame_latency(&self.device, latency);
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::SeqCst)
+    }
+
+    // Rebuilds every GPU resource on a fresh device from the CPU copies the renderer keeps.
+    pub fn recreate_device(&mut self) -> Result<()> {
+        warn!("Recreating the graphics device and its resources");
+
+        let runtime = Runtime::new().with_context(|| "Failed to create Tokio runtime")?;
+        let compatible_surface = self.surfaces.values().next().map(|surface| &surface.surface);
+        let (adapter, device, queue) = runtime.block_on(request_device(&self.instance, self.power_preference, compatible_surface))?;
+        watch_device_loss(&device, &self.device_lost);
+
+        let layouts = create_bind_group_layouts(&device);
+        let scene_pipelines = self.scene_pipelines.keys()
+            .map(|format| (*format, create_scene_pipelines(&device, *format, &layouts)))
+            .collect();
+        let upscale_pipeline = create_upscale_pipeline(&device, self.surface_format, &layouts.material);
+        let shadow_pipelines = shadow::ShadowPipelines::new(&device, &layouts.shadow_layer,
+            self.shadow_casters.settings().depth_bias, self.shadow_casters.settings().slope_bias);
+        let cluster_pipeline = create_cluster_pipeline(&adapter, &device, &layouts);
+        // Baked again from the world's map on the next frame.
+        let environment = environment::GpuEnvironment::new(&device, &queue, &layouts.skybox);
+
+        for mesh in self.meshes.iter_mut() {
+            mesh.recreate(&device);
+        }
+        for target in self.render_targets.iter_mut() {
+            target.recreate(&device, &layouts.camera);
+        }
+        for material in self.materials.iter_mut() {
+            let target = material.target().map(|handle| &self.render_targets[handle.0].target);
+            material.recreate(&device, &queue, layouts.for_shading(material.shading()), target);
+        }
+        for (mesh, _) in self.morph_meshes.iter_mut() {
+            mesh.recreate(&device, &layouts.morph);
+        }
+        for batch in self.instanced_batches.iter_mut() {
+            batch.recreate(&device);
+        }
+        self.scene_instances.recreate(&device);
+        self.lights.recreate(&device, &layouts.lights, &environment);
+        self.texture_pool = render_graph::TexturePool::new();
+        for node in self.render_nodes.iter_mut() {
+            node.reset();
+        }
+        self.post_process.reset();
+        for surface in self.surfaces.values_mut() {
+            surface.recreate(&device, &layouts.camera);
+        }
+
+        self.adapter = adapter;
+        self.device = device;
+        self.queue = queue;
+        self.layouts = layouts;
+        self.environment = environment;
+        self.scene_pipelines = scene_pipelines;
+        self.upscale_pipeline = upscale_pipeline;
+        self.shadow_pipelines = shadow_pipelines;
+        self.cluster_pipeline = cluster_pipeline;
+        self.device_lost.store(false, Ordering::SeqCst);
+
+        info!("Graphics device recreated");
+        Ok(())
+    }
+
+    pub fn is_suspended(&self, id: WindowId) -> bool {
+        self.surfaces.get(&id).map(|surface| surface.is_suspended()).unwrap_or(true)
+    }
+
+    fn set_occluded(&mut self, id: WindowId, occluded: bool) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.set_occluded(occluded);
+        }
+    }
+
+    pub fn viewport(&self, id: WindowId) -> Option<Viewport> {
+        self.surfaces.get(&id).map(|surface| surface.viewport())
+    }
+
+    pub fn render_scale(&self, id: WindowId) -> Option<f32> {
+        self.surfaces.get(&id).map(|surface| surface.render_scale())
+    }
+
+    pub fn render_size(&self, id: WindowId) -> Option<(u32, u32)> {
+        self.surfaces.get(&id).map(|surface| surface.render_size())
+    }
+
+    pub fn set_render_scale(&mut self, id: WindowId, render_scale: f32) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.set_render_scale(render_scale);
+        }
+    }
+
+    fn set_scale_factor(&mut self, id: WindowId, scale_factor: f64) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.set_scale_factor(scale_factor);
         }
     }
 
-    fn render(&mut self) {
-        let drawable = self.surface.get_current_texture().unwrap();
-        let image_view_descriptor = wgpu::TextureViewDescriptor::default();
-        let image_view = drawable.texture.create_view(&image_view_descriptor);
+    fn resize(&mut self, id: WindowId, new_size: PhysicalSize<u32>) {
+        if let Some(surface) = self.surfaces.get_mut(&id) {
+            surface.resize(&self.device, new_size);
+        }
+    }
+
+    fn prepare_scene_draws(&mut self, world: &World) {
+        let alpha = world.get_resource::<Time>().map(|time| time.alpha()).unwrap_or(1.0);
+
+        let mut renderables = self.scene.collect_renderables();
+        world.query::<(&Transform, Option<&PreviousTransform>, &MeshHandle, &MaterialHandle)>().for_each(|_, (transform, previous, mesh, material)| {
+            let transform = match previous {
+                Some(previous) => previous.0.lerp(transform, alpha),
+                None => *transform
+            };
+            renderables.push((Renderable::new(*mesh, *material), transform.to_matrix()));
+        });
+
+        let mut groups: Vec<(MeshHandle, MaterialHandle, Vec<instancing::InstanceData>)> = Vec::new();
+        let mut group_indices: HashMap<(MeshHandle, MaterialHandle), usize> = HashMap::new();
+
+        for (renderable, world_transform) in renderables {
+            let key = (renderable.mesh, renderable.material);
+            let index = *group_indices.entry(key).or_insert_with(|| {
+                groups.push((renderable.mesh, renderable.material, Vec::new()));
+                groups.len() - 1
+            });
+            groups[index].2.push(InstanceData::new(world_transform).with_tint(renderable.tint));
+        }
+
+        let mut instances = Vec::new();
+        let mut draws = Vec::with_capacity(groups.len());
+        for (mesh, material, group) in groups {
+            let start = instances.len() as u32;
+            instances.extend(group);
+            draws.push((mesh, material, start..instances.len() as u32));
+        }
+
+        self.scene_instances.update(&self.device, &self.queue, &instances);
+        self.scene_draws = draws;
+    }
+
+    fn prepare_environment(&mut self, world: &World) {
+        let environment = world.get_resource::<Environment>();
+        if self.environment.update(&self.device, &self.queue, &self.layouts.skybox, environment.as_deref()) {
+            self.lights.rebind(&self.device, &self.layouts.lights, &self.environment);
+        }
+    }
+
+    fn prepare_lights(&mut self, world: &World) {
+        let ambient = world.get_resource::<AmbientLight>().map(|ambient| *ambient).unwrap_or_default();
+
+        let settings = world.get_resource::<ShadowSettings>().map(|settings| settings.clone()).unwrap_or_default();
+        if !self.shadow_pipelines.has_bias(settings.depth_bias, settings.slope_bias) {
+            self.shadow_pipelines = shadow::ShadowPipelines::new(&self.device, &self.layouts.shadow_layer, settings.depth_bias, settings.slope_bias);
+        }
+
+        let mut casters = shadow::ShadowCasters::new(settings);
+        let mut lights = Vec::new();
+        world.query::<(&Light, &Transform)>().for_each(|_, (light, transform)| {
+            let world_transform = transform.to_matrix();
+            let mut gpu_light = lighting::GpuLight::new(light, &world_transform);
+            if let Some(layer) = light.shadows.then(|| casters.add(light, &world_transform)).flatten() {
+                gpu_light = gpu_light.with_shadow_layer(layer);
+            }
+            lights.push(gpu_light);
+        });
+
+        self.lights.update(&self.device, &self.queue, &self.layouts.lights, &self.environment, &ambient, lights);
+        self.shadow_casters = casters;
+    }
+
+    // Shared per-frame work, done once no matter how many windows draw the frame.
+    fn prepare(&mut self, world: &World) {
+        // Follows the game clock so pausing, time scale and replays reach animations and effects.
+        let time = world.get_resource::<Time>()
+            .map(|time| time.elapsed_seconds())
+            .unwrap_or_else(|| self.start_time.elapsed().as_secs_f32());
+        for (mesh, _) in self.morph_meshes.iter_mut() {
+            mesh.update(&self.queue, time);
+        }
+        for batch in self.instanced_batches.iter_mut() {
+            batch.upload(&self.device, &self.queue);
+        }
+
+        // An HDR scene would clip in the final blit, without settings it still gets the default tonemapping.
+        let settings = world.get_resource::<PostProcess>();
+        let fallback = (settings.is_none() && self.hdr).then(PostProcess::default);
+        self.post_process.prepare(&self.device, &self.queue, settings.as_deref().or(fallback.as_ref()), time);
 
-        let command_encoder_descriptor = wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder")
+        self.scene.update_world_transforms();
+        self.prepare_scene_draws(world);
+        self.prepare_environment(world);
+        self.prepare_lights(world);
+        self.draw_render_targets();
+        self.texture_pool.end_frame();
+    }
+
+    // Targets are drawn once per frame before any window, so every window shows the same picture.
+    fn draw_render_targets(&mut self) {
+        if !self.render_targets.iter().any(|target| target.enabled) {
+            return;
+        }
+        let shadow_size = self.shadow_casters.map_size();
+        let cpu_lights = self.cluster_pipeline.is_none().then(|| self.lights.lights());
+        let mut shadow_layers = Vec::new();
+        for target in self.render_targets.iter_mut().filter(|target| target.enabled) {
+            let viewport = target.viewport();
+            target.camera_binding.update(&self.queue, &target.camera, &viewport);
+            let lighting = lighting::ViewLighting::ensure(&mut target.lighting, &self.device, &self.layouts.view_lighting,
+                &self.layouts.shadow_layer, &self.layouts.clusters, shadow_size);
+            shadow_layers.push(lighting.update(&self.queue, &self.shadow_casters, cpu_lights,
+                &target.camera, &viewport, target.target.size()));
+        }
+
+        let mut texture_pool = std::mem::take(&mut self.texture_pool);
+        {
+            let state = &*self;
+            let mut graph = render_graph::RenderGraph::new();
+
+            let enabled = state.render_targets.iter().enumerate().filter(|(_, target)| target.enabled);
+            for ((index, target), layers) in enabled.zip(shadow_layers.iter()) {
+                let lighting = target.lighting.as_ref().unwrap();
+                let lighting_inputs = state.add_lighting_passes(&mut graph, lighting, layers);
+
+                let color = graph.import_target(&target.target);
+                let size = target.target.size();
+                let depth = match &target.target.depth_view {
+                    Some(depth_view) => graph.import_texture(depth_view, size, texture::DEPTH_FORMAT),
+                    None => graph.create_texture(render_graph::TextureDesc::new(size.0, size.1, texture::DEPTH_FORMAT))
+                };
+
+                let clear_color = target.clear_color.unwrap_or(state.clear_color);
+                let mut pass = render_graph::PassBuilder::new();
+                pass.add_color_attachment(color, wgpu::LoadOp::Clear(clear_color));
+                pass.set_depth_attachment(depth, wgpu::LoadOp::Clear(1.0));
+                for input in lighting_inputs {
+                    pass.add_input(input);
+                }
+                graph.add_render_pass(pass.build("Render target pass"), move |render_pass, _| {
+                    state.draw_scene(render_pass, target.target.format, &target.camera_binding.bind_group,
+                        &lighting.bind_group, Some(RenderTargetHandle(index)));
+                });
+            }
+
+            graph.execute(&state.device, &state.queue, &mut texture_pool);
+        }
+        self.texture_pool = texture_pool;
+    }
+
+    // Lit materials need the normals only lit meshes have, mismatched pairs are left out.
+    fn is_compatible(&self, mesh: MeshHandle, material: MaterialHandle) -> bool {
+        self.meshes[mesh.0].is_lit() == (self.materials[material.0].shading() != materials::Shading::Unlit)
+    }
+
+    // One depth pass per layer the view's shadow map uses and the pass binning the lights into its clusters,
+    // what they write are inputs of the view's scene pass.
+    fn add_lighting_passes<'a>(&'a self, graph: &mut render_graph::RenderGraph<'a>, lighting: &'a lighting::ViewLighting,
+        layers: &[usize]) -> Vec<render_graph::ResourceId> {
+
+        let shadow_map = &lighting.shadow_map;
+        let size = (shadow_map.size(), shadow_map.size());
+        let mut inputs: Vec<_> = layers.iter().map(|layer| {
+            let depth = graph.import_texture(shadow_map.layer_view(*layer), size, texture::DEPTH_FORMAT);
+            let mut pass = render_graph::PassBuilder::new();
+            pass.set_depth_attachment(depth, wgpu::LoadOp::Clear(1.0));
+            let layer_bind_group = shadow_map.layer_bind_group(*layer);
+            graph.add_render_pass(pass.build("Shadow pass"), move |render_pass, _| {
+                self.draw_shadow_casters(render_pass, layer_bind_group);
+            });
+            depth
+        }).collect();
+
+        if let Some(pipeline) = &self.cluster_pipeline {
+            let counts = graph.import_buffer(lighting.clusters.counts());
+            let indices = graph.import_buffer(lighting.clusters.indices());
+            let mut pass = render_graph::PassBuilder::new();
+            pass.add_output(counts);
+            pass.add_output(indices);
+            graph.add_encoder_pass(pass.build("Light cluster pass"), move |encoder, _| {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Light cluster pass"),
+                    timestamp_writes: None
+                });
+                lighting.clusters.dispatch(&mut compute_pass, pipeline, &self.lights.bind_group);
+            });
+            inputs.push(counts);
+            inputs.push(indices);
+        }
+        inputs
+    }
+
+    // Everything the scene draws casts shadows except morph meshes.
+    fn draw_shadow_casters(&self, render_pass: &mut wgpu::RenderPass<'_>, layer: &wgpu::BindGroup) {
+        let pipeline_for = |mesh: &mesh_builder::Mesh| if mesh.is_lit() { &self.shadow_pipelines.lit } else { &self.shadow_pipelines.unlit };
+
+        let mut current_pipeline = None;
+        render_pass.set_vertex_buffer(1, self.scene_instances.buffer.slice(..));
+        for (mesh, _, instances) in self.scene_draws.iter().filter(|(mesh, material, _)| self.is_compatible(*mesh, *material)) {
+            let mesh = &self.meshes[mesh.0];
+            let pipeline = pipeline_for(mesh);
+            if current_pipeline != Some(pipeline) {
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, layer, &[]);
+                current_pipeline = Some(pipeline);
+            }
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..mesh.index_count, 0, instances.clone());
+        }
+
+        for batch in self.instanced_batches.iter().filter(|batch| batch.instance_buffer.count() > 0 && self.is_compatible(batch.mesh, batch.material)) {
+            let mesh = &self.meshes[batch.mesh.0];
+            render_pass.set_pipeline(pipeline_for(mesh));
+            render_pass.set_bind_group(0, layer, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, batch.instance_buffer.buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..batch.instance_buffer.count());
+        }
+    }
+
+    // A target never samples itself, draws showing skip_target are left out.
+    fn draw_scene(&self, render_pass: &mut wgpu::RenderPass<'_>, format: wgpu::TextureFormat, camera: &wgpu::BindGroup,
+        view_lighting: &wgpu::BindGroup, skip_target: Option<RenderTargetHandle>) {
+
+        let pipelines = &self.scene_pipelines[&format];
+        let visible = |material: &MaterialHandle| skip_target.is_none() || self.materials[material.0].target() != skip_target;
+        let compatible = |mesh: &MeshHandle, material: &MaterialHandle| self.is_compatible(*mesh, *material);
+        let set_pipeline = |render_pass: &mut wgpu::RenderPass<'_>, material: &materials::Material| {
+            let pipeline = match material.shading() {
+                materials::Shading::Unlit => &pipelines.instanced,
+                materials::Shading::BlinnPhong => &pipelines.phong,
+                materials::Shading::Pbr => &pipelines.pbr
+            };
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(1, camera, &[]);
+            if material.shading() != materials::Shading::Unlit {
+                render_pass.set_bind_group(2, &self.lights.bind_group, &[]);
+                render_pass.set_bind_group(3, view_lighting, &[]);
+            }
         };
-        let mut command_encoder = self.device.create_command_encoder(&command_encoder_descriptor);
-
-        let color_attachment = wgpu::RenderPassColorAttachment {
-            view: &image_view,
-            resolve_target: None,
-            ops: wgpu::Operations {
-                load: wgpu::LoadOp::Clear(wgpu::Color {
-                    r: 0.0,
-                    g: 0.0,
-                    b: 1.0,
-                    a: 1.0
-                }),
-                store: wgpu::StoreOp::Store
+
+        let mut current_shading = None;
+        render_pass.set_vertex_buffer(1, self.scene_instances.buffer.slice(..));
+        for (mesh, material, instances) in self.scene_draws.iter().filter(|(mesh, material, _)| visible(material) && compatible(mesh, material)) {
+            let mesh = &self.meshes[mesh.0];
+            let material = &self.materials[material.0];
+            if current_shading != Some(material.shading()) {
+                set_pipeline(render_pass, material);
+                current_shading = Some(material.shading());
             }
+            render_pass.set_bind_group(0, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..mesh.index_count, 0, instances.clone());
+        }
+
+        render_pass.set_pipeline(&pipelines.morph);
+        render_pass.set_bind_group(1, camera, &[]);
+        for (mesh, material) in self.morph_meshes.iter().filter(|(_, material)| visible(material) && self.materials[material.0].shading() == materials::Shading::Unlit) {
+            render_pass.set_bind_group(0, &self.materials[material.0].bind_group, &[]);
+            render_pass.set_bind_group(2, &mesh.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, mesh.normal_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.draw_indexed(0..mesh.index_count, 0, 0..1);
+        }
+
+        for batch in self.instanced_batches.iter().filter(|batch| visible(&batch.material) && compatible(&batch.mesh, &batch.material)) {
+            set_pipeline(render_pass, &self.materials[batch.material.0]);
+            batch.draw(render_pass, &self.meshes[batch.mesh.0], &self.materials[batch.material.0]);
+        }
+
+        // Last, so the depth test leaves only the background to it.
+        if let Some(skybox) = self.environment.skybox() {
+            render_pass.set_pipeline(&pipelines.skybox);
+            render_pass.set_bind_group(0, skybox, &[]);
+            render_pass.set_bind_group(1, camera, &[]);
+            render_pass.draw(0..36, 0..1);
+        }
+    }
+
+    // Skipped frames are not errors, only a surface that cannot hand out frames anymore is.
+    fn render(&mut self, id: WindowId) -> Result<(), wgpu::SurfaceError> {
+        if self.is_device_lost() {
+            return Ok(());
+        }
+
+        let surface = match self.surfaces.get_mut(&id) {
+            Some(surface) if !surface.is_suspended() => surface,
+            _ => return Ok(())
         };
 
-        let render_pass_descriptor = wgpu::RenderPassDescriptor {
-            label: Some("Some Pass"),
-            color_attachments: &[Some(color_attachment)],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None
+        let drawable = match surface.acquire(&self.device)? {
+            Some(drawable) => drawable,
+            None => return Ok(())
         };
+        let viewport = surface.viewport();
+        let render_size = surface.render_size();
+        surface.camera_binding.update(&self.queue, &surface.camera, &viewport);
+        // Binned here only where there's no compute pipeline to do it.
+        let cpu_lights = self.cluster_pipeline.is_none().then(|| self.lights.lights());
+        let lighting = lighting::ViewLighting::ensure(&mut surface.lighting, &self.device, &self.layouts.view_lighting,
+            &self.layouts.shadow_layer, &self.layouts.clusters, self.shadow_casters.map_size());
+        let shadow_layers = lighting.update(&self.queue, &self.shadow_casters, cpu_lights,
+            &surface.camera, &viewport, render_size);
 
+        // Both are only borrowed by the graph, take them out so the passes can borrow the rest of self.
+        let mut texture_pool = std::mem::take(&mut self.texture_pool);
+        let mut render_nodes = std::mem::take(&mut self.render_nodes);
+        let mut post_process = std::mem::take(&mut self.post_process);
         {
-            let mut render_pass = command_encoder.begin_render_pass(&render_pass_descriptor);
-            render_pass.set_pipeline(&self.render_pipeline);
+            let state = &*self;
+            let surface = &state.surfaces[&id];
+            let image_view_descriptor = wgpu::TextureViewDescriptor::default();
+            let image_view = drawable.texture.create_view(&image_view_descriptor);
 
-            render_pass.set_vertex_buffer(0, self.quad_mesh.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.quad_mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.set_bind_group(0, &self.quad_material.bind_group, &[]);
-            render_pass.draw_indexed(0..6, 0, 0..1);
+            let mut graph = render_graph::RenderGraph::new();
+            let backbuffer = graph.import_texture(&image_view, (surface.config.width, surface.config.height), surface.config.format);
 
-            render_pass.set_bind_group(0, &self.triangle_material.bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.triangle_mesh.slice(..));
-            render_pass.draw(0..3, 0..1);
-        }
+            // HDR needs a float texture, nodes sample the scene and a render scale needs another size, the swapchain can't do any of it.
+            let size = surface.render_size();
+            let offscreen = state.scene_format != state.surface_format || surface.render_scale() != 1.0
+                || !render_nodes.is_empty() || post_process.is_active();
+            let color = if offscreen {
+                graph.create_texture(render_graph::TextureDesc::new(size.0, size.1, state.scene_format))
+            } else {
+                backbuffer
+            };
+
+            let depth = graph.create_texture(render_graph::TextureDesc::new(size.0, size.1, texture::DEPTH_FORMAT));
+            let lighting = surface.lighting.as_ref().unwrap();
+            let lighting_inputs = state.add_lighting_passes(&mut graph, lighting, &shadow_layers);
+
+            let clear_color = surface.clear_color.unwrap_or(state.clear_color);
+            let mut pass = render_graph::PassBuilder::new();
+            pass.add_color_attachment(color, wgpu::LoadOp::Clear(clear_color));
+            pass.set_depth_attachment(depth, wgpu::LoadOp::Clear(1.0));
+            for input in lighting_inputs {
+                pass.add_input(input);
+            }
+            graph.add_render_pass(pass.build("Scene pass"), move |render_pass, _| {
+                state.draw_scene(render_pass, state.scene_format, &surface.camera_binding.bind_group, &lighting.bind_group, None);
+            });
+
+            let mut targets = render_graph::FrameTargets {
+                color,
+                backbuffer,
+                size,
+                format: state.scene_format
+            };
+            for node in render_nodes.iter_mut() {
+                node.add_passes(&state.device, &mut graph, &mut targets);
+            }
+            post_process.add_passes(&state.device, &mut graph, &mut targets);
+
+            // Stretches whatever the frame ended up in over the window.
+            if targets.color != backbuffer {
+                let source = targets.color;
+                let mut pass = render_graph::PassBuilder::new();
+                pass.add_input(source);
+                pass.add_color_attachment(backbuffer, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+                graph.add_render_pass(pass.build("Blit pass"), move |render_pass, resources| {
+                    let bind_group = resources.bind_texture(source, &state.layouts.material, "Blit bind group");
+                    render_pass.set_pipeline(&state.upscale_pipeline);
+                    render_pass.set_bind_group(0, &bind_group, &[]);
+                    render_pass.draw(0..3, 0..1);
+                });
+            }
 
-        self.queue.submit(std::iter::once(command_encoder.finish()));
+            graph.execute(&state.device, &state.queue, &mut texture_pool);
+        }
+        self.texture_pool = texture_pool;
+        self.render_nodes = render_nodes;
+        self.post_process = post_process;
 
         drawable.present();
 
+        Ok(())
     }
 }
 
+async fn request_device(instance: &wgpu::Instance, power_preference: wgpu::PowerPreference,
+    compatible_surface: Option<&wgpu::Surface<'_>>) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+
+    let adapter_descriptor = wgpu::RequestAdapterOptionsBase {
+        power_preference,
+        compatible_surface,
+        force_fallback_adapter: false
+    };
+    let adapter = instance.request_adapter(&adapter_descriptor).await.context("No compatible graphics adapter")?;
+
+    let device_descriptor = wgpu::DeviceDescriptor {
+        required_features: wgpu::Features::empty(),
+        required_limits: wgpu::Limits::default(),
+        label: Some("Device"),
+        memory_hints: wgpu::MemoryHints::default()
+    };
+    let (device, queue) = adapter
+            .request_device(&device_descriptor, None)
+            .await
+            .context("Failed to request a graphics device")?;
+
+    Ok((adapter, device, queue))
+}
+
+// Dropping or replacing the device also fires the callback, only a real loss counts.
+fn watch_device_loss(device: &wgpu::Device, device_lost: &Arc<AtomicBool>) {
+    let flag = Arc::clone(device_lost);
+    device.set_device_lost_callback(move |reason, message| {
+        if matches!(reason, wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed) {
+            error!("Graphics device lost: {}", message);
+            flag.store(true, Ordering::SeqCst);
+        }
+    });
+
+    // Calls made between the loss and the rebuild fail, every other error still panics like the default handler.
+    let flag = Arc::clone(device_lost);
+    device.on_uncaptured_error(Box::new(move |e| {
+        if flag.load(Ordering::SeqCst) {
+            warn!("Ignoring error on lost device: {}", e);
+        } else {
+            panic!("{}", e);
+        }
+    }));
+}
+
+fn create_bind_group_layouts(device: &wgpu::Device) -> BindGroupLayouts {
+    let material_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_material();
+        material_bind_group_layout = builder.build("Material bind group layout");
+    }
+
+    let camera_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::VERTEX_FRAGMENT);
+        camera_bind_group_layout = builder.build("Camera bind group layout");
+    }
+
+    let morph_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::VERTEX);
+        builder.add_storage_buffer(wgpu::ShaderStages::VERTEX, true);
+        morph_bind_group_layout = builder.build("Morph bind group layout");
+    }
+
+    let phong_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        builder.add_material();
+        phong_bind_group_layout = builder.build("Phong bind group layout");
+    }
+
+    // Factors first, then base color, metallic-roughness, normal, occlusion and emissive.
+    let pbr_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        for _ in 0..5 {
+            builder.add_material();
+        }
+        pbr_bind_group_layout = builder.build("PBR bind group layout");
+    }
+
+    let lights_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_storage_buffer(wgpu::ShaderStages::FRAGMENT | wgpu::ShaderStages::COMPUTE, true);
+        builder.add_cubemap();
+        builder.add_cubemap();
+        builder.add_material();
+        lights_bind_group_layout = builder.build("Lights bind group layout");
+    }
+
+    // Shadows first, then the view's light clusters.
+    let view_lighting_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        builder.add_shadow_map();
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        builder.add_storage_buffer(wgpu::ShaderStages::FRAGMENT, true);
+        builder.add_storage_buffer(wgpu::ShaderStages::FRAGMENT, true);
+        view_lighting_bind_group_layout = builder.build("View lighting bind group layout");
+    }
+
+    let shadow_layer_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::VERTEX);
+        shadow_layer_bind_group_layout = builder.build("Shadow layer bind group layout");
+    }
+
+    let clusters_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::COMPUTE);
+        builder.add_storage_buffer(wgpu::ShaderStages::COMPUTE, false);
+        builder.add_storage_buffer(wgpu::ShaderStages::COMPUTE, false);
+        clusters_bind_group_layout = builder.build("Clusters bind group layout");
+    }
+
+    let skybox_bind_group_layout: wgpu::BindGroupLayout;
+    {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        builder.add_cubemap();
+        skybox_bind_group_layout = builder.build("Skybox bind group layout");
+    }
+
+    BindGroupLayouts {
+        material: material_bind_group_layout,
+        camera: camera_bind_group_layout,
+        morph: morph_bind_group_layout,
+        phong: phong_bind_group_layout,
+        pbr: pbr_bind_group_layout,
+        lights: lights_bind_group_layout,
+        view_lighting: view_lighting_bind_group_layout,
+        shadow_layer: shadow_layer_bind_group_layout,
+        clusters: clusters_bind_group_layout,
+        skybox: skybox_bind_group_layout
+    }
+}
+
+fn create_cluster_pipeline(adapter: &wgpu::Adapter, device: &wgpu::Device, layouts: &BindGroupLayouts) -> Option<wgpu::ComputePipeline> {
+    let compute = adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+    if !compute {
+        info!("Compute shaders are not supported, binning lights on the CPU");
+        return None;
+    }
+    match clustering::create_pipeline(device, &layouts.lights, &layouts.clusters) {
+        Ok(pipeline) => Some(pipeline),
+        Err(e) => {
+            warn!("Binning lights on the CPU: {:#}", e);
+            None
+        }
+    }
+}
+
+fn create_scene_pipelines(device: &wgpu::Device, format: wgpu::TextureFormat, layouts: &BindGroupLayouts) -> ScenePipelines {
+    let morph_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = pipeline::Builder::new(device);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_vertex_buffer_layout(morph::get_normal_layout());
+        builder.set_shader_module("morph.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(format);
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.add_bind_group_layout(&layouts.material);
+        builder.add_bind_group_layout(&layouts.camera);
+        builder.add_bind_group_layout(&layouts.morph);
+        morph_pipeline = builder.build_pipeline("Morph pipeline");
+    }
+
+    let instanced_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = pipeline::Builder::new(device);
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_vertex_buffer_layout(instancing::InstanceData::get_layout());
+        builder.set_shader_module("instanced.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(format);
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.add_bind_group_layout(&layouts.material);
+        builder.add_bind_group_layout(&layouts.camera);
+        instanced_pipeline = builder.build_pipeline("Instanced pipeline");
+    }
+
+    // The lit shaders share the light code, it goes in front of each of them.
+    let lit_shader = |filename: &str| pipeline::builtin_shader("lighting.wgsl") + "\n" + &pipeline::builtin_shader(filename);
+
+    let phong_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = pipeline::Builder::new(device);
+        builder.add_vertex_buffer_layout(mesh_builder::LitVertex::get_layout());
+        builder.add_vertex_buffer_layout(instancing::InstanceData::get_layout());
+        builder.set_shader_source(lit_shader("blinn_phong.wgsl"), "vs_main", "fs_main");
+        builder.set_pixel_format(format);
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.add_bind_group_layout(&layouts.phong);
+        builder.add_bind_group_layout(&layouts.camera);
+        builder.add_bind_group_layout(&layouts.lights);
+        builder.add_bind_group_layout(&layouts.view_lighting);
+        phong_pipeline = builder.build_pipeline("Blinn-Phong pipeline");
+    }
+
+    let pbr_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = pipeline::Builder::new(device);
+        builder.add_vertex_buffer_layout(mesh_builder::LitVertex::get_layout());
+        builder.add_vertex_buffer_layout(instancing::InstanceData::get_layout());
+        builder.set_shader_source(lit_shader("pbr.wgsl"), "vs_main", "fs_main");
+        builder.set_pixel_format(format);
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.add_bind_group_layout(&layouts.pbr);
+        builder.add_bind_group_layout(&layouts.camera);
+        builder.add_bind_group_layout(&layouts.lights);
+        builder.add_bind_group_layout(&layouts.view_lighting);
+        pbr_pipeline = builder.build_pipeline("PBR pipeline");
+    }
+
+    let skybox_pipeline: wgpu::RenderPipeline;
+    {
+        let mut builder = pipeline::Builder::new(device);
+        builder.set_shader_module("skybox.wgsl", "vs_main", "fs_main");
+        builder.set_pixel_format(format);
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.add_bind_group_layout(&layouts.skybox);
+        builder.add_bind_group_layout(&layouts.camera);
+        skybox_pipeline = builder.build_pipeline("Skybox pipeline");
+    }
+
+    ScenePipelines {
+        instanced: instanced_pipeline,
+        morph: morph_pipeline,
+        phong: phong_pipeline,
+        pbr: pbr_pipeline,
+        skybox: skybox_pipeline
+    }
+}
+
+fn create_upscale_pipeline(device: &wgpu::Device, surface_format: wgpu::TextureFormat, material_bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::RenderPipeline {
+    let mut builder = pipeline::Builder::new(device);
+    builder.set_shader_module("upscale.wgsl", "vs_main", "fs_main");
+    builder.set_pixel_format(surface_format);
+    builder.add_bind_group_layout(material_bind_group_layout);
+    builder.build_pipeline("Upscale pipeline")
+}
+
+fn window_viewport(window: &Window) -> Viewport {
+    let size = window.inner_size();
+    Viewport::new((size.width, size.height), window.scale_factor())
+}
+
+struct OpenWindow {
+    window: Arc<Window>,
+    config: WindowConfig
+}
+
+type Setup<'lifetime_1> = Box<dyn FnOnce(&mut GraphicState<'lifetime_1>, &mut World)>;
 
-#[derive(Default)]
 pub struct App<'lifetime_1> {
-    window: Option<Arc<Window>>,
-    graphic_state: Option<GraphicState<'lifetime_1>>
+    windows: HashMap<WindowId, OpenWindow>,
+    primary_window: Option<WindowId>,
+    graphic_state: Option<GraphicState<'lifetime_1>>,
+    world: World,
+    schedule: Schedule,
+    fixed_schedule: Schedule,
+    window_config: WindowConfig,
+    extra_windows: Vec<WindowConfig>,
+    renderer_config: RendererConfig,
+    render_enabled: bool,
+    loop_config: LoopConfig,
+    frame_limiter: FrameLimiter,
+    gamepad_backend: Option<Box<dyn GamepadBackend>>,
+    input_recorder: Option<InputRecorder>,
+    recording_path: Option<PathBuf>,
+    input_replay: Option<InputReplay>,
+    setups: Vec<Setup<'lifetime_1>>
+}
+
+impl App<'static> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Renders nothing and has no subsystems until plugins install them.
+    pub(crate) fn empty() -> Self {
+        App {
+            windows: HashMap::new(),
+            primary_window: None,
+            graphic_state: None,
+            world: World::new(),
+            schedule: Schedule::new(),
+            fixed_schedule: Schedule::new(),
+            window_config: WindowConfig::default(),
+            extra_windows: Vec::new(),
+            renderer_config: RendererConfig::default(),
+            render_enabled: false,
+            loop_config: LoopConfig::default(),
+            frame_limiter: FrameLimiter::default(),
+            gamepad_backend: None,
+            input_recorder: None,
+            recording_path: None,
+            input_replay: None,
+            setups: Vec::new()
+        }
+    }
+}
+
+// Same as building with the default plugins.
+impl Default for App<'static> {
+    fn default() -> Self {
+        AppBuilder::new()
+            .add_plugin(DefaultPlugins)
+            .build()
+    }
+}
+
+impl App<'_> {
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    pub fn add_system<S: System + 'static>(&mut self, system: S) {
+        self.schedule.add_system(system);
+    }
+
+    pub fn add_system_to_stage<S: System + 'static>(&mut self, stage: &str, system: S) {
+        self.schedule.add_system_to_stage(stage, system);
+    }
+
+    pub fn add_fixed_system<S: System + 'static>(&mut self, system: S) {
+        self.fixed_schedule.add_system(system);
+    }
+
+    pub fn schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.schedule
+    }
+
+    pub fn fixed_schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.fixed_schedule
+    }
+
+    // Live input is ignored while a replay drives the frame.
+    fn handle_input(&mut self, event: InputEvent) {
+        if self.input_replay.is_some() {
+            return;
+        }
+
+        if let Some(recorder) = self.input_recorder.as_mut() {
+            let windows = self.world.resource::<Windows>();
+            let event = event.clone().map_window(|id| WindowId::from(windows.index_of(id).unwrap_or(0) as u64));
+            recorder.record(RecordedEvent::Input(event));
+        }
+        if let Some(mut input) = self.world.get_resource_mut::<Input>() {
+            input.apply(&event);
+        }
+    }
+
+    fn replay_frame(&mut self, event_loop: &ActiveEventLoop) -> Option<Duration> {
+        let replay = self.input_replay.as_mut()?;
+        let frame = match replay.next_frame() {
+            Some(frame) => frame.clone(),
+            None => {
+                info!("Input replay finished after {} frames", replay.frame_index());
+                if replay.exit_when_finished() {
+                    event_loop.exit();
+                }
+                self.input_replay = None;
+                return None;
+            }
+        };
+
+        let windows = self.world.resource::<Windows>();
+        let mut input = self.world.get_resource_mut::<Input>();
+        let mut gamepads = self.world.get_resource_mut::<Gamepads>();
+        for event in frame.events {
+            match event {
+                RecordedEvent::Input(event) => if let Some(input) = input.as_mut() {
+                    let event = event.map_window(|index| windows.nth(u64::from(index) as usize).or(windows.primary()).unwrap_or(index));
+                    input.apply(&event);
+                },
+                RecordedEvent::Gamepad(event) => if let Some(gamepads) = gamepads.as_mut() {
+                    gamepads.handle_event(&event);
+                }
+            }
+        }
+
+        Some(frame.delta)
+    }
+
+    // Runs the fixed ticks owed since the last frame, then the variable rate update.
+    fn update(&mut self, event_loop: &ActiveEventLoop) {
+        let now = Instant::now();
+        let replayed_delta = self.replay_frame(event_loop);
+
+        if let (Some(backend), Some(mut gamepads)) = (self.gamepad_backend.as_mut(), self.world.get_resource_mut::<Gamepads>()) {
+            let mut events = Vec::new();
+            backend.poll(&mut events);
+
+            if self.input_replay.is_none() {
+                for event in events {
+                    gamepads.handle_event(&event);
+                    if let Some(recorder) = self.input_recorder.as_mut() {
+                        recorder.record(RecordedEvent::Gamepad(event));
+                    }
+                }
+            }
+            gamepads.flush_rumble(backend.as_mut());
+        }
+
+        if let (Some(mut actions), Some(input), Some(gamepads)) =
+            (self.world.get_resource_mut::<Actions>(), self.world.get_resource::<Input>(), self.world.get_resource::<Gamepads>()) {
+            actions.update(&input, &gamepads);
+        }
+
+        let ticks = {
+            let mut time = self.world.resource_mut::<Time>();
+            let ticks = match replayed_delta {
+                Some(delta) => time.advance_by(now, delta),
+                None => time.advance(now)
+            };
+            if let Some(recorder) = self.input_recorder.as_mut() {
+                recorder.end_frame(time.raw_delta());
+            }
+            ticks
+        };
+
+        for _ in 0..ticks {
+            self.world.query::<(&Transform, &mut PreviousTransform)>().for_each(|_, (transform, previous)| {
+                previous.0 = *transform;
+            });
+
+            self.world.resource_mut::<Time>().begin_fixed_tick();
+            self.fixed_schedule.run(&mut self.world);
+            self.world.resource_mut::<Time>().end_fixed_tick();
+        }
+
+        self.schedule.run(&mut self.world);
+    }
+
+    // Windows the renderer can't draw to are closed again, returns None for them.
+    fn open_window(&mut self, event_loop: &ActiveEventLoop, config: WindowConfig) -> Option<WindowId> {
+        let attributes = app::window::window_attributes(&config, event_loop);
+        let window = Arc::new(event_loop.create_window(attributes).expect("Failed to create window"));
+        let id = window.id();
+
+        if let Some(graphic_state) = self.graphic_state.as_mut() {
+            if let Err(e) = graphic_state.add_window(window.clone(), &config) {
+                error!("Failed to open window {}: {:#}", config.title, e);
+                return None;
+            }
+        }
+        self.world.resource_mut::<Windows>().insert(id, &config.title, window_viewport(&window));
+        if let Some(mut input) = self.world.get_resource_mut::<Input>() {
+            input.set_scale_factor(id, window.scale_factor());
+        }
+        self.windows.insert(id, OpenWindow { window, config });
+        Some(id)
+    }
+
+    fn close_window(&mut self, event_loop: &ActiveEventLoop, id: WindowId) {
+        if self.primary_window == Some(id) {
+            info!("The primary window was closed");
+            event_loop.exit();
+            return;
+        }
+
+        if let Some(graphic_state) = self.graphic_state.as_mut() {
+            graphic_state.remove_window(id);
+        }
+        self.world.resource_mut::<Windows>().remove(id);
+        if let Some(mut input) = self.world.get_resource_mut::<Input>() {
+            input.remove_window(id);
+        }
+        self.windows.remove(&id);
+    }
+
+    fn rendering_suspended(&self) -> bool {
+        match self.graphic_state.as_ref() {
+            Some(graphic_state) => self.windows.keys().all(|id| graphic_state.is_suspended(*id)),
+            None => false
+        }
+    }
+
+    fn request_redraws(&self) {
+        for open_window in self.windows.values() {
+            open_window.window.request_redraw();
+        }
+    }
+
+    fn apply_window_requests(&mut self, event_loop: &ActiveEventLoop) {
+        let requests = match self.world.get_resource_mut::<WindowControl>() {
+            Some(mut control) => control.drain(),
+            None => return
+        };
+
+        for (target, request) in requests {
+            let id = match target.or(self.primary_window) {
+                Some(id) => id,
+                None => continue
+            };
+
+            if let WindowRequest::Open(config) = request {
+                self.open_window(event_loop, config);
+                continue;
+            }
+            if let WindowRequest::Close = request {
+                self.close_window(event_loop, id);
+                continue;
+            }
+
+            let open_window = match self.windows.get_mut(&id) {
+                Some(open_window) => open_window,
+                None => {
+                    warn!("Window request for unknown window {:?}", id);
+                    continue;
+                }
+            };
+            let window = open_window.window.clone();
+            let config = &mut open_window.config;
+
+            match request {
+                WindowRequest::SetTitle(title) => {
+                    window.set_title(&title);
+                    self.world.resource_mut::<Windows>().set_title(id, &title);
+                    config.title = title;
+                },
+
+                WindowRequest::SetSize(width, height) => {
+                    let _ = window.request_inner_size(LogicalSize::new(width, height));
+                },
+
+                WindowRequest::SetMinSize(size) => {
+                    window.set_min_inner_size(size.map(|(width, height)| LogicalSize::new(width, height)));
+                    config.min_size = size;
+                },
+
+                WindowRequest::SetResizable(resizable) => {
+                    window.set_resizable(resizable);
+                    config.resizable = resizable;
+                },
+
+                WindowRequest::SetDecorations(decorations) => {
+                    window.set_decorations(decorations);
+                    config.decorations = decorations;
+                },
+
+                WindowRequest::SetFullscreen(mode) => {
+                    window.set_fullscreen(app::window::fullscreen(mode, window.current_monitor()));
+                    config.fullscreen = mode;
+                },
+
+                WindowRequest::ToggleFullscreen => {
+                    let mode = if window.fullscreen().is_some() {
+                        FullscreenMode::Windowed
+                    } else {
+                        FullscreenMode::Borderless
+                    };
+                    window.set_fullscreen(app::window::fullscreen(mode, window.current_monitor()));
+                    config.fullscreen = mode;
+                },
+
+                WindowRequest::SetVsync(vsync) => {
+                    if let Some(graphic_state) = self.graphic_state.as_mut() {
+                        graphic_state.set_vsync(id, vsync);
+                    }
+                    config.vsync = vsync;
+                },
+
+                WindowRequest::SetMaxFrameLatency(latency) => {
+                    if let Some(graphic_state) = self.graphic_state.as_mut() {
+                        graphic_state.set_max_frame_latency(id, latency);
+                    }
+                    config.max_frame_latency = latency;
+                },
+
+                WindowRequest::SetCamera(camera) => {
+                    if let Some(graphic_state) = self.graphic_state.as_mut() {
+                        graphic_state.set_camera(id, camera);
+                    }
+                    config.camera = camera;
+                },
+
+                WindowRequest::SetRenderScale(render_scale) => {
+                    if let Some(graphic_state) = self.graphic_state.as_mut() {
+                        graphic_state.set_render_scale(id, render_scale);
+                    }
+                    config.render_scale = render_scale;
+                },
+
+                WindowRequest::Redraw => window.request_redraw(),
+
+                WindowRequest::Open(_) | WindowRequest::Close => ()
+            }
+        }
+    }
 }
 
 impl ApplicationHandler for App<'_> {
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        let window = Arc::new(event_loop.create_window(Window::default_attributes().with_title("Fuji Engine")).expect("Failed to create window in resumed"));
-        self.window = Some(window.clone());
+        if self.primary_window.is_some() {
+            return;
+        }
+
+        let attributes = app::window::window_attributes(&self.window_config, event_loop);
+        let window = Arc::new(event_loop.create_window(attributes).expect("Failed to create window in resumed"));
+        let id = window.id();
+        self.primary_window = Some(id);
+
+        if !self.world.contains_resource::<WindowControl>() {
+            self.world.insert_resource(WindowControl::default());
+        }
+        if !self.world.contains_resource::<Windows>() {
+            self.world.insert_resource(Windows::default());
+        }
+        if !self.world.contains_resource::<Time>() {
+            self.world.insert_resource(Time::default());
+        }
+        if let Some(mut input) = self.world.get_resource_mut::<Input>() {
+            input.set_scale_factor(id, window.scale_factor());
+        }
+        {
+            let mut windows = self.world.resource_mut::<Windows>();
+            windows.insert(id, &self.window_config.title, window_viewport(&window));
+            windows.set_primary(id);
+        }
+        self.windows.insert(id, OpenWindow { window: window.clone(), config: self.window_config.clone() });
+
+        if self.render_enabled {
+            self.graphic_state = Some(GraphicState::new(window.clone(), &self.renderer_config, &self.window_config));
+        }
+
+        for config in std::mem::take(&mut self.extra_windows) {
+            self.open_window(event_loop, config);
+        }
+
+        if let Some(graphic_state) = self.graphic_state.as_mut() {
+            for setup in self.setups.drain(..) {
+                setup(graphic_state, &mut self.world);
+            }
+        }
+
+        self.frame_limiter = FrameLimiter::new(self.loop_config.target_fps, self.loop_config.spin_threshold);
+        self.request_redraws();
+    }
+
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            self.request_redraws();
+        }
+    }
 
-        let graphic_state = GraphicState::new(window.clone());
-        self.graphic_state = Some(graphic_state);
+    fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
+        if let Some(event) = InputEvent::from_device_event(&event) {
+            self.handle_input(event);
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let (Some(recorder), Some(path)) = (self.input_recorder.take(), self.recording_path.as_ref()) {
+            match recorder.finish().save(path) {
+                Ok(()) => info!("Saved input recording to {}", path.display()),
+                Err(e) => error!("{:#}", e)
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Nothing can be shown, so sleep until a window comes back instead of spinning.
+        if self.rendering_suspended() {
+            event_loop.set_control_flow(ControlFlow::Wait);
+            return;
+        }
+
+        if self.loop_config.mode == LoopMode::Poll {
+            self.request_redraws();
+        }
+        event_loop.set_control_flow(self.frame_limiter.control_flow(self.loop_config.mode));
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, id: WindowId, event: WindowEvent) {
+        if !self.windows.contains_key(&id) {
+            return;
+        }
+
+        if let Some(input_event) = InputEvent::from_window_event(id, &event) {
+            self.handle_input(input_event);
+        }
+
+        // In Wait mode frames only happen in response to events.
+        if self.loop_config.mode == LoopMode::Wait && !matches!(event, WindowEvent::RedrawRequested) {
+            self.request_redraws();
+        }
+
         match event {
             WindowEvent::CloseRequested => {
                 info!("The close button was pressed");
-                event_loop.exit();
+                self.close_window(event_loop, id);
             },
 
             WindowEvent::RedrawRequested => {
-                self.window.as_ref().expect("Failed to get window in request redraw").request_redraw();
+                // The primary window drives the frame, the others only present it.
+                if self.primary_window == Some(id) {
+                    self.frame_limiter.wait();
+                    self.update(event_loop);
+                    if let Some(mut input) = self.world.get_resource_mut::<Input>() {
+                        input.end_frame();
+                    }
+                    if let Some(mut gamepads) = self.world.get_resource_mut::<Gamepads>() {
+                        gamepads.end_frame();
+                    }
+                    self.apply_window_requests(event_loop);
+
+                    if let Some(graphic_state) = self.graphic_state.as_mut() {
+                        if graphic_state.is_device_lost() {
+                            if let Err(e) = graphic_state.recreate_device() {
+                                error!("{:#}", e);
+                                event_loop.exit();
+                                return;
+                            }
+                        }
+                        graphic_state.prepare(&self.world);
+                    }
+                }
+
+                if let Some(graphic_state) = self.graphic_state.as_mut() {
+                    if let Err(e) = graphic_state.render(id) {
+                        error!("Cannot render window {:?}: {}", id, e);
+                        event_loop.exit();
+                    }
+                }
+            },
 
-                self.graphic_state.as_mut().unwrap().render();
+            WindowEvent::Occluded(occluded) => {
+                if let Some(graphic_state) = self.graphic_state.as_mut() {
+                    graphic_state.set_occluded(id, occluded);
+                }
             },
 
-            WindowEvent::Resized(physical_size) => self.graphic_state.as_mut().unwrap().resize(physical_size),
+            WindowEvent::Resized(physical_size) => {
+                if let Some(graphic_state) = self.graphic_state.as_mut() {
+                    graphic_state.resize(id, physical_size);
+                }
+                self.world.resource_mut::<Windows>().set_physical_size(id, (physical_size.width, physical_size.height));
+            },
+
+            // The new physical size arrives with the Resized event that follows.
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if let Some(graphic_state) = self.graphic_state.as_mut() {
+                    graphic_state.set_scale_factor(id, scale_factor);
+                }
+                self.world.resource_mut::<Windows>().set_scale_factor(id, scale_factor);
+            },
 
             _ => ()
         }
@@ -229,11 +1637,15 @@ impl ApplicationHandler for App<'_> {
 }
 
 pub fn run() -> Result<()> {
-    let event_loop = EventLoop::builder().build().with_context(|| "Failed to create event loop")?;
+    AppBuilder::new()
+        .add_plugin(DefaultPlugins)
+        .run()
+}
 
-    event_loop.set_control_flow(ControlFlow::Wait);
+pub fn run_with(mut app: App) -> Result<()> {
+    let event_loop = EventLoop::builder().build().with_context(|| "Failed to create event loop")?;
 
-    let mut app = App::default();
+    event_loop.set_control_flow(app.frame_limiter.control_flow(app.loop_config.mode));
 
     event_loop.run_app(&mut app);
 
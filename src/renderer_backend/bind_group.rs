@@ -33,6 +33,13 @@ impl<'lifetime_3> Builder<'lifetime_3> {
         });
     }
 
+    pub fn add_buffer(&mut self, buffer: &'lifetime_3 wgpu::Buffer) {
+        self.entries.push(wgpu::BindGroupEntry {
+            binding: self.entries.len() as u32,
+            resource: buffer.as_entire_binding()
+        });
+    }
+
     pub fn build(&mut self, label: &str) -> wgpu::BindGroup {
         let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(label),
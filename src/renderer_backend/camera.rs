@@ -0,0 +1,92 @@
+use glm::*;
+use super::bind_group;
+use super::mesh_builder;
+use super::lighting::ViewLighting;
+use super::texture::RenderTarget;
+use crate::scene::{Camera, Viewport};
+
+#[repr(C)]
+struct CameraUniform {
+    view_projection: Mat4,
+    position: Vec4
+}
+
+pub struct CameraBinding {
+    pub bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer
+}
+
+impl CameraBinding {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Camera buffer"),
+            size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_buffer(&buffer);
+        let bind_group = builder.build("Camera bind group");
+
+        CameraBinding {
+            bind_group,
+            buffer
+        }
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, viewport: &Viewport) {
+        let uniform = CameraUniform {
+            view_projection: camera.view_projection(viewport),
+            position: Vec4::new(camera.position.x, camera.position.y, camera.position.z, 1.0)
+        };
+        queue.write_buffer(&self.buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+    }
+}
+
+// A camera that draws the scene into a texture instead of a window.
+pub struct TargetCamera {
+    pub target: RenderTarget,
+    pub camera: Camera,
+    pub camera_binding: CameraBinding,
+    pub lighting: Option<ViewLighting>,
+    pub clear_color: Option<wgpu::Color>,
+    pub enabled: bool
+}
+
+impl TargetCamera {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, target: RenderTarget, camera: Camera) -> Self {
+        TargetCamera {
+            target,
+            camera,
+            camera_binding: CameraBinding::new(device, layout),
+            lighting: None,
+            clear_color: None,
+            enabled: true
+        }
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport::new(self.target.size(), 1.0)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.target = self.rebuild_target(device, width, height);
+    }
+
+    pub fn recreate(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) {
+        let (width, height) = self.target.size();
+        self.target = self.rebuild_target(device, width, height);
+        self.camera_binding = CameraBinding::new(device, layout);
+        self.lighting = None;
+    }
+
+    fn rebuild_target(&self, device: &wgpu::Device, width: u32, height: u32) -> RenderTarget {
+        let target = RenderTarget::new(device, width, height, self.target.format, "Render target");
+        match self.target.depth_view {
+            Some(_) => target.with_depth(device),
+            None => target
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use anyhow::Result;
+use glm::*;
+use super::bind_group;
+use super::lighting::GpuLight;
+use super::mesh_builder;
+use super::pipeline;
+use crate::scene::{Camera, Projection, Viewport};
+
+// Screen tiles across and down and depth slices of the froxel grid every view is split into.
+pub const CLUSTER_GRID: (u32, u32, u32) = (16, 9, 24);
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+const CLUSTER_COUNT: u32 = CLUSTER_GRID.0 * CLUSTER_GRID.1 * CLUSTER_GRID.2;
+const WORKGROUP_SIZE: u32 = 64;
+
+// Matches Clusters in clusters.wgsl and lighting.wgsl.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ClusterUniform {
+    view: Mat4,
+    inverse_projection: Mat4,
+    grid: [u32; 4],
+    // Width and height of the target in pixels, then the view depth at the near and far planes.
+    screen: Vec4,
+    // Non-zero when the depth slices are spaced logarithmically, as perspective cameras need.
+    logarithmic: [u32; 4]
+}
+
+impl ClusterUniform {
+    pub fn new(camera: &Camera, viewport: &Viewport, screen_size: (u32, u32)) -> Self {
+        let view = camera.view();
+        let inverse_projection = camera.projection(viewport).inverse().unwrap_or(camera.projection(viewport));
+
+        let near = -view_point(&inverse_projection, Vec2::new(0.0, 0.0), 0.0).z;
+        let far = -view_point(&inverse_projection, Vec2::new(0.0, 0.0), 1.0).z;
+        let logarithmic = matches!(camera.projection, Projection::Perspective { .. }) && near > 0.0;
+
+        ClusterUniform {
+            view,
+            inverse_projection,
+            grid: [CLUSTER_GRID.0, CLUSTER_GRID.1, CLUSTER_GRID.2, MAX_LIGHTS_PER_CLUSTER],
+            screen: Vec4::new(screen_size.0 as f32, screen_size.1 as f32, near, far),
+            logarithmic: [logarithmic as u32, 0, 0, 0]
+        }
+    }
+
+    fn slice_depth(&self, slice: u32) -> f32 {
+        let (near, far) = (self.screen.z, self.screen.w);
+        let t = slice as f32 / CLUSTER_GRID.2 as f32;
+        if self.logarithmic[0] != 0 {
+            near * (far / near).powf(t)
+        } else {
+            near + (far - near) * t
+        }
+    }
+
+    // View space bounds of a cluster, tile rows count down from the top of the screen like pixels.
+    fn cluster_bounds(&self, cluster: u32) -> (Vec3, Vec3) {
+        let x = cluster % CLUSTER_GRID.0;
+        let y = (cluster / CLUSTER_GRID.0) % CLUSTER_GRID.1;
+        let z = cluster / (CLUSTER_GRID.0 * CLUSTER_GRID.1);
+
+        let left = x as f32 / CLUSTER_GRID.0 as f32 * 2.0 - 1.0;
+        let right = (x + 1) as f32 / CLUSTER_GRID.0 as f32 * 2.0 - 1.0;
+        let top = 1.0 - y as f32 / CLUSTER_GRID.1 as f32 * 2.0;
+        let bottom = 1.0 - (y + 1) as f32 / CLUSTER_GRID.1 as f32 * 2.0;
+
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+        for depth in [self.slice_depth(z), self.slice_depth(z + 1)] {
+            for corner in [Vec2::new(left, bottom), Vec2::new(right, bottom), Vec2::new(left, top), Vec2::new(right, top)] {
+                let point = point_at_depth(&self.inverse_projection, corner, depth);
+                min = Vec3::new(min.x.min(point.x), min.y.min(point.y), min.z.min(point.z));
+                max = Vec3::new(max.x.max(point.x), max.y.max(point.y), max.z.max(point.z));
+            }
+        }
+        (min, max)
+    }
+}
+
+fn view_point(inverse_projection: &Mat4, ndc: Vec2, ndc_depth: f32) -> Vec3 {
+    let point = *inverse_projection * Vec4::new(ndc.x, ndc.y, ndc_depth, 1.0);
+    Vec3::new(point.x, point.y, point.z) / point.w
+}
+
+// Where the ray through ndc reaches the given view depth, works for any projection.
+fn point_at_depth(inverse_projection: &Mat4, ndc: Vec2, depth: f32) -> Vec3 {
+    let near = view_point(inverse_projection, ndc, 0.0);
+    let far = view_point(inverse_projection, ndc, 1.0);
+    let span = near.z - far.z;
+    let t = if span.abs() > f32::EPSILON { (depth + near.z) / span } else { 0.0 };
+    near + (far - near) * t
+}
+
+// The reference the compute shader follows step by step, also used where compute shaders are missing.
+// Returns the light count of every cluster and MAX_LIGHTS_PER_CLUSTER light indices per cluster.
+pub fn cluster_lights(uniform: &ClusterUniform, lights: &[GpuLight]) -> (Vec<u32>, Vec<u32>) {
+    let mut counts = vec![0; CLUSTER_COUNT as usize];
+    let mut indices = vec![0; (CLUSTER_COUNT * MAX_LIGHTS_PER_CLUSTER) as usize];
+
+    let spheres: Vec<(usize, Vec3, f32)> = lights.iter()
+        .enumerate()
+        .filter_map(|(index, light)| light.bounds().map(|(center, radius)| {
+            let center = uniform.view * Vec4::new(center.x, center.y, center.z, 1.0);
+            (index, Vec3::new(center.x, center.y, center.z), radius)
+        }))
+        .collect();
+
+    for cluster in 0..CLUSTER_COUNT {
+        let (min, max) = uniform.cluster_bounds(cluster);
+        let mut count = 0;
+        for (index, center, radius) in spheres.iter() {
+            if count < MAX_LIGHTS_PER_CLUSTER && sphere_touches_box(*center, *radius, min, max) {
+                indices[(cluster * MAX_LIGHTS_PER_CLUSTER + count) as usize] = *index as u32;
+                count += 1;
+            }
+        }
+        counts[cluster as usize] = count;
+    }
+    (counts, indices)
+}
+
+fn sphere_touches_box(center: Vec3, radius: f32, min: Vec3, max: Vec3) -> bool {
+    let closest = Vec3::new(center.x.clamp(min.x, max.x), center.y.clamp(min.y, max.y), center.z.clamp(min.z, max.z));
+    let offset = closest - center;
+    dot(offset, offset) <= radius * radius
+}
+
+// A view's froxel grid, filled by the compute pass in front of its scene pass.
+pub struct LightClusters {
+    pub compute_bind_group: wgpu::BindGroup,
+    uniform: wgpu::Buffer,
+    counts: wgpu::Buffer,
+    indices: wgpu::Buffer
+}
+
+impl LightClusters {
+    pub fn new(device: &wgpu::Device, compute_layout: &wgpu::BindGroupLayout) -> Self {
+        let create_buffer = |label: &str, size: usize, usage: wgpu::BufferUsages| device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as wgpu::BufferAddress,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let uniform = create_buffer("Cluster buffer", std::mem::size_of::<ClusterUniform>(), wgpu::BufferUsages::UNIFORM);
+        let counts = create_buffer("Cluster light counts", CLUSTER_COUNT as usize * 4, wgpu::BufferUsages::STORAGE);
+        let indices = create_buffer("Cluster light indices", (CLUSTER_COUNT * MAX_LIGHTS_PER_CLUSTER) as usize * 4, wgpu::BufferUsages::STORAGE);
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(compute_layout);
+        builder.add_buffer(&uniform);
+        builder.add_buffer(&counts);
+        builder.add_buffer(&indices);
+        let compute_bind_group = builder.build("Cluster compute bind group");
+
+        LightClusters {
+            compute_bind_group,
+            uniform,
+            counts,
+            indices
+        }
+    }
+
+    // The uniform, counts and indices, in the order the lit shaders bind them.
+    pub fn add_bindings<'a>(&'a self, builder: &mut bind_group::Builder<'a>) {
+        builder.add_buffer(&self.uniform);
+        builder.add_buffer(&self.counts);
+        builder.add_buffer(&self.indices);
+    }
+
+    pub fn counts(&self) -> &wgpu::Buffer {
+        &self.counts
+    }
+
+    pub fn indices(&self) -> &wgpu::Buffer {
+        &self.indices
+    }
+
+    // Without a compute pipeline the lights are binned here on the CPU instead.
+    pub fn update(&self, queue: &wgpu::Queue, uniform: &ClusterUniform, cpu_lights: Option<&[GpuLight]>) {
+        queue.write_buffer(&self.uniform, 0, unsafe { mesh_builder::any_as_u8_slice(uniform) });
+        if let Some(lights) = cpu_lights {
+            let (counts, indices) = cluster_lights(uniform, lights);
+            queue.write_buffer(&self.counts, 0, unsafe { mesh_builder::slice_as_u8_slice(&counts) });
+            queue.write_buffer(&self.indices, 0, unsafe { mesh_builder::slice_as_u8_slice(&indices) });
+        }
+    }
+
+    pub fn dispatch(&self, compute_pass: &mut wgpu::ComputePass<'_>, pipeline: &wgpu::ComputePipeline, lights: &wgpu::BindGroup) {
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, lights, &[]);
+        compute_pass.set_bind_group(1, &self.compute_bind_group, &[]);
+        compute_pass.dispatch_workgroups(CLUSTER_COUNT.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
+
+pub fn create_pipeline(device: &wgpu::Device, lights_layout: &wgpu::BindGroupLayout, compute_layout: &wgpu::BindGroupLayout) -> Result<wgpu::ComputePipeline> {
+    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Cluster shader"),
+        source: wgpu::ShaderSource::Wgsl(pipeline::read_shader("clusters.wgsl")?.into())
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Cluster pipeline"),
+        bind_group_layouts: &[lights_layout, compute_layout],
+        push_constant_ranges: &[]
+    });
+
+    Ok(device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Cluster pipeline"),
+        layout: Some(&layout),
+        module: &shader_module,
+        entry_point: Some("cs_main"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: None
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::{Light, Transform};
+
+    const SCREEN: (u32, u32) = (1600, 900);
+
+    fn perspective_camera() -> Camera {
+        Camera::perspective(1.0, 0.1, 100.0).looking_at(Vec3::new(2.0, 1.0, 5.0), Vec3::new(2.0, 1.0, 0.0))
+    }
+
+    fn orthographic_camera() -> Camera {
+        Camera::orthographic(10.0, 0.5, 50.0).looking_at(Vec3::new(-1.0, 3.0, 4.0), Vec3::new(-1.0, 3.0, 0.0))
+    }
+
+    fn viewport() -> Viewport {
+        Viewport::new(SCREEN, 1.0)
+    }
+
+    // Places a point light at a view space position.
+    fn point_light(camera: &Camera, view_position: Vec3, range: f32) -> GpuLight {
+        let world = camera.view().inverse().unwrap() * Vec4::new(view_position.x, view_position.y, view_position.z, 1.0);
+        let transform = Transform::from_translation(Vec3::new(world.x, world.y, world.z)).to_matrix();
+        GpuLight::new(&Light::point(Vec3::new(1.0, 1.0, 1.0), 1.0, range), &transform)
+    }
+
+    // The point in the middle of a tile at the given view depth.
+    fn tile_point(uniform: &ClusterUniform, tile: (u32, u32), depth: f32) -> Vec3 {
+        let ndc = Vec2::new(
+            (tile.0 as f32 + 0.5) / CLUSTER_GRID.0 as f32 * 2.0 - 1.0,
+            1.0 - (tile.1 as f32 + 0.5) / CLUSTER_GRID.1 as f32 * 2.0
+        );
+        point_at_depth(&uniform.inverse_projection, ndc, depth)
+    }
+
+    fn cluster(x: u32, y: u32, z: u32) -> u32 {
+        (z * CLUSTER_GRID.1 + y) * CLUSTER_GRID.0 + x
+    }
+
+    // Same steps as cluster_index in lighting.wgsl, from a view space point instead of a fragment.
+    fn shaded_cluster(uniform: &ClusterUniform, camera: &Camera, view_position: Vec3) -> u32 {
+        let clip = camera.projection(&viewport()) * Vec4::new(view_position.x, view_position.y, view_position.z, 1.0);
+        let frag = Vec2::new((clip.x / clip.w + 1.0) * 0.5 * SCREEN.0 as f32, (1.0 - clip.y / clip.w) * 0.5 * SCREEN.1 as f32);
+        let tile_x = (frag.x / uniform.screen.x * CLUSTER_GRID.0 as f32).clamp(0.0, (CLUSTER_GRID.0 - 1) as f32) as u32;
+        let tile_y = (frag.y / uniform.screen.y * CLUSTER_GRID.1 as f32).clamp(0.0, (CLUSTER_GRID.1 - 1) as f32) as u32;
+
+        let (near, far) = (uniform.screen.z, uniform.screen.w);
+        let depth = -view_position.z;
+        let slice = if uniform.logarithmic[0] != 0 {
+            (depth.max(near) / near).ln() / (far / near).ln()
+        } else {
+            (depth - near) / (far - near)
+        };
+        let z = (slice * CLUSTER_GRID.2 as f32).clamp(0.0, (CLUSTER_GRID.2 - 1) as f32) as u32;
+        cluster(tile_x, tile_y, z)
+    }
+
+    fn lights_in<'a>(counts: &[u32], indices: &'a [u32], cluster: u32) -> &'a [u32] {
+        let start = (cluster * MAX_LIGHTS_PER_CLUSTER) as usize;
+        &indices[start..start + counts[cluster as usize] as usize]
+    }
+
+    fn clusters_with(counts: &[u32], indices: &[u32], light: u32) -> Vec<u32> {
+        (0..CLUSTER_COUNT).filter(|cluster| lights_in(counts, indices, *cluster).contains(&light)).collect()
+    }
+
+    #[test]
+    fn slicing_follows_the_projection() {
+        let perspective = ClusterUniform::new(&perspective_camera(), &viewport(), SCREEN);
+        assert_eq!(perspective.logarithmic[0], 1);
+        let orthographic = ClusterUniform::new(&orthographic_camera(), &viewport(), SCREEN);
+        assert_eq!(orthographic.logarithmic[0], 0);
+
+        for (uniform, near, far) in [(&perspective, 0.1, 100.0), (&orthographic, 0.5, 50.0)] {
+            assert!((uniform.slice_depth(0) - near).abs() < 1e-3 * near);
+            assert!((uniform.slice_depth(CLUSTER_GRID.2) - far).abs() < 1e-3 * far);
+        }
+        // Logarithmic slices grow by the same ratio, linear ones by the same step.
+        let ratio = perspective.slice_depth(2) / perspective.slice_depth(1);
+        assert!((perspective.slice_depth(13) / perspective.slice_depth(12) - ratio).abs() < 1e-3);
+        let step = orthographic.slice_depth(2) - orthographic.slice_depth(1);
+        assert!((orthographic.slice_depth(13) - orthographic.slice_depth(12) - step).abs() < 1e-3);
+    }
+
+    #[test]
+    fn small_light_lands_in_one_cluster() {
+        for camera in [perspective_camera(), orthographic_camera()] {
+            let uniform = ClusterUniform::new(&camera, &viewport(), SCREEN);
+            let depth = (uniform.slice_depth(10) + uniform.slice_depth(11)) * 0.5;
+            let light = point_light(&camera, tile_point(&uniform, (7, 4), depth), 0.001);
+
+            let (counts, indices) = cluster_lights(&uniform, &[light]);
+            assert_eq!(clusters_with(&counts, &indices, 0), [cluster(7, 4, 10)]);
+            assert_eq!(counts.iter().sum::<u32>(), 1);
+        }
+    }
+
+    #[test]
+    fn light_on_a_slice_boundary_lands_in_both_slices() {
+        for camera in [perspective_camera(), orthographic_camera()] {
+            let uniform = ClusterUniform::new(&camera, &viewport(), SCREEN);
+            let light = point_light(&camera, tile_point(&uniform, (7, 4), uniform.slice_depth(11)), 0.001);
+
+            let (counts, indices) = cluster_lights(&uniform, &[light]);
+            assert_eq!(clusters_with(&counts, &indices, 0), [cluster(7, 4, 10), cluster(7, 4, 11)]);
+        }
+    }
+
+    #[test]
+    fn directional_lights_are_not_binned() {
+        let camera = perspective_camera();
+        let uniform = ClusterUniform::new(&camera, &viewport(), SCREEN);
+        let sun = GpuLight::new(&Light::directional(Vec3::new(1.0, 1.0, 1.0), 1.0), &crate::scene::transform::identity());
+        let lamp = point_light(&camera, Vec3::new(0.0, 0.0, -10.0), 2.0);
+
+        let (counts, indices) = cluster_lights(&uniform, &[sun, lamp]);
+        assert!(clusters_with(&counts, &indices, 0).is_empty());
+        assert!(!clusters_with(&counts, &indices, 1).is_empty());
+        assert!((0..CLUSTER_COUNT).all(|cluster| lights_in(&counts, &indices, cluster).iter().all(|light| *light == 1)));
+    }
+
+    #[test]
+    fn clusters_hold_at_most_the_cap() {
+        let camera = perspective_camera();
+        let uniform = ClusterUniform::new(&camera, &viewport(), SCREEN);
+        let lights: Vec<GpuLight> = (0..MAX_LIGHTS_PER_CLUSTER + 10)
+            .map(|_| point_light(&camera, Vec3::new(0.0, 0.0, -10.0), 3.0))
+            .collect();
+
+        let (counts, indices) = cluster_lights(&uniform, &lights);
+        assert!(counts.iter().all(|count| *count <= MAX_LIGHTS_PER_CLUSTER));
+        let center = shaded_cluster(&uniform, &camera, Vec3::new(0.0, 0.0, -10.0));
+        let expected: Vec<u32> = (0..MAX_LIGHTS_PER_CLUSTER).collect();
+        assert_eq!(lights_in(&counts, &indices, center), expected.as_slice());
+    }
+
+    #[test]
+    fn shaded_point_finds_its_light() {
+        let positions = [
+            Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.3, -0.2, -0.5), Vec3::new(-2.0, 1.0, -7.5),
+            Vec3::new(5.0, -2.5, -20.0), Vec3::new(-0.01, 0.02, -45.0), Vec3::new(1.5, 1.5, -3.3)
+        ];
+        for camera in [perspective_camera(), orthographic_camera()] {
+            let uniform = ClusterUniform::new(&camera, &viewport(), SCREEN);
+            let lights: Vec<GpuLight> = positions.iter().map(|position| point_light(&camera, *position, 0.05)).collect();
+
+            let (counts, indices) = cluster_lights(&uniform, &lights);
+            for (index, position) in positions.iter().enumerate() {
+                let cluster = shaded_cluster(&uniform, &camera, *position);
+                assert!(lights_in(&counts, &indices, cluster).contains(&(index as u32)),
+                    "Light {} at {:?} is missing from cluster {}", index, position, cluster);
+            }
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use anyhow::{ensure, Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+use super::bind_group;
+use super::bind_group_layout;
+use super::mesh_builder;
+use super::pipeline;
+
+const CUBEMAP_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+const BRDF_LUT_SIZE: u32 = 256;
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+const SPECULAR_MIPS: u32 = 5;
+const MAX_CUBEMAP_SIZE: u32 = 1024;
+
+// World resource, lights the lit materials from the map and draws it behind the scene.
+// Takes the place of the ambient light while a map is set.
+#[derive(Clone, Debug)]
+pub struct Environment {
+    pub map: Option<Arc<EnvironmentMap>>,
+    pub intensity: f32,
+    pub skybox: bool
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment {
+            map: None,
+            intensity: 1.0,
+            skybox: true
+        }
+    }
+}
+
+// Linear HDR pixels as half floats, either six faces stacked or one panorama.
+#[derive(Debug)]
+pub struct EnvironmentMap {
+    width: u32,
+    height: u32,
+    panorama: bool,
+    data: Vec<u16>
+}
+
+impl EnvironmentMap {
+    // Faces go +X, -X, +Y, -Y, +Z, -Z, all square and the same size.
+    pub fn from_faces<P: AsRef<Path>>(paths: [P; 6]) -> Result<Self> {
+        let mut size = None;
+        let mut data = Vec::new();
+        for path in paths.iter() {
+            let face = load_linear(path.as_ref())?;
+            let (width, height) = face.dimensions();
+            ensure!(width == height, "Cubemap face {} is {}x{}, faces need to be square", path.as_ref().display(), width, height);
+            let expected = *size.get_or_insert(width);
+            ensure!(width == expected, "Cubemap face {} is {}x{}, the faces before it are {}x{}",
+                path.as_ref().display(), width, height, expected, expected);
+            data.extend(face.into_raw().into_iter().map(to_half));
+        }
+
+        let size = size.unwrap();
+        Ok(EnvironmentMap {
+            width: size,
+            height: size,
+            panorama: false,
+            data
+        })
+    }
+
+    // An equirectangular panorama such as an .hdr file, turned into a cubemap on the GPU.
+    pub fn from_equirectangular<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let image = load_linear(path)?;
+        let (width, height) = image.dimensions();
+        ensure!(width == height * 2, "Panorama {} is {}x{}, expected twice as wide as it is high", path.display(), width, height);
+
+        Ok(EnvironmentMap {
+            width,
+            height,
+            panorama: true,
+            data: image.into_raw().into_iter().map(to_half).collect()
+        })
+    }
+
+    // Edge length of the cubemap faces.
+    pub fn size(&self) -> u32 {
+        if self.panorama { std::cmp::min((self.width / 4).next_power_of_two(), MAX_CUBEMAP_SIZE) } else { self.width }
+    }
+}
+
+// Float images are taken as linear already, anything else as sRGB.
+fn load_linear(path: &Path) -> Result<image::Rgba32FImage> {
+    let image = image::open(path).with_context(|| format!("Failed to load environment map {}", path.display()))?;
+    let linear = matches!(image, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+    let mut pixels = image.to_rgba32f();
+    if !linear {
+        for pixel in pixels.pixels_mut() {
+            for channel in pixel.0[..3].iter_mut() {
+                *channel = if *channel <= 0.04045 { *channel / 12.92 } else { ((*channel + 0.055) / 1.055).powf(2.4) };
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+// Rounds towards zero, values too small for a half become zero and too large the largest half.
+fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    if exponent <= 0 {
+        sign
+    } else if exponent >= 31 {
+        sign | 0x7bff
+    } else {
+        sign | ((exponent as u16) << 10) | ((bits >> 13) & 0x3ff) as u16
+    }
+}
+
+// Matches BakeParams in environment_common.wgsl.
+#[repr(C)]
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    source_lod: f32,
+    source_size: f32
+}
+
+struct BakePipelines {
+    params_layout: wgpu::BindGroupLayout,
+    panorama_layout: wgpu::BindGroupLayout,
+    source_layout: wgpu::BindGroupLayout,
+    equirect: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    irradiance: wgpu::RenderPipeline,
+    specular: wgpu::RenderPipeline
+}
+
+impl BakePipelines {
+    fn new(device: &wgpu::Device) -> Self {
+        let mut builder = bind_group_layout::Builder::new(device);
+        builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+        let params_layout = builder.build("Environment bake bind group layout");
+        builder.add_material();
+        let panorama_layout = builder.build("Panorama bind group layout");
+        builder.add_cubemap();
+        let source_layout = builder.build("Environment source bind group layout");
+
+        let create_pipeline = |file: &str, fragment_entry: &str, layout: &wgpu::BindGroupLayout, label: &str| {
+            let mut builder = pipeline::Builder::new(device);
+            builder.set_shader_source(pipeline::builtin_shader("environment_common.wgsl") + "\n" + &pipeline::builtin_shader(file), "vs_main", fragment_entry);
+            builder.set_pixel_format(CUBEMAP_FORMAT);
+            builder.add_bind_group_layout(&params_layout);
+            builder.add_bind_group_layout(layout);
+            builder.build_pipeline(label)
+        };
+        let equirect = create_pipeline("environment_equirect.wgsl", "fs_main", &panorama_layout, "Equirectangular pipeline");
+        let downsample = create_pipeline("environment_filter.wgsl", "fs_downsample", &source_layout, "Environment downsample pipeline");
+        let irradiance = create_pipeline("environment_filter.wgsl", "fs_irradiance", &source_layout, "Irradiance pipeline");
+        let specular = create_pipeline("environment_filter.wgsl", "fs_specular", &source_layout, "Specular prefilter pipeline");
+
+        BakePipelines {
+            params_layout,
+            panorama_layout,
+            source_layout,
+            equirect,
+            downsample,
+            irradiance,
+            specular
+        }
+    }
+
+    fn params(&self, device: &wgpu::Device, params: BakeParams) -> wgpu::BindGroup {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment bake buffer"),
+            contents: unsafe { mesh_builder::any_as_u8_slice(&params) },
+            usage: wgpu::BufferUsages::UNIFORM
+        });
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(&self.params_layout);
+        builder.add_buffer(&buffer);
+        builder.build("Environment bake bind group")
+    }
+}
+
+fn create_cubemap(device: &wgpu::Device, size: u32, mips: u32, label: &str) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6
+        },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: CUBEMAP_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[]
+    })
+}
+
+fn write_pixels(queue: &wgpu::Queue, texture: &wgpu::Texture, map: &EnvironmentMap, layers: u32) {
+    let size = wgpu::Extent3d {
+        width: map.width,
+        height: map.height,
+        depth_or_array_layers: layers
+    };
+    queue.write_texture(
+        texture.as_image_copy(),
+        unsafe { mesh_builder::slice_as_u8_slice(&map.data) },
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(map.width * 8),
+            rows_per_image: Some(map.height)
+        },
+        size
+    );
+}
+
+fn cube_view(texture: &wgpu::Texture, base_mip_level: u32, mip_level_count: Option<u32>) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        base_mip_level,
+        mip_level_count,
+        ..Default::default()
+    })
+}
+
+fn face_view(texture: &wgpu::Texture, face: u32, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+fn draw_fullscreen(encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView, pipeline: &wgpu::RenderPipeline, bind_groups: &[&wgpu::BindGroup]) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Environment bake pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: wgpu::StoreOp::Store
+            }
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None
+    });
+    render_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, *bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+// The split sum scale and bias only depend on the BRDF, so one table serves every environment.
+fn bake_brdf_lut(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("BRDF table"),
+        size: wgpu::Extent3d {
+            width: BRDF_LUT_SIZE,
+            height: BRDF_LUT_SIZE,
+            depth_or_array_layers: 1
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: BRDF_LUT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[]
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut builder = pipeline::Builder::new(device);
+    builder.set_shader_module("environment_common.wgsl", "vs_main", "fs_brdf");
+    builder.set_pixel_format(BRDF_LUT_FORMAT);
+    let brdf_pipeline = builder.build_pipeline("BRDF table pipeline");
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("BRDF table encoder") });
+    draw_fullscreen(&mut encoder, &view, &brdf_pipeline, &[]);
+    queue.submit(std::iter::once(encoder.finish()));
+    view
+}
+
+// The maps lit surfaces and the skybox sample. Until a map is set they're black and only the ambient light counts.
+pub struct GpuEnvironment {
+    source: Option<Arc<EnvironmentMap>>,
+    cubemap: wgpu::TextureView,
+    irradiance: wgpu::TextureView,
+    specular: wgpu::TextureView,
+    specular_mips: u32,
+    brdf_lut: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    skybox_uniform: wgpu::Buffer,
+    skybox_bind_group: wgpu::BindGroup,
+    intensity: f32,
+    skybox: bool,
+    bake: BakePipelines
+}
+
+impl GpuEnvironment {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, skybox_layout: &wgpu::BindGroupLayout) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let skybox_uniform = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Skybox buffer"),
+            size: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let cubemap = cube_view(&create_cubemap(device, 1, 1, "Empty environment"), 0, None);
+        let skybox_bind_group = create_skybox_bind_group(device, skybox_layout, &skybox_uniform, &cubemap, &sampler);
+
+        GpuEnvironment {
+            source: None,
+            cubemap,
+            irradiance: cube_view(&create_cubemap(device, 1, 1, "Empty irradiance"), 0, None),
+            specular: cube_view(&create_cubemap(device, 1, 1, "Empty specular"), 0, None),
+            specular_mips: 1,
+            brdf_lut: bake_brdf_lut(device, queue),
+            sampler,
+            skybox_uniform,
+            skybox_bind_group,
+            intensity: 0.0,
+            skybox: false,
+            bake: BakePipelines::new(device)
+        }
+    }
+
+    // Bakes the maps again when the resource points at a different map, returns whether it did.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, skybox_layout: &wgpu::BindGroupLayout,
+        environment: Option<&Environment>) -> bool {
+
+        let map = environment.and_then(|environment| environment.map.as_ref());
+        self.intensity = environment.filter(|_| map.is_some()).map(|environment| environment.intensity).unwrap_or(0.0);
+        self.skybox = environment.map(|environment| environment.skybox).unwrap_or(false) && map.is_some();
+        queue.write_buffer(&self.skybox_uniform, 0, unsafe { mesh_builder::slice_as_u8_slice(&[self.intensity, 0.0, 0.0, 0.0]) });
+
+        let current = match (map, &self.source) {
+            (Some(map), Some(source)) => Arc::ptr_eq(map, source),
+            (None, _) => true,
+            _ => false
+        };
+        if current {
+            return false;
+        }
+
+        let map = map.unwrap();
+        self.bake(device, queue, map);
+        self.skybox_bind_group = create_skybox_bind_group(device, skybox_layout, &self.skybox_uniform, &self.cubemap, &self.sampler);
+        self.source = Some(Arc::clone(map));
+        true
+    }
+
+    fn bake(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, map: &EnvironmentMap) {
+        let size = map.size();
+        let mips = size.ilog2() + 1;
+        let cubemap = create_cubemap(device, size, mips, "Environment");
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Environment bake encoder") });
+
+        let face_params = |face: u32, roughness: f32, source_lod: f32, source_size: u32| self.bake.params(device, BakeParams {
+            face,
+            roughness,
+            source_lod,
+            source_size: source_size as f32
+        });
+
+        // Six faces go straight into the top mip, a panorama is projected onto it.
+        if map.panorama {
+            let panorama = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Panorama"),
+                size: wgpu::Extent3d {
+                    width: map.width,
+                    height: map.height,
+                    depth_or_array_layers: 1
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: CUBEMAP_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[]
+            });
+            write_pixels(queue, &panorama, map, 1);
+            let panorama_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                min_filter: wgpu::FilterMode::Linear,
+                mag_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            });
+            let panorama_view = panorama.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&self.bake.panorama_layout);
+            builder.add_material(&panorama_view, &panorama_sampler);
+            let panorama = builder.build("Panorama bind group");
+            for face in 0..6 {
+                draw_fullscreen(&mut encoder, &face_view(&cubemap, face, 0), &self.bake.equirect, &[&face_params(face, 0.0, 0.0, size), &panorama]);
+            }
+        } else {
+            write_pixels(queue, &cubemap, map, 6);
+        }
+
+        let source_bind_group = |view: &wgpu::TextureView| {
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&self.bake.source_layout);
+            builder.add_material(view, &self.sampler);
+            builder.build("Environment source bind group")
+        };
+
+        for mip in 1..mips {
+            let source = source_bind_group(&cube_view(&cubemap, mip - 1, Some(1)));
+            for face in 0..6 {
+                draw_fullscreen(&mut encoder, &face_view(&cubemap, face, mip), &self.bake.downsample, &[&face_params(face, 0.0, 0.0, size), &source]);
+            }
+        }
+
+        // The irradiance steps are a few degrees apart, a mip around 16 texels across matches them.
+        let source = source_bind_group(&cube_view(&cubemap, 0, None));
+        let irradiance = create_cubemap(device, IRRADIANCE_SIZE, 1, "Irradiance");
+        let irradiance_lod = size.ilog2().saturating_sub(4) as f32;
+        for face in 0..6 {
+            draw_fullscreen(&mut encoder, &face_view(&irradiance, face, 0), &self.bake.irradiance, &[&face_params(face, 0.0, irradiance_lod, size), &source]);
+        }
+
+        let specular_size = std::cmp::min(SPECULAR_SIZE, size);
+        let specular_mips = std::cmp::min(SPECULAR_MIPS, specular_size.ilog2() + 1);
+        let specular = create_cubemap(device, specular_size, specular_mips, "Specular environment");
+        for mip in 0..specular_mips {
+            let roughness = if specular_mips > 1 { mip as f32 / (specular_mips - 1) as f32 } else { 0.0 };
+            for face in 0..6 {
+                draw_fullscreen(&mut encoder, &face_view(&specular, face, mip), &self.bake.specular, &[&face_params(face, roughness, 0.0, size), &source]);
+            }
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+        self.cubemap = cube_view(&cubemap, 0, None);
+        self.irradiance = cube_view(&irradiance, 0, None);
+        self.specular = cube_view(&specular, 0, None);
+        self.specular_mips = specular_mips;
+    }
+
+    // Irradiance, prefiltered specular and the BRDF table, in the order the lit shaders bind them.
+    pub fn add_bindings<'a>(&'a self, builder: &mut bind_group::Builder<'a>) {
+        builder.add_material(&self.irradiance, &self.sampler);
+        builder.add_material(&self.specular, &self.sampler);
+        builder.add_material(&self.brdf_lut, &self.sampler);
+    }
+
+    // Intensity, zero without a map, and the mip of the roughest specular level.
+    pub fn params(&self) -> [f32; 2] {
+        [self.intensity, (self.specular_mips - 1) as f32]
+    }
+
+    pub fn skybox(&self) -> Option<&wgpu::BindGroup> {
+        self.skybox.then_some(&self.skybox_bind_group)
+    }
+}
+
+fn create_skybox_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform: &wgpu::Buffer,
+    cubemap: &wgpu::TextureView, sampler: &wgpu::Sampler) -> wgpu::BindGroup {
+
+    let mut builder = bind_group::Builder::new(device);
+    builder.set_layout(layout);
+    builder.add_buffer(uniform);
+    builder.add_material(cubemap, sampler);
+    builder.build("Skybox bind group")
+}
@@ -0,0 +1,151 @@
+use glm::*;
+use super::mesh_builder::{self, Mesh};
+use super::materials::Material;
+use crate::scene::{MeshHandle, MaterialHandle};
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct InstanceData {
+    pub transform: Mat4,
+    pub tint: Vec4,
+    pub custom: Vec4
+}
+
+impl InstanceData {
+    pub fn new(transform: Mat4) -> Self {
+        InstanceData {
+            transform,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            custom: Vec4::new(0.0, 0.0, 0.0, 0.0)
+        }
+    }
+
+    pub fn with_tint(mut self, tint: Vec4) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_custom(mut self, custom: Vec4) -> Self {
+        self.custom = custom;
+        self
+    }
+
+    pub fn get_layout() -> wgpu::VertexBufferLayout<'static> {
+
+        const ATTRIBUTES: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
+            2 => Float32x4, 3 => Float32x4, 4 => Float32x4, 5 => Float32x4,
+            6 => Float32x4,
+            7 => Float32x4
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceData>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &ATTRIBUTES
+        }
+    }
+}
+
+pub struct InstanceBuffer {
+    pub buffer: wgpu::Buffer,
+    capacity: usize,
+    count: usize
+}
+
+impl InstanceBuffer {
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        InstanceBuffer {
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            count: 0
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance buffer"),
+            size: (capacity * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        })
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[InstanceData]) {
+        if instances.len() > self.capacity {
+            self.capacity = instances.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+
+        if !instances.is_empty() {
+            queue.write_buffer(&self.buffer, 0, unsafe { mesh_builder::slice_as_u8_slice(instances) });
+        }
+        self.count = instances.len();
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count as u32
+    }
+
+    // The contents are gone with the old device, the next update refills the buffer.
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        self.buffer = Self::create_buffer(device, self.capacity);
+        self.count = 0;
+    }
+}
+
+pub struct InstancedBatch {
+    pub mesh: MeshHandle,
+    pub material: MaterialHandle,
+    pub instances: Vec<InstanceData>,
+    pub instance_buffer: InstanceBuffer,
+    dirty: bool
+}
+
+impl InstancedBatch {
+    pub fn new(device: &wgpu::Device, mesh: MeshHandle, material: MaterialHandle) -> Self {
+        InstancedBatch {
+            mesh,
+            material,
+            instances: Vec::new(),
+            instance_buffer: InstanceBuffer::new(device, 64),
+            dirty: false
+        }
+    }
+
+    pub fn set_instances(&mut self, instances: &[InstanceData]) {
+        self.instances.clear();
+        self.instances.extend_from_slice(instances);
+        self.dirty = true;
+    }
+
+    pub fn push(&mut self, instance: InstanceData) {
+        self.instances.push(instance);
+        self.dirty = true;
+    }
+
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        self.instance_buffer.recreate(device);
+        self.dirty = true;
+    }
+
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.dirty {
+            self.instance_buffer.update(device, queue, &self.instances);
+            self.dirty = false;
+        }
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass<'_>, mesh: &Mesh, material: &Material) {
+        if self.instance_buffer.count() == 0 {
+            return;
+        }
+
+        render_pass.set_bind_group(0, &material.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..mesh.index_count, 0, 0..self.instance_buffer.count());
+    }
+}
@@ -0,0 +1,199 @@
+use glm::*;
+use super::bind_group;
+use super::clustering::{ClusterUniform, LightClusters};
+use super::environment::GpuEnvironment;
+use super::mesh_builder;
+use super::shadow::{ShadowCasters, ShadowMap};
+use crate::scene::{Light, LightKind, AmbientLight, Camera, Viewport};
+
+// Kind in position.w, range in direction.w, intensity in color.w, the spot cone cosines in cone.xy
+// and the first shadow map layer in cone.z, negative without shadows.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct GpuLight {
+    position: Vec4,
+    direction: Vec4,
+    color: Vec4,
+    cone: Vec4
+}
+
+impl GpuLight {
+    pub fn new(light: &Light, world_transform: &Mat4) -> Self {
+        let position = *world_transform * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let forward = *world_transform * Vec4::new(0.0, 0.0, -1.0, 0.0);
+        let direction = normalize(Vec3::new(forward.x, forward.y, forward.z));
+
+        let (kind, cone) = match light.kind {
+            LightKind::Directional => (0.0, Vec4::new(0.0, 0.0, -1.0, 0.0)),
+            LightKind::Point => (1.0, Vec4::new(0.0, 0.0, -1.0, 0.0)),
+            LightKind::Spot { inner_angle, outer_angle } => {
+                let outer_angle = outer_angle.max(inner_angle);
+                (2.0, Vec4::new(inner_angle.cos(), outer_angle.cos(), -1.0, 0.0))
+            }
+        };
+
+        GpuLight {
+            position: Vec4::new(position.x, position.y, position.z, kind),
+            direction: Vec4::new(direction.x, direction.y, direction.z, light.range.min(f32::MAX)),
+            color: Vec4::new(light.color.x, light.color.y, light.color.z, light.intensity),
+            cone
+        }
+    }
+
+    pub fn with_shadow_layer(mut self, layer: u32) -> Self {
+        self.cone.z = layer as f32;
+        self
+    }
+
+    pub fn is_directional(&self) -> bool {
+        self.position.w == 0.0
+    }
+
+    // Center and radius of the sphere the light reaches, directional lights reach everywhere.
+    pub fn bounds(&self) -> Option<(Vec3, f32)> {
+        if self.is_directional() {
+            None
+        } else {
+            Some((Vec3::new(self.position.x, self.position.y, self.position.z), self.direction.w))
+        }
+    }
+}
+
+// Matches the start of the Lights struct in lighting.wgsl, the lights array follows at 32 bytes.
+#[repr(C)]
+struct LightsHeader {
+    ambient: Vec4,
+    count: u32,
+    directional_count: u32,
+    environment: [f32; 2]
+}
+
+// Every light in the frame goes into one storage buffer, it grows when the scene gets more of them.
+// Directional lights light every fragment and come first, the clusters only hold the lights after them.
+// The environment's maps are bound next to it, they light the whole frame as well.
+pub struct LightBuffer {
+    pub bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    lights: Vec<GpuLight>
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, environment: &GpuEnvironment, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let buffer = Self::create_buffer(device, capacity);
+        let bind_group = Self::create_bind_group(device, layout, &buffer, environment);
+
+        LightBuffer {
+            bind_group,
+            buffer,
+            capacity,
+            lights: Vec::new()
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light buffer"),
+            size: (std::mem::size_of::<LightsHeader>() + capacity * std::mem::size_of::<GpuLight>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        })
+    }
+
+    fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, buffer: &wgpu::Buffer, environment: &GpuEnvironment) -> wgpu::BindGroup {
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_buffer(buffer);
+        environment.add_bindings(&mut builder);
+        builder.build("Light bind group")
+    }
+
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout,
+        environment: &GpuEnvironment, ambient: &AmbientLight, mut lights: Vec<GpuLight>) {
+
+        lights.sort_by_key(|light| !light.is_directional());
+        if lights.len() > self.capacity {
+            self.capacity = lights.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+            self.rebind(device, layout, environment);
+        }
+
+        let header = LightsHeader {
+            ambient: Vec4::new(ambient.color.x, ambient.color.y, ambient.color.z, ambient.intensity),
+            count: lights.len() as u32,
+            directional_count: lights.iter().filter(|light| light.is_directional()).count() as u32,
+            environment: environment.params()
+        };
+        queue.write_buffer(&self.buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&header) });
+        if !lights.is_empty() {
+            queue.write_buffer(&self.buffer, std::mem::size_of::<LightsHeader>() as wgpu::BufferAddress,
+                unsafe { mesh_builder::slice_as_u8_slice(&lights) });
+        }
+        self.lights = lights;
+    }
+
+    // In the order they were uploaded in.
+    pub fn lights(&self) -> &[GpuLight] {
+        &self.lights
+    }
+
+    // For when the environment's maps were baked again.
+    pub fn rebind(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, environment: &GpuEnvironment) {
+        self.bind_group = Self::create_bind_group(device, layout, &self.buffer, environment);
+    }
+
+    // The next update fills the new buffer again.
+    pub fn recreate(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, environment: &GpuEnvironment) {
+        self.buffer = Self::create_buffer(device, self.capacity);
+        self.rebind(device, layout, environment);
+    }
+}
+
+// What a view binds at group 3 of the lit pipelines, the shadow maps and light clusters fitted to its camera.
+pub struct ViewLighting {
+    pub bind_group: wgpu::BindGroup,
+    pub shadow_map: ShadowMap,
+    pub clusters: LightClusters
+}
+
+impl ViewLighting {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, shadow_layer_layout: &wgpu::BindGroupLayout,
+        cluster_layout: &wgpu::BindGroupLayout, shadow_size: u32) -> Self {
+
+        let shadow_map = ShadowMap::new(device, shadow_layer_layout, shadow_size);
+        let clusters = LightClusters::new(device, cluster_layout);
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        shadow_map.add_bindings(&mut builder);
+        clusters.add_bindings(&mut builder);
+        let bind_group = builder.build("View lighting bind group");
+
+        ViewLighting {
+            bind_group,
+            shadow_map,
+            clusters
+        }
+    }
+
+    // Makes sure the slot holds one with shadow maps of the given size.
+    pub fn ensure<'a>(slot: &'a mut Option<ViewLighting>, device: &wgpu::Device, layout: &wgpu::BindGroupLayout,
+        shadow_layer_layout: &wgpu::BindGroupLayout, cluster_layout: &wgpu::BindGroupLayout, shadow_size: u32) -> &'a ViewLighting {
+
+        if slot.as_ref().map(|lighting| lighting.shadow_map.size()) != Some(shadow_size) {
+            *slot = Some(Self::new(device, layout, shadow_layer_layout, cluster_layout, shadow_size));
+        }
+        slot.as_ref().unwrap()
+    }
+
+    // Fits both to the view's camera, returns the shadow map layers that need drawing.
+    // Without a compute pipeline the clusters are binned from cpu_lights here instead of by the cluster pass.
+    pub fn update(&self, queue: &wgpu::Queue, casters: &ShadowCasters, cpu_lights: Option<&[GpuLight]>,
+        camera: &Camera, viewport: &Viewport, screen_size: (u32, u32)) -> Vec<usize> {
+
+        let uniform = ClusterUniform::new(camera, viewport, screen_size);
+        self.clusters.update(queue, &uniform, cpu_lights);
+        self.shadow_map.update(queue, casters, camera, viewport)
+    }
+}
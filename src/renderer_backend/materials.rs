@@ -1,54 +1,130 @@
-use image::GenericImageView;
+use glm::*;
+use anyhow::{Context, Result};
 use super::bind_group;
+use super::mesh_builder;
+use super::texture::RenderTarget;
+use crate::scene::RenderTargetHandle;
+
+// Decoded pixels are kept so the texture can be uploaded again after a device loss.
+enum Source {
+    Image(image::RgbaImage),
+    Target(RenderTargetHandle),
+    Pbr(Box<PbrSource>),
+    Phong(Box<PhongSource>)
+}
+
+// Which pipeline draws the material, lit ones only go on lit meshes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Shading {
+    Unlit,
+    BlinnPhong,
+    Pbr
+}
+
+// Metallic-roughness as in glTF: roughness is read from green, metallic from blue and occlusion from red.
+// Maps left out are replaced by a 1x1 texture that leaves the factor as it is.
+#[derive(Clone)]
+pub struct PbrMaterialDesc {
+    pub base_color_map: Option<String>,
+    pub metallic_roughness_map: Option<String>,
+    pub normal_map: Option<String>,
+    pub occlusion_map: Option<String>,
+    pub emissive_map: Option<String>,
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    pub emissive: Vec3
+}
+
+impl Default for PbrMaterialDesc {
+    fn default() -> Self {
+        PbrMaterialDesc {
+            base_color_map: None,
+            metallic_roughness_map: None,
+            normal_map: None,
+            occlusion_map: None,
+            emissive_map: None,
+            base_color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
+            normal_scale: 1.0,
+            occlusion_strength: 1.0,
+            emissive: Vec3::new(0.0, 0.0, 0.0)
+        }
+    }
+}
+
+struct PbrSource {
+    desc: PbrMaterialDesc,
+    images: Vec<image::RgbaImage>
+}
+
+// Cheaper than metallic-roughness, the diffuse map is tinted by the diffuse color.
+#[derive(Clone)]
+pub struct PhongMaterialDesc {
+    pub diffuse_map: Option<String>,
+    pub diffuse: Vec4,
+    pub specular: Vec3,
+    pub shininess: f32
+}
+
+impl Default for PhongMaterialDesc {
+    fn default() -> Self {
+        PhongMaterialDesc {
+            diffuse_map: None,
+            diffuse: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            specular: Vec3::new(0.5, 0.5, 0.5),
+            shininess: 32.0
+        }
+    }
+}
+
+struct PhongSource {
+    desc: PhongMaterialDesc,
+    image: image::RgbaImage
+}
+
+#[repr(C)]
+struct PhongUniform {
+    diffuse: Vec4,
+    specular: Vec4
+}
+
+#[repr(C)]
+struct PbrUniform {
+    base_color: Vec4,
+    emissive: Vec4,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32
+}
+
+// Base color and emissive hold colors and are stored as sRGB, the others hold data.
+const PBR_MAPS: [(wgpu::TextureFormat, [u8; 4]); 5] = [
+    (wgpu::TextureFormat::Rgba8UnormSrgb, [255, 255, 255, 255]),
+    (wgpu::TextureFormat::Rgba8Unorm, [255, 255, 255, 255]),
+    (wgpu::TextureFormat::Rgba8Unorm, [128, 128, 255, 255]),
+    (wgpu::TextureFormat::Rgba8Unorm, [255, 255, 255, 255]),
+    (wgpu::TextureFormat::Rgba8UnormSrgb, [255, 255, 255, 255])
+];
 
 pub struct Material {
-    pub bind_group: wgpu::BindGroup
+    pub bind_group: wgpu::BindGroup,
+    label: String,
+    source: Source
 }
 
 impl Material {
-    pub fn new(filename: &str, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Self {
-        let bytes = std::fs::read(format!("../img/{}", filename)).unwrap();
-        let loaded_image = image::load_from_memory(&bytes).unwrap();
-        let converted = loaded_image.to_rgba8();
-        let size = loaded_image.dimensions();
-
-        println!("Image size: {} x {}", size.0, size.1);
-
-        let texture_size = wgpu::Extent3d {
-            width: size.0,
-            height: size.1,
-            depth_or_array_layers: 1
-        };
-
-        let texture_descriptor = wgpu::TextureDescriptor {
-            label: Some(filename),
-            mip_level_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            size: texture_size,
-            sample_count: 1,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm]
-        };
-        let texture = device.create_texture(&texture_descriptor);
-
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All
-            },
-            &converted,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(size.0 * 4),
-                rows_per_image: Some(size.1)
-            },
-            texture_size
-        );
+    pub fn new(filename: &str, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Result<Self> {
+        let image = load_image(filename).with_context(|| format!("Failed to create material {}", filename))?;
+        Ok(Self::from_image(filename, image, device, queue, layout))
+    }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    pub fn from_image(label: &str, converted: image::RgbaImage, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Self {
+        let view = upload_image(device, queue, label, &converted, wgpu::TextureFormat::Rgba8Unorm);
 
         let sampler_descriptor = wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::Repeat,
@@ -63,11 +139,235 @@ impl Material {
         let mut builder = bind_group::Builder::new(device);
         builder.set_layout(layout);
         builder.add_material(&view, &sampler);
-        let bind_group = builder.build(&filename);
+        let bind_group = builder.build(label);
+
+        Material {
+            bind_group,
+            label: label.to_string(),
+            source: Source::Image(converted)
+        }
+
+    }
+
+    // Shows whatever the target's camera sees, the texture is bound like any image.
+    pub fn from_target(label: &str, handle: RenderTargetHandle, target: &RenderTarget, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        Material {
+            bind_group: target.create_bind_group(device, layout, label),
+            label: label.to_string(),
+            source: Source::Target(handle)
+        }
+    }
+
+    pub fn pbr(label: &str, desc: &PbrMaterialDesc, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Result<Self> {
+        let maps = [&desc.base_color_map, &desc.metallic_roughness_map, &desc.normal_map, &desc.occlusion_map, &desc.emissive_map];
+        let images = maps.iter().zip(PBR_MAPS.iter())
+            .map(|(map, (_, default))| load_map(map.as_deref(), *default))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Failed to create material {}", label))?;
+
+        Ok(Self::from_pbr_images(label, desc.clone(), images, device, queue, layout))
+    }
+
+    fn from_pbr_images(label: &str, desc: PbrMaterialDesc, images: Vec<image::RgbaImage>, device: &wgpu::Device,
+        queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Self {
+
+        let uniform = PbrUniform {
+            base_color: desc.base_color,
+            emissive: Vec4::new(desc.emissive.x, desc.emissive.y, desc.emissive.z, 0.0),
+            metallic: desc.metallic,
+            roughness: desc.roughness,
+            normal_scale: desc.normal_scale,
+            occlusion_strength: desc.occlusion_strength
+        };
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<PbrUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        queue.write_buffer(&buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+
+        let views: Vec<wgpu::TextureView> = images.iter().zip(PBR_MAPS.iter())
+            .map(|(image, (format, _))| upload_image(device, queue, label, image, *format))
+            .collect();
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_buffer(&buffer);
+        for view in views.iter() {
+            builder.add_material(view, &sampler);
+        }
+        let bind_group = builder.build(label);
+
+        Material {
+            bind_group,
+            label: label.to_string(),
+            source: Source::Pbr(Box::new(PbrSource {
+                desc,
+                images
+            }))
+        }
+    }
+
+    pub fn phong(label: &str, desc: &PhongMaterialDesc, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Result<Self> {
+        let image = load_map(desc.diffuse_map.as_deref(), [255, 255, 255, 255])
+            .with_context(|| format!("Failed to create material {}", label))?;
+        Ok(Self::from_phong_image(label, desc.clone(), image, device, queue, layout))
+    }
+
+    fn from_phong_image(label: &str, desc: PhongMaterialDesc, image: image::RgbaImage, device: &wgpu::Device,
+        queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout) -> Self {
+
+        let uniform = PhongUniform {
+            diffuse: desc.diffuse,
+            specular: Vec4::new(desc.specular.x, desc.specular.y, desc.specular.z, desc.shininess)
+        };
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<PhongUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        queue.write_buffer(&buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+
+        let view = upload_image(device, queue, label, &image, wgpu::TextureFormat::Rgba8UnormSrgb);
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_buffer(&buffer);
+        builder.add_material(&view, &sampler);
+        let bind_group = builder.build(label);
 
         Material {
-            bind_group
+            bind_group,
+            label: label.to_string(),
+            source: Source::Phong(Box::new(PhongSource {
+                desc,
+                image
+            }))
         }
+    }
+
+    pub fn target(&self) -> Option<RenderTargetHandle> {
+        match self.source {
+            Source::Target(handle) => Some(handle),
+            Source::Image(_) | Source::Pbr(_) | Source::Phong(_) => None
+        }
+    }
+
+    pub fn shading(&self) -> Shading {
+        match self.source {
+            Source::Image(_) | Source::Target(_) => Shading::Unlit,
+            Source::Phong(_) => Shading::BlinnPhong,
+            Source::Pbr(_) => Shading::Pbr
+        }
+    }
+
+    // Targets get recreated and resized on their own, the caller passes the current one in.
+    // The layout has to be the one for the material's shading.
+    pub fn recreate(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout, target: Option<&RenderTarget>) {
+        let label = self.label.clone();
+        *self = match std::mem::replace(&mut self.source, Source::Image(image::RgbaImage::default())) {
+            Source::Image(image) => Self::from_image(&label, image, device, queue, layout),
+            Source::Target(handle) => Self::from_target(&label, handle, target.expect("Target material needs its render target"), device, layout),
+            Source::Pbr(source) => {
+                let PbrSource { desc, images } = *source;
+                Self::from_pbr_images(&label, desc, images, device, queue, layout)
+            },
+            Source::Phong(source) => {
+                let PhongSource { desc, image } = *source;
+                Self::from_phong_image(&label, desc, image, device, queue, layout)
+            }
+        };
+    }
+}
+
+// Maps left out become a single pixel of the given color.
+fn load_map(filename: Option<&str>, default: [u8; 4]) -> Result<image::RgbaImage> {
+    match filename {
+        Some(filename) => load_image(filename),
+        None => Ok(image::RgbaImage::from_pixel(1, 1, image::Rgba(default)))
+    }
+}
+
+fn load_image(filename: &str) -> Result<image::RgbaImage> {
+    let path = format!("../img/{}", filename);
+    let bytes = std::fs::read(&path).with_context(|| format!("Failed to read material map {}", path))?;
+    let image = image::load_from_memory(&bytes).with_context(|| format!("Failed to decode material map {}", path))?;
+    Ok(image.to_rgba8())
+}
+
+fn upload_image(device: &wgpu::Device, queue: &wgpu::Queue, label: &str, image: &image::RgbaImage, format: wgpu::TextureFormat) -> wgpu::TextureView {
+    let size = image.dimensions();
+
+    let texture_size = wgpu::Extent3d {
+        width: size.0,
+        height: size.1,
+        depth_or_array_layers: 1
+    };
+
+    let texture_descriptor = wgpu::TextureDescriptor {
+        label: Some(label),
+        mip_level_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        size: texture_size,
+        sample_count: 1,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[format]
+    };
+    let texture = device.create_texture(&texture_descriptor);
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All
+        },
+        image,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(size.0 * 4),
+            rows_per_image: Some(size.1)
+        },
+        texture_size
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_maps_name_their_path() {
+        let error = load_map(Some("no_such_map.png"), [0, 0, 0, 255]).err().unwrap();
+        assert!(format!("{:#}", error).contains("../img/no_such_map.png"));
+    }
 
+    #[test]
+    fn left_out_maps_are_one_pixel() {
+        let image = load_map(None, [1, 2, 3, 4]).unwrap();
+        assert_eq!(image.dimensions(), (1, 1));
+        assert_eq!(image.get_pixel(0, 0).0, [1, 2, 3, 4]);
     }
 }
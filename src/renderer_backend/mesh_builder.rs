@@ -2,17 +2,57 @@ use glm::*;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Vertex {
     position: Vec3,
     color: Vec3
 }
 
+// Vertex for meshes drawn with lighting. Locations 2 to 7 belong to the instance data, so the rest start at 8.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LitVertex {
+    position: Vec3,
+    normal: Vec3,
+    tex_coord: Vec2,
+    // w is the handedness of the bitangent.
+    tangent: Vec4
+}
+
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    // CPU copies kept so the buffers can be rebuilt after a device loss.
+    vertex_data: Vec<u8>,
+    indices: Vec<u16>,
+    label: String,
+    lit: bool
+}
+
+impl Mesh {
+    pub fn recreate(&mut self, device: &wgpu::Device) {
+        *self = make_mesh_from_bytes(device, &self.vertex_data, &self.indices, &self.label, self.lit);
+    }
+
+    // Lit meshes are made of LitVertex and only go with lit materials.
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
 }
 
 impl Vertex {
+    pub fn new(position: Vec3, color: Vec3) -> Self {
+        Vertex {
+            position,
+            color
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
     pub fn get_layout() -> wgpu::VertexBufferLayout<'static> {
 
         const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
@@ -25,27 +65,102 @@ impl Vertex {
     }
 }
 
-unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
+impl LitVertex {
+    // The tangent is left for compute_tangents to fill in.
+    pub fn new(position: Vec3, normal: Vec3, tex_coord: Vec2) -> Self {
+        LitVertex {
+            position,
+            normal,
+            tex_coord,
+            tangent: Vec4::new(0.0, 0.0, 0.0, 1.0)
+        }
+    }
+
+    pub fn with_tangent(mut self, tangent: Vec4) -> Self {
+        self.tangent = tangent;
+        self
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn has_tangent(&self) -> bool {
+        self.tangent.x != 0.0 || self.tangent.y != 0.0 || self.tangent.z != 0.0
+    }
+
+    pub fn get_layout() -> wgpu::VertexBufferLayout<'static> {
+
+        const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+            0 => Float32x3, 1 => Float32x3, 8 => Float32x2, 9 => Float32x4
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LitVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBUTES
+        }
+    }
+}
+
+// Per-vertex tangents from the texture coordinates, averaged over the triangles sharing the vertex.
+pub fn compute_tangents(vertices: &mut [LitVertex], indices: &[u16]) {
+    let mut tangents = vec![Vec3::new(0.0, 0.0, 0.0); vertices.len()];
+    let mut bitangents = vec![Vec3::new(0.0, 0.0, 0.0); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (triangle[0] as usize, triangle[1] as usize, triangle[2] as usize);
+        let edge_1 = vertices[b].position - vertices[a].position;
+        let edge_2 = vertices[c].position - vertices[a].position;
+        let delta_1 = vertices[b].tex_coord - vertices[a].tex_coord;
+        let delta_2 = vertices[c].tex_coord - vertices[a].tex_coord;
+
+        let determinant = delta_1.x * delta_2.y - delta_2.x * delta_1.y;
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (edge_1 * delta_2.y - edge_2 * delta_1.y) * r;
+        let bitangent = (edge_2 * delta_1.x - edge_1 * delta_2.x) * r;
+
+        for index in [a, b, c] {
+            tangents[index] = tangents[index] + tangent;
+            bitangents[index] = bitangents[index] + bitangent;
+        }
+    }
+
+    for (vertex, (tangent, bitangent)) in vertices.iter_mut().zip(tangents.into_iter().zip(bitangents)) {
+        let normal = vertex.normal;
+        // Gram-Schmidt, then any direction at right angles to the normal for vertices without UV area.
+        let mut tangent = tangent - normal * dot(normal, tangent);
+        if length(tangent) < 1e-6 {
+            let axis = if normal.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+            tangent = cross(normal, axis);
+        }
+        let tangent = normalize(tangent);
+        let handedness = if dot(cross(normal, tangent), bitangent) < 0.0 { -1.0 } else { 1.0 };
+        vertex.tangent = Vec4::new(tangent.x, tangent.y, tangent.z, handedness);
+    }
+}
+
+pub unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     ::core::slice::from_raw_parts((p as *const T) as *const u8, ::core::mem::size_of::<T>())
 }
 
-pub fn make_triangle(device: &wgpu::Device) -> wgpu::Buffer {
+pub unsafe fn slice_as_u8_slice<T: Sized>(p: &[T]) -> &[u8] {
+    ::core::slice::from_raw_parts(p.as_ptr() as *const u8, ::core::mem::size_of_val(p))
+}
+
+pub fn make_triangle(device: &wgpu::Device) -> Mesh {
     let vertices: [Vertex; 3] = [
         Vertex {position: Vec3::new(-0.75, -0.75, 0.0), color: Vec3::new(1.0, 0.0, 0.0)},
         Vertex {position: Vec3::new(0.75, -0.75, 0.0), color: Vec3::new(0.0, 1.0, 0.0)},
         Vertex {position: Vec3::new(0.0, 0.75, 0.0), color: Vec3::new(0.0, 0.0, 1.0)}
     ];
 
-    let bytes: &[u8] = unsafe { any_as_u8_slice(&vertices) };
-
-    let buffer_descriptor = wgpu::util::BufferInitDescriptor {
-        label: Some("Triangle vertex buffer"),
-        contents: &bytes,
-        usage: wgpu::BufferUsages::VERTEX
-    };
+    let indices: [u16; 3] = [0, 1, 2];
 
-    let buffer = device.create_buffer_init(&buffer_descriptor);
-    return buffer;
+    make_mesh(device, &vertices, &indices, "Triangle")
 }
 
 pub fn make_quad(device: &wgpu::Device) -> Mesh {
@@ -56,29 +171,39 @@ pub fn make_quad(device: &wgpu::Device) -> Mesh {
         Vertex {position: Vec3::new(-0.75,  0.75, 0.0), color: Vec3::new(0.0, 1.0, 1.0)}
     ];
 
-    let mut bytes: &[u8] = unsafe { any_as_u8_slice(&vertices) };
+    let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
 
-    let mut buffer_descriptor = wgpu::util::BufferInitDescriptor {
-        label: Some("Quad vertex buffer"),
-        contents: &bytes,
-        usage: wgpu::BufferUsages::VERTEX
-    };
+    make_mesh(device, &vertices, &indices, "Quad")
+}
 
-    let vertex_buffer = device.create_buffer_init(&buffer_descriptor);
+pub fn make_mesh(device: &wgpu::Device, vertices: &[Vertex], indices: &[u16], label: &str) -> Mesh {
+    make_mesh_from_bytes(device, unsafe { slice_as_u8_slice(vertices) }, indices, label, false)
+}
 
-    let indices: [u16; 6] = [0, 1, 2, 2, 3, 0];
-    bytes = unsafe { any_as_u8_slice(&indices) };
+pub fn make_lit_mesh(device: &wgpu::Device, vertices: &[LitVertex], indices: &[u16], label: &str) -> Mesh {
+    make_mesh_from_bytes(device, unsafe { slice_as_u8_slice(vertices) }, indices, label, true)
+}
 
-    buffer_descriptor = wgpu::util::BufferInitDescriptor {
-        label: Some("Quad index buffer"),
-        contents: bytes,
-        usage: wgpu::BufferUsages::INDEX
-    };
+fn make_mesh_from_bytes(device: &wgpu::Device, vertex_data: &[u8], indices: &[u16], label: &str, lit: bool) -> Mesh {
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} vertex buffer", label)),
+        contents: vertex_data,
+        usage: wgpu::BufferUsages::VERTEX
+    });
 
-    let index_buffer = device.create_buffer_init(&buffer_descriptor);
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} index buffer", label)),
+        contents: unsafe { slice_as_u8_slice(indices) },
+        usage: wgpu::BufferUsages::INDEX
+    });
 
     Mesh {
         vertex_buffer,
-        index_buffer
+        index_buffer,
+        index_count: indices.len() as u32,
+        vertex_data: vertex_data.to_vec(),
+        indices: indices.to_vec(),
+        label: label.to_string(),
+        lit
     }
 }
@@ -4,3 +4,13 @@ pub mod bind_group_layout;
 pub mod bind_group;
 pub mod texture;
 pub mod materials;
+pub mod morph;
+pub mod instancing;
+pub mod camera;
+pub mod window_surface;
+pub mod render_graph;
+pub mod post_process;
+pub mod lighting;
+pub mod shadow;
+pub mod clustering;
+pub mod environment;
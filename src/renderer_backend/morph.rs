@@ -0,0 +1,296 @@
+use glm::*;
+use wgpu::util::DeviceExt;
+use super::bind_group;
+use super::mesh_builder::{self, Vertex};
+
+pub const MAX_MORPH_TARGETS: usize = 64;
+
+#[derive(Clone)]
+pub struct MorphTarget {
+    pub position_deltas: Vec<Vec3>,
+    // Either one per vertex or empty for targets that leave the normals alone.
+    pub normal_deltas: Vec<Vec3>
+}
+
+#[repr(C)]
+struct MorphDelta {
+    position: [f32; 4],
+    normal: [f32; 4]
+}
+
+#[repr(C)]
+struct MorphUniform {
+    vertex_count: u32,
+    target_count: u32,
+    _padding: [u32; 2],
+    weights: [[f32; 4]; MAX_MORPH_TARGETS / 4]
+}
+
+pub struct MorphKeyframe {
+    pub time: f32,
+    pub weights: Vec<f32>
+}
+
+pub struct MorphAnimation {
+    pub keyframes: Vec<MorphKeyframe>,
+    pub looping: bool
+}
+
+impl MorphAnimation {
+    pub fn new(looping: bool) -> Self {
+        MorphAnimation {
+            keyframes: Vec::new(),
+            looping
+        }
+    }
+
+    pub fn add_keyframe(&mut self, time: f32, weights: &[f32]) {
+        self.keyframes.push(MorphKeyframe {
+            time,
+            weights: weights.to_vec()
+        });
+        self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map(|k| k.time).unwrap_or(0.0)
+    }
+
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let (first, last) = match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec::new()
+        };
+
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            time
+        };
+
+        if time <= first.time {
+            return first.weights.clone();
+        }
+        if time >= last.time {
+            return last.weights.clone();
+        }
+
+        let next = self.keyframes.iter().position(|k| k.time > time).unwrap();
+        let a = &self.keyframes[next - 1];
+        let b = &self.keyframes[next];
+        let t = (time - a.time) / (b.time - a.time);
+
+        let count = a.weights.len().max(b.weights.len());
+        (0..count)
+            .map(|i| {
+                let from = a.weights.get(i).copied().unwrap_or(0.0);
+                let to = b.weights.get(i).copied().unwrap_or(0.0);
+                from + (to - from) * t
+            })
+            .collect()
+    }
+}
+
+pub struct MorphMesh {
+    pub vertex_buffer: wgpu::Buffer,
+    pub normal_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+    pub index_count: u32,
+    pub bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    vertex_count: u32,
+    target_count: u32,
+    weights: Vec<f32>,
+    animation: Option<MorphAnimation>,
+    // CPU copies kept so the buffers can be rebuilt after a device loss.
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    normals: Vec<Vec3>,
+    targets: Vec<MorphTarget>
+}
+
+pub fn get_normal_layout() -> wgpu::VertexBufferLayout<'static> {
+
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![2 => Float32x3];
+
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vec3>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBUTES
+    }
+}
+
+impl MorphMesh {
+    pub fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout,
+        vertices: &[Vertex], indices: &[u16], normals: &[Vec3], targets: &[MorphTarget]) -> Self {
+
+        assert!(targets.len() <= MAX_MORPH_TARGETS, "Too many morph targets: {}", targets.len());
+        assert_eq!(vertices.len(), normals.len(), "Every vertex needs a base normal");
+
+        let vertex_count = vertices.len();
+        let mut deltas: Vec<MorphDelta> = Vec::with_capacity(vertex_count * targets.len().max(1));
+        for target in targets {
+            assert_eq!(target.position_deltas.len(), vertex_count, "Morph target vertex count mismatch");
+            assert!(target.normal_deltas.is_empty() || target.normal_deltas.len() == vertex_count,
+                "Morph target normal count mismatch: {} normals for {} vertices", target.normal_deltas.len(), vertex_count);
+            for i in 0..vertex_count {
+                let position = target.position_deltas[i];
+                let normal = target.normal_deltas.get(i).copied().unwrap_or(Vec3::new(0.0, 0.0, 0.0));
+                deltas.push(MorphDelta {
+                    position: [position.x, position.y, position.z, 0.0],
+                    normal: [normal.x, normal.y, normal.z, 0.0]
+                });
+            }
+        }
+
+        // Storage buffers can't be empty, keep a zeroed delta around for meshes without targets.
+        if deltas.is_empty() {
+            deltas.push(MorphDelta {
+                position: [0.0; 4],
+                normal: [0.0; 4]
+            });
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph vertex buffer"),
+            contents: unsafe { mesh_builder::slice_as_u8_slice(vertices) },
+            usage: wgpu::BufferUsages::VERTEX
+        });
+
+        let normal_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph normal buffer"),
+            contents: unsafe { mesh_builder::slice_as_u8_slice(normals) },
+            usage: wgpu::BufferUsages::VERTEX
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph index buffer"),
+            contents: unsafe { mesh_builder::slice_as_u8_slice(indices) },
+            usage: wgpu::BufferUsages::INDEX
+        });
+
+        let delta_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Morph delta buffer"),
+            contents: unsafe { mesh_builder::slice_as_u8_slice(&deltas) },
+            usage: wgpu::BufferUsages::STORAGE
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Morph weight buffer"),
+            size: std::mem::size_of::<MorphUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_buffer(&uniform_buffer);
+        builder.add_buffer(&delta_buffer);
+        let bind_group = builder.build("Morph bind group");
+
+        MorphMesh {
+            vertex_buffer,
+            normal_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            bind_group,
+            uniform_buffer,
+            vertex_count: vertex_count as u32,
+            target_count: targets.len() as u32,
+            weights: vec![0.0; targets.len()],
+            animation: None,
+            vertices: vertices.to_vec(),
+            indices: indices.to_vec(),
+            normals: normals.to_vec(),
+            targets: targets.to_vec()
+        }
+    }
+
+    // Weights and animation are CPU state and carry over to the new buffers.
+    pub fn recreate(&mut self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) {
+        let mut mesh = MorphMesh::new(device, layout, &self.vertices, &self.indices, &self.normals, &self.targets);
+        mesh.weights = std::mem::take(&mut self.weights);
+        mesh.animation = self.animation.take();
+        *self = mesh;
+    }
+
+    pub fn set_weights(&mut self, weights: &[f32]) {
+        for (i, weight) in self.weights.iter_mut().enumerate() {
+            *weight = weights.get(i).copied().unwrap_or(0.0);
+        }
+    }
+
+    pub fn set_animation(&mut self, animation: Option<MorphAnimation>) {
+        self.animation = animation;
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, time: f32) {
+        if let Some(animation) = &self.animation {
+            let weights = animation.sample(time);
+            self.set_weights(&weights);
+        }
+
+        let mut uniform = MorphUniform {
+            vertex_count: self.vertex_count,
+            target_count: self.target_count,
+            _padding: [0; 2],
+            weights: [[0.0; 4]; MAX_MORPH_TARGETS / 4]
+        };
+        for (i, weight) in self.weights.iter().enumerate() {
+            uniform.weights[i / 4][i % 4] = *weight;
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_weights(actual: Vec<f32>, expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} vs {:?}", actual, expected);
+        }
+    }
+
+    fn animation(looping: bool) -> MorphAnimation {
+        let mut animation = MorphAnimation::new(looping);
+        // Added out of order, keyframes are kept sorted by time.
+        animation.add_keyframe(2.0, &[1.0, 0.0]);
+        animation.add_keyframe(1.0, &[0.0, 1.0]);
+        animation.add_keyframe(4.0, &[0.5]);
+        animation
+    }
+
+    #[test]
+    fn empty_animation_has_no_weights() {
+        assert!(MorphAnimation::new(true).sample(1.0).is_empty());
+    }
+
+    #[test]
+    fn before_the_first_key_holds_it() {
+        let animation = animation(false);
+        assert_weights(animation.sample(0.0), &[0.0, 1.0]);
+        assert_weights(animation.sample(-3.0), &[0.0, 1.0]);
+    }
+
+    #[test]
+    fn between_keys_interpolates() {
+        let animation = animation(false);
+        assert_weights(animation.sample(1.5), &[0.5, 0.5]);
+        assert_weights(animation.sample(2.0), &[1.0, 0.0]);
+        // Weights missing from one key count as zero.
+        assert_weights(animation.sample(3.0), &[0.75, 0.0]);
+    }
+
+    #[test]
+    fn past_the_last_key_clamps_or_wraps() {
+        assert_weights(animation(false).sample(10.0), &[0.5]);
+        assert_eq!(animation(true).duration(), 4.0);
+        assert_weights(animation(true).sample(5.5), &[0.5, 0.5]);
+        assert_weights(animation(true).sample(-2.5), &[0.5, 0.5]);
+    }
+}
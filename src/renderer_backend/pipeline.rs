@@ -1,11 +1,17 @@
 use std::env::current_dir;
 use std::fs;
+use anyhow::{Context, Result};
+use tracing::debug;
 
 pub struct Builder<'lifetime_3> {
     shader_filename: String,
+    shader_source: Option<String>,
     vertex_entry: String,
     fragment_entry: String,
     pixel_format: wgpu::TextureFormat,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_bias: wgpu::DepthBiasState,
+    depth_only: bool,
     vertex_buffer_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
     device: &'lifetime_3 wgpu::Device,
     bind_group_layouts: Vec<&'lifetime_3 wgpu::BindGroupLayout>
@@ -15,9 +21,13 @@ impl<'lifetime_3> Builder<'lifetime_3> {
     pub fn new(device: &'lifetime_3 wgpu::Device) -> Self {
         Builder {
             shader_filename: "dummy".to_string(),
+            shader_source: None,
             vertex_entry: "dummy".to_string(),
             fragment_entry: "dummy".to_string(),
             pixel_format: wgpu::TextureFormat::Rgba8Unorm,
+            depth_format: None,
+            depth_bias: wgpu::DepthBiasState::default(),
+            depth_only: false,
             vertex_buffer_layouts: Vec::new(),
             device: device,
             bind_group_layouts: Vec::new()
@@ -25,6 +35,7 @@ impl<'lifetime_3> Builder<'lifetime_3> {
     }
 
     pub fn reset(&mut self) {
+        self.shader_source = None;
         self.vertex_buffer_layouts.clear();
         self.bind_group_layouts.clear();
     }
@@ -43,10 +54,35 @@ impl<'lifetime_3> Builder<'lifetime_3> {
         self.fragment_entry = fragment_entry.to_string();
     }
 
+    // For shaders put together at runtime, takes the place of the file.
+    pub fn set_shader_source(&mut self, source: String, vertex_entry: &str, fragment_entry: &str) {
+        self.shader_source = Some(source);
+        self.vertex_entry = vertex_entry.to_string();
+        self.fragment_entry = fragment_entry.to_string();
+    }
+
     pub fn set_pixel_format(&mut self, pixel_format: wgpu::TextureFormat) {
         self.pixel_format = pixel_format;
     }
 
+    // Passes that draw with this pipeline need a depth attachment of the same format.
+    pub fn set_depth_format(&mut self, depth_format: wgpu::TextureFormat) {
+        self.depth_format = Some(depth_format);
+    }
+
+    pub fn set_depth_bias(&mut self, constant: i32, slope_scale: f32) {
+        self.depth_bias = wgpu::DepthBiasState {
+            constant,
+            slope_scale,
+            clamp: 0.0
+        };
+    }
+
+    // No fragment stage and no color target, the fragment entry and pixel format are ignored.
+    pub fn set_depth_only(&mut self) {
+        self.depth_only = true;
+    }
+
     pub fn build_pipeline(&mut self, label: &str) -> wgpu::RenderPipeline {
         /*
 
@@ -58,9 +94,10 @@ impl<'lifetime_3> Builder<'lifetime_3> {
 
         */
 
-        let filepath = format!("../src/shaders/{}", self.shader_filename.as_str());
-        println!("{}", filepath);
-        let source_code = fs::read_to_string(filepath).expect("Failed to read shader's source code");
+        let source_code = match self.shader_source.take() {
+            Some(source) => source,
+            None => builtin_shader(&self.shader_filename)
+        };
 
         let shader_module_descriptor = wgpu::ShaderModuleDescriptor {
             label: Some("Shader Module"),
@@ -115,14 +152,21 @@ impl<'lifetime_3> Builder<'lifetime_3> {
                 conservative: false
             },
 
-            fragment: Some(wgpu::FragmentState {
+            fragment: (!self.depth_only).then(|| wgpu::FragmentState {
                 module: &shader_module,
                 entry_point: Some(&self.fragment_entry),
                 targets: &render_targets,
                 compilation_options: wgpu::PipelineCompilationOptions::default()
             }),
 
-            depth_stencil: None,
+            // Less or equal keeps the draw order for geometry at the same depth.
+            depth_stencil: self.depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: self.depth_bias
+            }),
             multisample: wgpu::MultisampleState {
                 count: 1,
                 mask: !0,
@@ -138,3 +182,14 @@ impl<'lifetime_3> Builder<'lifetime_3> {
         render_pipeline
     }
 }
+
+pub fn read_shader(shader_filename: &str) -> Result<String> {
+    let filepath = format!("../src/shaders/{}", shader_filename);
+    debug!("Reading shader {}", filepath);
+    fs::read_to_string(&filepath).with_context(|| format!("Failed to read shader {}", filepath))
+}
+
+// The engine's own shaders, nothing can be drawn without them.
+pub(crate) fn builtin_shader(shader_filename: &str) -> String {
+    read_shader(shader_filename).unwrap_or_else(|e| panic!("{:#}", e))
+}
@@ -0,0 +1,772 @@
+use anyhow::{ensure, Context, Result};
+use image::GenericImageView;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::bind_group;
+use super::bind_group_layout;
+use super::mesh_builder;
+use super::pipeline;
+use super::render_graph::{FrameTargets, PassBuilder, RenderGraph, RenderNode, ResourceId, TextureDesc};
+
+// Physically based bloom: the frame is blurred down a chain of half sized textures and
+// back up again, then blended in. Runs before every other effect, on the HDR values.
+#[derive(Copy, Clone, Debug)]
+pub struct Bloom {
+    pub enabled: bool,
+    pub intensity: f32,
+    // Zero lets every pixel bloom a little, which is what a real lens does.
+    pub threshold: f32,
+    pub knee: f32,
+    pub filter_radius: f32,
+    pub levels: u32
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            enabled: false,
+            intensity: 0.04,
+            threshold: 0.0,
+            knee: 0.5,
+            filter_radius: 1.0,
+            levels: 6
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Pixelate {
+    pub enabled: bool,
+    pub pixel_size: f32
+}
+
+impl Default for Pixelate {
+    fn default() -> Self {
+        Pixelate {
+            enabled: false,
+            pixel_size: 4.0
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ChromaticAberration {
+    pub enabled: bool,
+    pub strength: f32
+}
+
+impl Default for ChromaticAberration {
+    fn default() -> Self {
+        ChromaticAberration {
+            enabled: false,
+            strength: 0.01
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Tonemapper {
+    Reinhard,
+    #[default]
+    Aces,
+    AgX
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Tonemap {
+    pub enabled: bool,
+    pub tonemapper: Tonemapper,
+    pub exposure: f32
+}
+
+// On by default since the scene is drawn in HDR unless RendererConfig::hdr is turned off,
+// and without it everything above 1.0 clips in the final blit.
+impl Default for Tonemap {
+    fn default() -> Self {
+        Tonemap {
+            enabled: true,
+            tonemapper: Tonemapper::default(),
+            exposure: 1.0
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ColorGrading {
+    pub enabled: bool,
+    pub intensity: f32,
+    pub lut: Option<Arc<ColorLut>>
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        ColorGrading {
+            enabled: false,
+            intensity: 1.0,
+            lut: None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Fxaa {
+    pub enabled: bool,
+    pub edge_threshold: f32,
+    pub edge_threshold_min: f32,
+    pub subpixel: f32
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Fxaa {
+            enabled: false,
+            edge_threshold: 0.125,
+            edge_threshold_min: 0.0312,
+            subpixel: 1.0
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Vignette {
+    pub enabled: bool,
+    pub intensity: f32,
+    pub radius: f32,
+    pub softness: f32
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Vignette {
+            enabled: false,
+            intensity: 0.5,
+            radius: 0.9,
+            softness: 0.5
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FilmGrain {
+    pub enabled: bool,
+    pub intensity: f32
+}
+
+impl Default for FilmGrain {
+    fn default() -> Self {
+        FilmGrain {
+            enabled: false,
+            intensity: 0.05
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Gamma {
+    pub enabled: bool,
+    pub gamma: f32
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Gamma {
+            enabled: false,
+            gamma: 2.2
+        }
+    }
+}
+
+// A color grading table, size^3 RGBA8 entries with red changing fastest.
+#[derive(Clone, Debug)]
+pub struct ColorLut {
+    size: u32,
+    data: Vec<u8>
+}
+
+impl ColorLut {
+    pub fn identity(size: u32) -> Self {
+        assert!(size >= 2, "A color table needs at least two entries per channel");
+
+        let max = (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.push((red as f32 / max * 255.0).round() as u8);
+                    data.push((green as f32 / max * 255.0).round() as u8);
+                    data.push((blue as f32 / max * 255.0).round() as u8);
+                    data.push(255);
+                }
+            }
+        }
+
+        ColorLut {
+            size,
+            data
+        }
+    }
+
+    // The common strip layout: size slices of size x size side by side, blue picks the slice.
+    pub fn from_image<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let image = image::open(path).with_context(|| format!("Failed to load color table {}", path.display()))?;
+        let (width, height) = image.dimensions();
+        ensure!(height >= 2 && width == height * height,
+            "Color table {} is {}x{}, expected a strip of {} slices", path.display(), width, height, height);
+
+        let image = image.to_rgba8();
+        let size = height;
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.extend_from_slice(&image.get_pixel(blue * size + red, green).0);
+                }
+            }
+        }
+
+        Ok(ColorLut {
+            size,
+            data
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+// A user effect, its WGSL only needs an fs_main. The source is appended to post_common.wgsl,
+// which provides the fullscreen vertex shader, the input texture and the params.
+#[derive(Clone, Debug)]
+pub struct CustomEffect {
+    pub name: String,
+    pub enabled: bool,
+    pub params: [f32; 8],
+    source: Arc<str>
+}
+
+impl CustomEffect {
+    pub fn new(name: &str, source: &str) -> Self {
+        CustomEffect {
+            name: name.to_string(),
+            enabled: true,
+            params: [0.0; 8],
+            source: Arc::from(source)
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(name: &str, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).with_context(|| format!("Failed to read effect {}", path.display()))?;
+        Ok(Self::new(name, &source))
+    }
+
+    pub fn with_params(mut self, params: [f32; 8]) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+// Settings for the post-process chain, kept as a world resource so systems can change them.
+// The built-in effects run in the order of the fields, custom ones after them in the order they were added.
+#[derive(Clone, Debug, Default)]
+pub struct PostProcess {
+    pub bloom: Bloom,
+    pub pixelate: Pixelate,
+    pub chromatic_aberration: ChromaticAberration,
+    pub tonemap: Tonemap,
+    pub color_grading: ColorGrading,
+    pub fxaa: Fxaa,
+    pub vignette: Vignette,
+    pub film_grain: FilmGrain,
+    pub gamma: Gamma,
+    custom: Vec<CustomEffect>
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_effect(&mut self, effect: CustomEffect) -> usize {
+        self.custom.push(effect);
+        self.custom.len() - 1
+    }
+
+    pub fn effect(&self, index: usize) -> &CustomEffect {
+        &self.custom[index]
+    }
+
+    pub fn effect_mut(&mut self, index: usize) -> &mut CustomEffect {
+        &mut self.custom[index]
+    }
+
+    pub fn effects(&self) -> &[CustomEffect] {
+        &self.custom
+    }
+
+    fn active_effects(&self) -> Vec<ActiveEffect> {
+        let mut effects = Vec::new();
+        let mut builtin = |enabled: bool, file: &'static str, params: &[f32]| {
+            if enabled {
+                let mut padded = [0.0; 8];
+                padded[..params.len()].copy_from_slice(params);
+                effects.push(ActiveEffect {
+                    shader: ShaderKey::File(file),
+                    params: padded
+                });
+            }
+        };
+
+        builtin(self.pixelate.enabled, "post_pixelate.wgsl", &[self.pixelate.pixel_size]);
+        builtin(self.chromatic_aberration.enabled, "post_chromatic_aberration.wgsl", &[self.chromatic_aberration.strength]);
+        builtin(self.tonemap.enabled, "post_tonemap.wgsl", &[self.tonemap.exposure, self.tonemap.tonemapper as u32 as f32]);
+        if let Some(lut) = &self.color_grading.lut {
+            builtin(self.color_grading.enabled, "post_color_grading.wgsl", &[self.color_grading.intensity, lut.size() as f32]);
+        }
+        builtin(self.fxaa.enabled, "post_fxaa.wgsl", &[self.fxaa.edge_threshold, self.fxaa.edge_threshold_min, self.fxaa.subpixel]);
+        builtin(self.vignette.enabled, "post_vignette.wgsl", &[self.vignette.intensity, self.vignette.radius, self.vignette.softness]);
+        builtin(self.film_grain.enabled, "post_film_grain.wgsl", &[self.film_grain.intensity]);
+        builtin(self.gamma.enabled, "post_gamma.wgsl", &[self.gamma.gamma]);
+
+        for effect in self.custom.iter().filter(|effect| effect.enabled) {
+            effects.push(ActiveEffect {
+                shader: ShaderKey::Source(Arc::clone(&effect.source)),
+                params: effect.params
+            });
+        }
+        effects
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ShaderKey {
+    File(&'static str),
+    Source(Arc<str>)
+}
+
+impl ShaderKey {
+    fn uses_lut(&self) -> bool {
+        *self == ShaderKey::File("post_color_grading.wgsl")
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            ShaderKey::File(file) => file,
+            ShaderKey::Source(_) => "Custom post effect"
+        }
+    }
+}
+
+struct ActiveEffect {
+    shader: ShaderKey,
+    params: [f32; 8]
+}
+
+#[repr(C)]
+struct PostUniform {
+    params: [f32; 8],
+    time: f32,
+    padding: [f32; 3]
+}
+
+struct BloomPipelines {
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+    composite: wgpu::RenderPipeline
+}
+
+struct Layouts {
+    source: wgpu::BindGroupLayout,
+    uniform: wgpu::BindGroupLayout,
+    lut: wgpu::BindGroupLayout
+}
+
+// Runs the chain after the scene and any render nodes. Every effect draws into a new transient,
+// the graph's pool aliases the ones that are done so the chain ping-pongs between two textures.
+#[derive(Default)]
+pub struct PostProcessStack {
+    layouts: Option<Layouts>,
+    effects: Vec<ActiveEffect>,
+    uniforms: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    lut: Option<(Arc<ColorLut>, wgpu::BindGroup)>,
+    bloom_levels: Option<u32>,
+    pipelines: HashMap<(ShaderKey, wgpu::TextureFormat), wgpu::RenderPipeline>,
+    bloom_pipelines: HashMap<wgpu::TextureFormat, BloomPipelines>
+}
+
+impl PostProcessStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.effects.is_empty() || self.bloom_levels.is_some()
+    }
+
+    // Picks up this frame's settings, nothing runs without them.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: Option<&PostProcess>, time: f32) {
+        self.effects = settings.map(|settings| settings.active_effects()).unwrap_or_default();
+        let bloom = settings.map(|settings| settings.bloom).filter(|bloom| bloom.enabled);
+        self.bloom_levels = bloom.map(|bloom| std::cmp::max(bloom.levels, 1));
+        if !self.is_active() {
+            return;
+        }
+
+        let layouts = self.layouts.get_or_insert_with(|| create_layouts(device));
+
+        // Bloom's parameters go in the slot after the effects.
+        let mut params: Vec<[f32; 8]> = self.effects.iter().map(|effect| effect.params).collect();
+        if let Some(bloom) = bloom {
+            params.push([bloom.threshold, bloom.knee, bloom.filter_radius, bloom.intensity, 0.0, 0.0, 0.0, 0.0]);
+        }
+
+        while self.uniforms.len() < params.len() {
+            let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Post process uniform buffer"),
+                size: std::mem::size_of::<PostUniform>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false
+            });
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(&layouts.uniform);
+            builder.add_buffer(&buffer);
+            let bind_group = builder.build("Post process uniform bind group");
+            self.uniforms.push((buffer, bind_group));
+        }
+
+        for (params, (buffer, _)) in params.into_iter().zip(self.uniforms.iter()) {
+            let uniform = PostUniform {
+                params,
+                time,
+                padding: [0.0; 3]
+            };
+            queue.write_buffer(buffer, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+        }
+
+        if let Some(lut) = settings.and_then(|settings| settings.color_grading.lut.as_ref()) {
+            let current = self.lut.as_ref().map(|(uploaded, _)| Arc::ptr_eq(uploaded, lut)).unwrap_or(false);
+            if !current {
+                let bind_group = upload_lut(device, queue, &layouts.lut, lut);
+                self.lut = Some((Arc::clone(lut), bind_group));
+            }
+        }
+    }
+}
+
+impl RenderNode for PostProcessStack {
+    fn name(&self) -> &str {
+        "post_process"
+    }
+
+    fn add_passes<'a>(&'a mut self, device: &wgpu::Device, graph: &mut RenderGraph<'a>, targets: &mut FrameTargets) {
+        let Some(layouts) = &self.layouts else {
+            return;
+        };
+
+        for effect in self.effects.iter() {
+            self.pipelines
+                .entry((effect.shader.clone(), targets.format))
+                .or_insert_with(|| {
+                    let mut bind_group_layouts = vec![&layouts.source, &layouts.uniform];
+                    if effect.shader.uses_lut() {
+                        bind_group_layouts.push(&layouts.lut);
+                    }
+                    create_effect_pipeline(device, &effect.shader, "fs_main", &bind_group_layouts, targets.format)
+                });
+        }
+        if self.bloom_levels.is_some() {
+            self.bloom_pipelines
+                .entry(targets.format)
+                .or_insert_with(|| create_bloom_pipelines(device, layouts, targets.format));
+        }
+
+        let stack = &*self;
+        if let Some(levels) = stack.bloom_levels {
+            stack.add_bloom_passes(graph, targets, layouts, levels);
+        }
+
+        for (index, effect) in stack.effects.iter().enumerate() {
+            let source = targets.color;
+            let output = graph.create_texture(TextureDesc::new(targets.size.0, targets.size.1, targets.format));
+
+            let mut pass = PassBuilder::new();
+            pass.add_input(source);
+            pass.add_color_attachment(output, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+
+            let pipeline = &stack.pipelines[&(effect.shader.clone(), targets.format)];
+            let uniform = &stack.uniforms[index].1;
+            let lut = stack.lut.as_ref().filter(|_| effect.shader.uses_lut()).map(|(_, bind_group)| bind_group);
+            graph.add_render_pass(pass.build(effect.shader.label()), move |render_pass, resources| {
+                let bind_group = resources.bind_texture(source, &layouts.source, "Post process source bind group");
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.set_bind_group(1, uniform, &[]);
+                if let Some(lut) = lut {
+                    render_pass.set_bind_group(2, lut, &[]);
+                }
+                render_pass.draw(0..3, 0..1);
+            });
+
+            targets.color = output;
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl PostProcessStack {
+    fn add_bloom_passes<'a>(&'a self, graph: &mut RenderGraph<'a>, targets: &mut FrameTargets, layouts: &'a Layouts, levels: u32) {
+        let pipelines = &self.bloom_pipelines[&targets.format];
+        let uniform = &self.uniforms[self.effects.len()].1;
+
+        let add_pass = |graph: &mut RenderGraph<'a>, label: &str, pipeline: &'a wgpu::RenderPipeline,
+            source: ResourceId, add: Option<ResourceId>, size: (u32, u32)| -> ResourceId {
+
+            let output = graph.create_texture(TextureDesc::new(size.0, size.1, targets.format));
+            let mut pass = PassBuilder::new();
+            pass.add_input(source);
+            if let Some(add) = add {
+                pass.add_input(add);
+            }
+            pass.add_color_attachment(output, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+
+            graph.add_render_pass(pass.build(label), move |render_pass, resources| {
+                let source = resources.bind_texture(source, &layouts.source, "Bloom source bind group");
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &source, &[]);
+                render_pass.set_bind_group(1, uniform, &[]);
+                if let Some(add) = add {
+                    let add = resources.bind_texture(add, &layouts.source, "Bloom add bind group");
+                    render_pass.set_bind_group(2, &add, &[]);
+                }
+                render_pass.draw(0..3, 0..1);
+            });
+            output
+        };
+
+        let mut chain = Vec::new();
+        let mut source = targets.color;
+        let mut size = targets.size;
+        for level in 0..levels {
+            size = (size.0 / 2, size.1 / 2);
+            if size.0 == 0 || size.1 == 0 {
+                break;
+            }
+            let pipeline = if level == 0 { &pipelines.prefilter } else { &pipelines.downsample };
+            source = add_pass(graph, "Bloom downsample", pipeline, source, None, size);
+            chain.push((source, size));
+        }
+
+        let Some(&(mut upsampled, _)) = chain.last() else {
+            return;
+        };
+        for &(level, size) in chain.iter().rev().skip(1) {
+            upsampled = add_pass(graph, "Bloom upsample", &pipelines.upsample, upsampled, Some(level), size);
+        }
+
+        targets.color = add_pass(graph, "Bloom composite", &pipelines.composite, targets.color, Some(upsampled), targets.size);
+    }
+}
+
+fn create_layouts(device: &wgpu::Device) -> Layouts {
+    let mut builder = bind_group_layout::Builder::new(device);
+    builder.add_material();
+    let source = builder.build("Post process source layout");
+
+    builder.add_uniform_buffer(wgpu::ShaderStages::FRAGMENT);
+    let uniform = builder.build("Post process uniform layout");
+
+    builder.add_material_3d();
+    let lut = builder.build("Post process color table layout");
+
+    Layouts {
+        source,
+        uniform,
+        lut
+    }
+}
+
+fn create_effect_pipeline(device: &wgpu::Device, shader: &ShaderKey, fragment_entry: &str,
+    bind_group_layouts: &[&wgpu::BindGroupLayout], format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+
+    let effect_source = match shader {
+        ShaderKey::File(file) => pipeline::builtin_shader(file),
+        ShaderKey::Source(source) => source.to_string()
+    };
+    let source = pipeline::builtin_shader("post_common.wgsl") + "\n" + &effect_source;
+
+    let mut builder = pipeline::Builder::new(device);
+    builder.set_shader_source(source, "vs_main", fragment_entry);
+    builder.set_pixel_format(format);
+    for layout in bind_group_layouts {
+        builder.add_bind_group_layout(layout);
+    }
+    builder.build_pipeline(shader.label())
+}
+
+fn create_bloom_pipelines(device: &wgpu::Device, layouts: &Layouts, format: wgpu::TextureFormat) -> BloomPipelines {
+    let shader = ShaderKey::File("post_bloom.wgsl");
+    let downsample_layouts = [&layouts.source, &layouts.uniform];
+    let upsample_layouts = [&layouts.source, &layouts.uniform, &layouts.source];
+
+    BloomPipelines {
+        prefilter: create_effect_pipeline(device, &shader, "fs_prefilter", &downsample_layouts, format),
+        downsample: create_effect_pipeline(device, &shader, "fs_downsample", &downsample_layouts, format),
+        upsample: create_effect_pipeline(device, &shader, "fs_upsample", &upsample_layouts, format),
+        composite: create_effect_pipeline(device, &shader, "fs_composite", &upsample_layouts, format)
+    }
+}
+
+fn upload_lut(device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout, lut: &ColorLut) -> wgpu::BindGroup {
+    let size = wgpu::Extent3d {
+        width: lut.size,
+        height: lut.size,
+        depth_or_array_layers: lut.size
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Color table"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[]
+    });
+
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All
+        },
+        &lut.data,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * lut.size),
+            rows_per_image: Some(lut.size)
+        },
+        size
+    );
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        min_filter: wgpu::FilterMode::Linear,
+        mag_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let mut builder = bind_group::Builder::new(device);
+    builder.set_layout(layout);
+    builder.add_material(&view, &sampler);
+    builder.build("Color table bind group")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shader_names(settings: &PostProcess) -> Vec<String> {
+        settings.active_effects().iter().map(|effect| match &effect.shader {
+            ShaderKey::File(file) => file.to_string(),
+            ShaderKey::Source(source) => source.to_string()
+        }).collect()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fuji-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn identity_table_maps_colors_to_themselves() {
+        let lut = ColorLut::identity(3);
+        assert_eq!(lut.size(), 3);
+        assert_eq!(lut.data.len(), 3 * 3 * 3 * 4);
+        let entry = |red: usize, green: usize, blue: usize| {
+            let index = ((blue * 3 + green) * 3 + red) * 4;
+            &lut.data[index..index + 4]
+        };
+        assert_eq!(entry(0, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(entry(2, 1, 0), [255, 128, 0, 255]);
+        assert_eq!(entry(0, 2, 1), [0, 255, 128, 255]);
+        assert_eq!(entry(2, 2, 2), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn strips_are_reordered_into_a_cube() {
+        // Two 2x2 slices side by side, each pixel stores where it came from.
+        let mut strip = image::RgbaImage::new(4, 2);
+        for (x, y, pixel) in strip.enumerate_pixels_mut() {
+            *pixel = image::Rgba([x as u8, y as u8, 0, 255]);
+        }
+        let path = temp_path("strip.png");
+        strip.save(&path).unwrap();
+        let lut = ColorLut::from_image(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(lut.size(), 2);
+        for blue in 0..2u8 {
+            for green in 0..2u8 {
+                for red in 0..2u8 {
+                    let index = ((blue as usize * 2 + green as usize) * 2 + red as usize) * 4;
+                    assert_eq!(lut.data[index..index + 4], [blue * 2 + red, green, 0, 255]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn badly_sized_strips_are_rejected() {
+        let path = temp_path("square.png");
+        image::RgbaImage::new(4, 4).save(&path).unwrap();
+        let error = ColorLut::from_image(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", error).contains("4x4"));
+
+        assert!(ColorLut::from_image(temp_path("missing.png")).is_err());
+    }
+
+    #[test]
+    fn effects_run_in_field_order_then_custom() {
+        let mut settings = PostProcess::new();
+        settings.gamma.enabled = true;
+        settings.vignette.enabled = true;
+        settings.pixelate.enabled = true;
+        settings.tonemap.enabled = true;
+        settings.color_grading.enabled = true;
+        settings.color_grading.lut = Some(Arc::new(ColorLut::identity(2)));
+        settings.add_effect(CustomEffect::new("first", "first"));
+        let second = settings.add_effect(CustomEffect::new("second", "second"));
+        settings.add_effect(CustomEffect::new("third", "third"));
+        settings.effect_mut(second).enabled = false;
+
+        assert_eq!(shader_names(&settings), [
+            "post_pixelate.wgsl", "post_tonemap.wgsl", "post_color_grading.wgsl",
+            "post_vignette.wgsl", "post_gamma.wgsl", "first", "third"
+        ]);
+    }
+
+    #[test]
+    fn default_settings_tonemap() {
+        assert_eq!(shader_names(&PostProcess::default()), ["post_tonemap.wgsl"]);
+    }
+
+    #[test]
+    fn color_grading_needs_a_table() {
+        let mut settings = PostProcess::new();
+        settings.tonemap.enabled = false;
+        settings.color_grading.enabled = true;
+        assert!(shader_names(&settings).is_empty());
+    }
+}
@@ -0,0 +1,634 @@
+use super::bind_group;
+use super::texture::RenderTarget;
+
+// A pass that reads a resource runs after the last pass added before it that writes it. A pass
+// that writes a resource runs after the previous writer and every pass that read it since, so
+// later passes can overwrite what earlier ones read. Passes whose results never reach an imported
+// resource are culled unless they are marked as having side effects.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages
+}
+
+impl TextureDesc {
+    pub fn new(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        TextureDesc {
+            width: width.max(1),
+            height: height.max(1),
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        }
+    }
+
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage |= usage;
+        self
+    }
+}
+
+enum Resource<'a> {
+    Transient {
+        desc: TextureDesc
+    },
+    Texture {
+        view: &'a wgpu::TextureView,
+        sampler: Option<&'a wgpu::Sampler>,
+        size: (u32, u32),
+        format: wgpu::TextureFormat
+    },
+    Buffer(&'a wgpu::Buffer)
+}
+
+pub struct PassDesc {
+    name: String,
+    inputs: Vec<ResourceId>,
+    outputs: Vec<ResourceId>,
+    color_attachments: Vec<(ResourceId, wgpu::LoadOp<wgpu::Color>)>,
+    depth_attachment: Option<(ResourceId, wgpu::LoadOp<f32>)>,
+    side_effects: bool
+}
+
+impl PassDesc {
+    fn writes(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.color_attachments.iter()
+            .map(|(id, _)| *id)
+            .chain(self.depth_attachment.iter().map(|(id, _)| *id))
+            .chain(self.outputs.iter().copied())
+    }
+
+    fn uses(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.inputs.iter().copied().chain(self.writes())
+    }
+}
+
+#[derive(Default)]
+pub struct PassBuilder {
+    inputs: Vec<ResourceId>,
+    outputs: Vec<ResourceId>,
+    color_attachments: Vec<(ResourceId, wgpu::LoadOp<wgpu::Color>)>,
+    depth_attachment: Option<(ResourceId, wgpu::LoadOp<f32>)>,
+    side_effects: bool
+}
+
+impl PassBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // A texture sampled or a buffer read by the pass.
+    pub fn add_input(&mut self, resource: ResourceId) {
+        self.inputs.push(resource);
+    }
+
+    // A resource the pass writes outside of its attachments, like a storage buffer or texture.
+    pub fn add_output(&mut self, resource: ResourceId) {
+        self.outputs.push(resource);
+    }
+
+    pub fn add_color_attachment(&mut self, resource: ResourceId, load: wgpu::LoadOp<wgpu::Color>) {
+        self.color_attachments.push((resource, load));
+    }
+
+    pub fn set_depth_attachment(&mut self, resource: ResourceId, load: wgpu::LoadOp<f32>) {
+        self.depth_attachment = Some((resource, load));
+    }
+
+    // Keeps the pass even when nothing in the graph consumes what it writes.
+    pub fn set_side_effects(&mut self) {
+        self.side_effects = true;
+    }
+
+    pub fn build(&mut self, label: &str) -> PassDesc {
+        let desc = PassDesc {
+            name: label.to_string(),
+            inputs: std::mem::take(&mut self.inputs),
+            outputs: std::mem::take(&mut self.outputs),
+            color_attachments: std::mem::take(&mut self.color_attachments),
+            depth_attachment: self.depth_attachment.take(),
+            side_effects: self.side_effects
+        };
+
+        self.reset();
+
+        desc
+    }
+}
+
+type RenderFn<'a> = Box<dyn FnOnce(&mut wgpu::RenderPass<'_>, &PassResources<'_>) + 'a>;
+type EncoderFn<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources<'_>) + 'a>;
+
+enum Execute<'a> {
+    Render(RenderFn<'a>),
+    Encoder(EncoderFn<'a>)
+}
+
+struct Pass<'a> {
+    desc: PassDesc,
+    execute: Execute<'a>
+}
+
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource<'a>>,
+    passes: Vec<Pass<'a>>
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_resource(&mut self, resource: Resource<'a>) -> ResourceId {
+        self.resources.push(resource);
+        ResourceId(self.resources.len() - 1)
+    }
+
+    // A texture that only lives for this frame, it gets memory from the pool when the graph runs.
+    pub fn create_texture(&mut self, desc: TextureDesc) -> ResourceId {
+        self.add_resource(Resource::Transient { desc })
+    }
+
+    pub fn import_texture(&mut self, view: &'a wgpu::TextureView, size: (u32, u32), format: wgpu::TextureFormat) -> ResourceId {
+        self.add_resource(Resource::Texture {
+            view,
+            sampler: None,
+            size,
+            format
+        })
+    }
+
+    pub fn import_target(&mut self, target: &'a RenderTarget) -> ResourceId {
+        self.add_resource(Resource::Texture {
+            view: &target.view,
+            sampler: Some(&target.sampler),
+            size: target.size(),
+            format: target.format
+        })
+    }
+
+    pub fn import_buffer(&mut self, buffer: &'a wgpu::Buffer) -> ResourceId {
+        self.add_resource(Resource::Buffer(buffer))
+    }
+
+    pub fn texture_size(&self, resource: ResourceId) -> (u32, u32) {
+        match &self.resources[resource.0] {
+            Resource::Transient { desc } => (desc.width, desc.height),
+            Resource::Texture { size, .. } => *size,
+            Resource::Buffer(_) => panic!("Resource {:?} is a buffer, not a texture", resource)
+        }
+    }
+
+    pub fn texture_format(&self, resource: ResourceId) -> wgpu::TextureFormat {
+        match &self.resources[resource.0] {
+            Resource::Transient { desc } => desc.format,
+            Resource::Texture { format, .. } => *format,
+            Resource::Buffer(_) => panic!("Resource {:?} is a buffer, not a texture", resource)
+        }
+    }
+
+    pub fn add_render_pass(&mut self, desc: PassDesc, execute: impl FnOnce(&mut wgpu::RenderPass<'_>, &PassResources<'_>) + 'a) {
+        assert!(!desc.color_attachments.is_empty() || desc.depth_attachment.is_some(),
+            "Render pass {} has no attachments", desc.name);
+        self.passes.push(Pass {
+            desc,
+            execute: Execute::Render(Box::new(execute))
+        });
+    }
+
+    // For compute work, copies or anything else that needs the raw encoder.
+    pub fn add_encoder_pass(&mut self, desc: PassDesc, execute: impl FnOnce(&mut wgpu::CommandEncoder, &PassResources<'_>) + 'a) {
+        self.passes.push(Pass {
+            desc,
+            execute: Execute::Encoder(Box::new(execute))
+        });
+    }
+
+    // Returns the live passes in execution order.
+    fn compile(&self) -> Vec<usize> {
+        let pass_count = self.passes.len();
+        let mut writers: Vec<Vec<usize>> = vec![Vec::new(); self.resources.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for resource in pass.desc.writes() {
+                if !writers[resource.0].contains(&index) {
+                    writers[resource.0].push(index);
+                }
+            }
+        }
+
+        // Walk back from everything that leaves the graph.
+        let mut live = vec![false; pass_count];
+        let mut stack = Vec::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            let imported = pass.desc.writes().any(|resource| !matches!(self.resources[resource.0], Resource::Transient { .. }));
+            if pass.desc.side_effects || imported {
+                live[index] = true;
+                stack.push(index);
+            }
+        }
+        while let Some(index) = stack.pop() {
+            for resource in self.passes[index].desc.uses() {
+                for &writer in writers[resource.0].iter().filter(|writer| **writer < index) {
+                    if !live[writer] {
+                        live[writer] = true;
+                        stack.push(writer);
+                    }
+                }
+            }
+        }
+
+        let previous_writer = |resource: ResourceId, index: usize| {
+            writers[resource.0].iter().copied().rev().find(|writer| live[*writer] && *writer < index)
+        };
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); pass_count];
+        for (index, pass) in self.passes.iter().enumerate().filter(|(index, _)| live[*index]) {
+            for resource in pass.desc.inputs.iter() {
+                dependencies[index].extend(previous_writer(*resource, index));
+            }
+            for resource in pass.desc.writes() {
+                let previous = previous_writer(resource, index);
+                dependencies[index].extend(previous);
+                let since = previous.map_or(0, |previous| previous + 1);
+                dependencies[index].extend((since..index).filter(|reader| {
+                    live[*reader] && self.passes[*reader].desc.inputs.contains(&resource)
+                }));
+            }
+        }
+
+        // Kahn's algorithm, always picking the earliest added pass that is ready keeps the order stable.
+        let mut order = Vec::with_capacity(pass_count);
+        let mut done = vec![false; pass_count];
+        let live_count = live.iter().filter(|live| **live).count();
+        while order.len() < live_count {
+            let next = (0..pass_count).find(|index| {
+                live[*index] && !done[*index] && dependencies[*index].iter().all(|dependency| done[*dependency])
+            });
+            match next {
+                Some(index) => {
+                    done[index] = true;
+                    order.push(index);
+                },
+                None => {
+                    let stuck: Vec<&str> = (0..pass_count)
+                        .filter(|index| live[*index] && !done[*index])
+                        .map(|index| self.passes[index].desc.name.as_str())
+                        .collect();
+                    panic!("Render graph has a dependency cycle between passes {:?}", stuck);
+                }
+            }
+        }
+
+        order
+    }
+
+    pub fn execute(self, device: &wgpu::Device, queue: &wgpu::Queue, pool: &mut TexturePool) {
+        let order = self.compile();
+
+        // Transients whose lifetimes don't overlap can share the same texture.
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        for (position, index) in order.iter().enumerate() {
+            for resource in self.passes[*index].desc.uses() {
+                let lifetime = &mut lifetimes[resource.0];
+                *lifetime = Some(match *lifetime {
+                    Some((first, _)) => (first, position),
+                    None => (position, position)
+                });
+            }
+        }
+
+        let mut transients: Vec<(usize, TextureDesc, usize, usize)> = self.resources.iter()
+            .enumerate()
+            .filter_map(|(index, resource)| match (resource, lifetimes[index]) {
+                (Resource::Transient { desc }, Some((first, last))) => Some((index, *desc, first, last)),
+                _ => None
+            })
+            .collect();
+        transients.sort_by_key(|(_, _, first, _)| *first);
+
+        pool.begin_graph(device);
+        let mut allocations: Vec<Option<usize>> = vec![None; self.resources.len()];
+        for (index, desc, first, last) in transients {
+            allocations[index] = Some(pool.acquire(device, desc, first, last));
+        }
+
+        let resolved = self.resources.iter()
+            .enumerate()
+            .map(|(index, resource)| match resource {
+                Resource::Transient { .. } => allocations[index].map(|entry| {
+                    let target = &pool.entries[entry].target;
+                    Resolved::Texture {
+                        view: &target.view,
+                        sampler: &target.sampler,
+                        size: target.size(),
+                        format: target.format
+                    }
+                }),
+                Resource::Texture { view, sampler, size, format } => Some(Resolved::Texture {
+                    view,
+                    sampler: sampler.unwrap_or_else(|| pool.sampler.as_ref().unwrap()),
+                    size: *size,
+                    format: *format
+                }),
+                Resource::Buffer(buffer) => Some(Resolved::Buffer(buffer))
+            })
+            .collect();
+        let resources = PassResources {
+            device,
+            resources: resolved
+        };
+
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render graph encoder")
+        });
+
+        let mut passes: Vec<Option<Pass<'a>>> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            let pass = passes[index].take().unwrap();
+            match pass.execute {
+                Execute::Render(execute) => {
+                    let color_attachments: Vec<Option<wgpu::RenderPassColorAttachment>> = pass.desc.color_attachments.iter()
+                        .map(|(resource, load)| Some(wgpu::RenderPassColorAttachment {
+                            view: resources.view(*resource),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: *load,
+                                store: wgpu::StoreOp::Store
+                            }
+                        }))
+                        .collect();
+
+                    let depth_stencil_attachment = pass.desc.depth_attachment.map(|(resource, load)| wgpu::RenderPassDepthStencilAttachment {
+                        view: resources.view(resource),
+                        depth_ops: Some(wgpu::Operations {
+                            load,
+                            store: wgpu::StoreOp::Store
+                        }),
+                        stencil_ops: None
+                    });
+
+                    let mut render_pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(&pass.desc.name),
+                        color_attachments: &color_attachments,
+                        depth_stencil_attachment,
+                        occlusion_query_set: None,
+                        timestamp_writes: None
+                    });
+                    execute(&mut render_pass, &resources);
+                },
+
+                Execute::Encoder(execute) => execute(&mut command_encoder, &resources)
+            }
+        }
+
+        queue.submit(std::iter::once(command_encoder.finish()));
+    }
+}
+
+enum Resolved<'r> {
+    Texture {
+        view: &'r wgpu::TextureView,
+        sampler: &'r wgpu::Sampler,
+        size: (u32, u32),
+        format: wgpu::TextureFormat
+    },
+    Buffer(&'r wgpu::Buffer)
+}
+
+// What a pass sees of the graph while it records, transients only have memory at this point.
+pub struct PassResources<'r> {
+    device: &'r wgpu::Device,
+    resources: Vec<Option<Resolved<'r>>>
+}
+
+impl<'r> PassResources<'r> {
+    pub fn device(&self) -> &wgpu::Device {
+        self.device
+    }
+
+    fn texture(&self, resource: ResourceId) -> (&wgpu::TextureView, &wgpu::Sampler, (u32, u32), wgpu::TextureFormat) {
+        match &self.resources[resource.0] {
+            Some(Resolved::Texture { view, sampler, size, format }) => (view, sampler, *size, *format),
+            Some(Resolved::Buffer(_)) => panic!("Resource {:?} is a buffer, not a texture", resource),
+            None => panic!("Resource {:?} is not used by any pass that runs", resource)
+        }
+    }
+
+    pub fn view(&self, resource: ResourceId) -> &wgpu::TextureView {
+        self.texture(resource).0
+    }
+
+    pub fn sampler(&self, resource: ResourceId) -> &wgpu::Sampler {
+        self.texture(resource).1
+    }
+
+    pub fn size(&self, resource: ResourceId) -> (u32, u32) {
+        self.texture(resource).2
+    }
+
+    pub fn format(&self, resource: ResourceId) -> wgpu::TextureFormat {
+        self.texture(resource).3
+    }
+
+    pub fn buffer(&self, resource: ResourceId) -> &wgpu::Buffer {
+        match &self.resources[resource.0] {
+            Some(Resolved::Buffer(buffer)) => buffer,
+            _ => panic!("Resource {:?} is not a buffer", resource)
+        }
+    }
+
+    // Binds a texture with its sampler, for layouts built with add_material.
+    pub fn bind_texture(&self, resource: ResourceId, layout: &wgpu::BindGroupLayout, label: &str) -> wgpu::BindGroup {
+        let (view, sampler, _, _) = self.texture(resource);
+        let mut builder = bind_group::Builder::new(self.device);
+        builder.set_layout(layout);
+        builder.add_material(view, sampler);
+        builder.build(label)
+    }
+}
+
+struct PoolEntry {
+    desc: TextureDesc,
+    target: RenderTarget,
+    busy_until: Option<usize>,
+    last_used_frame: u64
+}
+
+// Keeps transient textures alive between frames so graphs don't allocate every frame.
+#[derive(Default)]
+pub struct TexturePool {
+    entries: Vec<PoolEntry>,
+    sampler: Option<wgpu::Sampler>,
+    frame: u64
+}
+
+impl TexturePool {
+    const MAX_UNUSED_FRAMES: u64 = 3;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn texture_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Every graph is submitted before the next one runs, so all textures are free again.
+    fn begin_graph(&mut self, device: &wgpu::Device) {
+        for entry in self.entries.iter_mut() {
+            entry.busy_until = None;
+        }
+
+        if self.sampler.is_none() {
+            self.sampler = Some(device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Render graph sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                min_filter: wgpu::FilterMode::Linear,
+                mag_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }));
+        }
+    }
+
+    fn acquire(&mut self, device: &wgpu::Device, desc: TextureDesc, first: usize, last: usize) -> usize {
+        let free = self.entries.iter().position(|entry| {
+            entry.desc == desc && entry.busy_until.map(|busy_until| busy_until < first).unwrap_or(true)
+        });
+
+        let index = match free {
+            Some(index) => index,
+            None => {
+                let target = RenderTarget::with_usage(device, desc.width, desc.height, desc.format, desc.usage, "Render graph texture");
+                self.entries.push(PoolEntry {
+                    desc,
+                    target,
+                    busy_until: None,
+                    last_used_frame: self.frame
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        let entry = &mut self.entries[index];
+        entry.busy_until = Some(last);
+        entry.last_used_frame = self.frame;
+        index
+    }
+
+    // Textures no graph asked for in a while are released, after a resize for example.
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.entries.retain(|entry| entry.last_used_frame + Self::MAX_UNUSED_FRAMES >= frame);
+    }
+}
+
+// The textures a window frame is built around. A node that writes its result somewhere else
+// points color at it, whatever color holds at the end is what the window shows.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameTargets {
+    pub color: ResourceId,
+    pub backbuffer: ResourceId,
+    pub size: (u32, u32),
+    pub format: wgpu::TextureFormat
+}
+
+// Adds its passes to every window's graph after the scene has been drawn.
+pub trait RenderNode {
+    fn name(&self) -> &str;
+
+    fn add_passes<'a>(&'a mut self, device: &wgpu::Device, graph: &mut RenderGraph<'a>, targets: &mut FrameTargets);
+
+    // Called after a device loss, anything created on the old device has to be rebuilt.
+    fn reset(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(graph: &mut RenderGraph<'_>) -> ResourceId {
+        graph.create_texture(TextureDesc::new(4, 4, wgpu::TextureFormat::Rgba8Unorm))
+    }
+
+    // Imports need a device, so the passes that end a frame are marked as having side effects instead.
+    fn add_pass(graph: &mut RenderGraph<'_>, name: &str, reads: &[ResourceId], writes: &[ResourceId], side_effects: bool) {
+        let mut builder = PassBuilder::new();
+        for resource in reads {
+            builder.add_input(*resource);
+        }
+        for resource in writes {
+            builder.add_output(*resource);
+        }
+        if side_effects {
+            builder.set_side_effects();
+        }
+        graph.add_encoder_pass(builder.build(name), |_, _| {});
+    }
+
+    fn names(graph: &RenderGraph<'_>) -> Vec<String> {
+        graph.compile().into_iter().map(|index| graph.passes[index].desc.name.clone()).collect()
+    }
+
+    #[test]
+    fn passes_run_after_what_they_read() {
+        let mut graph = RenderGraph::new();
+        let shadow = texture(&mut graph);
+        let color = texture(&mut graph);
+        add_pass(&mut graph, "shadow", &[], &[shadow], false);
+        add_pass(&mut graph, "scene", &[shadow], &[color], false);
+        add_pass(&mut graph, "decals", &[], &[color], false);
+        add_pass(&mut graph, "present", &[color], &[], true);
+        assert_eq!(names(&graph), ["shadow", "scene", "decals", "present"]);
+    }
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let mut graph = RenderGraph::new();
+        let color = texture(&mut graph);
+        let unused = texture(&mut graph);
+        add_pass(&mut graph, "scene", &[], &[color], false);
+        add_pass(&mut graph, "unused", &[color], &[unused], false);
+        add_pass(&mut graph, "present", &[color], &[], true);
+        // Written after the only reader, nothing sees it.
+        add_pass(&mut graph, "late", &[], &[color], false);
+        add_pass(&mut graph, "capture", &[], &[], true);
+        assert_eq!(names(&graph), ["scene", "present", "capture"]);
+    }
+
+    #[test]
+    fn a_read_resource_can_be_overwritten() {
+        let mut graph = RenderGraph::new();
+        let color = texture(&mut graph);
+        let copy = texture(&mut graph);
+        add_pass(&mut graph, "scene", &[], &[color], false);
+        add_pass(&mut graph, "copy", &[color], &[copy], false);
+        add_pass(&mut graph, "composite", &[copy], &[color], false);
+        add_pass(&mut graph, "present", &[color], &[], true);
+        assert_eq!(names(&graph), ["scene", "copy", "composite", "present"]);
+    }
+
+    #[test]
+    fn writes_wait_for_earlier_readers() {
+        let mut graph = RenderGraph::new();
+        let color = texture(&mut graph);
+        let bloom = texture(&mut graph);
+        add_pass(&mut graph, "scene", &[], &[color], false);
+        add_pass(&mut graph, "bloom", &[color], &[bloom], false);
+        add_pass(&mut graph, "tonemap", &[color, bloom], &[color], false);
+        add_pass(&mut graph, "present", &[color], &[], true);
+
+        assert_eq!(names(&graph), ["scene", "bloom", "tonemap", "present"]);
+    }
+}
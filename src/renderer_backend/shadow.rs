@@ -0,0 +1,377 @@
+use glm::*;
+use glm::ext::look_at;
+use super::bind_group;
+use super::instancing;
+use super::mesh_builder;
+use super::pipeline;
+use super::texture;
+use crate::scene::{Camera, Viewport, Light, LightKind};
+
+pub const MAX_CASCADES: usize = 4;
+pub const MAX_SPOT_SHADOWS: usize = 4;
+const LAYERS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+
+// World resource. Only lights with shadows turned on cast them, the first directional one
+// gets the cascades and every spot light after it one map of its own, up to MAX_SPOT_SHADOWS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    pub map_size: u32,
+    // Distance in front of the camera where each cascade ends, at most MAX_CASCADES of them.
+    pub cascade_splits: Vec<f32>,
+    // Applied while the maps are drawn, in depth units and scaled by the surface's slope.
+    pub depth_bias: i32,
+    pub slope_bias: f32,
+    // Moves the sampled point off the surface by this many shadow map texels.
+    pub normal_bias: f32,
+    // Texels sampled on each side by the PCF filter, 0 gives hard edges.
+    pub pcf_radius: u32
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            enabled: true,
+            map_size: 2048,
+            cascade_splits: vec![8.0, 20.0, 50.0, 120.0],
+            depth_bias: 2,
+            slope_bias: 2.0,
+            normal_bias: 1.5,
+            pcf_radius: 1
+        }
+    }
+}
+
+impl ShadowSettings {
+    // Blends evenly spaced splits with logarithmic ones, lambda 0 is even and 1 fully logarithmic.
+    pub fn practical_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+        (1..=count).map(|i| {
+            let t = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let even = near + (far - near) * t;
+            even + (logarithmic - even) * lambda
+        }).collect()
+    }
+}
+
+// The shadowed lights of a frame, found once and shared by every view.
+pub struct ShadowCasters {
+    settings: ShadowSettings,
+    directional: Option<Vec3>,
+    // View projection and world size of a texel one unit away from the light.
+    spots: Vec<(Mat4, f32)>
+}
+
+impl Default for ShadowCasters {
+    fn default() -> Self {
+        Self::new(ShadowSettings::default())
+    }
+}
+
+impl ShadowCasters {
+    pub fn new(settings: ShadowSettings) -> Self {
+        ShadowCasters {
+            settings,
+            directional: None,
+            spots: Vec::new()
+        }
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.directional.is_some() || !self.spots.is_empty()
+    }
+
+    // A single texel is enough while nothing casts shadows.
+    pub fn map_size(&self) -> u32 {
+        if self.is_active() { std::cmp::max(self.settings.map_size, 1) } else { 1 }
+    }
+
+    // Returns the first shadow map layer the light was given.
+    pub fn add(&mut self, light: &Light, world_transform: &Mat4) -> Option<u32> {
+        if !self.settings.enabled {
+            return None;
+        }
+
+        let position = *world_transform * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let forward = *world_transform * Vec4::new(0.0, 0.0, -1.0, 0.0);
+        let position = Vec3::new(position.x, position.y, position.z);
+        let direction = normalize(Vec3::new(forward.x, forward.y, forward.z));
+
+        match light.kind {
+            LightKind::Directional if self.directional.is_none() => {
+                self.directional = Some(direction);
+                Some(0)
+            },
+            LightKind::Spot { inner_angle, outer_angle } if self.spots.len() < MAX_SPOT_SHADOWS => {
+                let half_angle = outer_angle.max(inner_angle).clamp(0.01, 1.5);
+                let far = if light.range.is_finite() { light.range.max(0.1) } else { 100.0 };
+                let near = (far * 0.001).max(0.05);
+
+                let mut camera = Camera::perspective(half_angle * 2.0, near, far).looking_at(position, position + direction);
+                camera.up = up_for(direction);
+                let view_projection = camera.view_projection(&Viewport::new((1, 1), 1.0));
+
+                self.spots.push((view_projection, 2.0 * half_angle.tan() / self.settings.map_size as f32));
+                Some((MAX_CASCADES + self.spots.len() - 1) as u32)
+            },
+            _ => None
+        }
+    }
+
+    fn cascade_count(&self) -> usize {
+        self.settings.cascade_splits.len().min(MAX_CASCADES)
+    }
+}
+
+// Looking straight up or down needs another up vector.
+fn up_for(direction: Vec3) -> Vec3 {
+    if direction.y.abs() > 0.99 {
+        Vec3::new(0.0, 0.0, 1.0)
+    } else {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+#[repr(C)]
+struct ShadowUniform {
+    matrices: [Mat4; LAYERS],
+    texel_sizes: [f32; LAYERS],
+    splits: [f32; MAX_CASCADES],
+    view_position: Vec4,
+    view_forward: Vec4,
+    params: Vec4
+}
+
+// Every view fits the cascades to its own camera, so each one draws into maps of its own.
+pub struct ShadowMap {
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    layer_views: Vec<wgpu::TextureView>,
+    layer_bindings: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    uniform: wgpu::Buffer,
+    size: u32
+}
+
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device, layer_layout: &wgpu::BindGroupLayout, size: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow map"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: LAYERS as u32
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..LAYERS as u32).map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: layer,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })).collect();
+
+        // Linear filtering compares the four nearest texels, which smooths every PCF tap a little more.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform = create_uniform_buffer(device, "Shadow buffer", std::mem::size_of::<ShadowUniform>());
+
+        let layer_bindings = (0..LAYERS).map(|_| {
+            let buffer = create_uniform_buffer(device, "Shadow layer buffer", std::mem::size_of::<Mat4>());
+            let mut builder = bind_group::Builder::new(device);
+            builder.set_layout(layer_layout);
+            builder.add_buffer(&buffer);
+            let bind_group = builder.build("Shadow layer bind group");
+            (buffer, bind_group)
+        }).collect();
+
+        ShadowMap {
+            view,
+            sampler,
+            layer_views,
+            layer_bindings,
+            uniform,
+            size
+        }
+    }
+
+    // The uniform and the depth array with its comparison sampler, in the order the lit shaders bind them.
+    pub fn add_bindings<'a>(&'a self, builder: &mut bind_group::Builder<'a>) {
+        builder.add_buffer(&self.uniform);
+        builder.add_material(&self.view, &self.sampler);
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn layer_view(&self, layer: usize) -> &wgpu::TextureView {
+        &self.layer_views[layer]
+    }
+
+    pub fn layer_bind_group(&self, layer: usize) -> &wgpu::BindGroup {
+        &self.layer_bindings[layer].1
+    }
+
+    // Fits the cascades to the camera and uploads what the view samples, returns the layers to draw.
+    pub fn update(&self, queue: &wgpu::Queue, casters: &ShadowCasters, camera: &Camera, viewport: &Viewport) -> Vec<usize> {
+        let settings = casters.settings();
+        let view_projection = camera.view_projection(viewport);
+        let view = camera.view().inverse().unwrap_or(camera.view());
+        let eye = view * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        let forward = view * Vec4::new(0.0, 0.0, -1.0, 0.0);
+        let eye = Vec3::new(eye.x, eye.y, eye.z);
+        let forward = normalize(Vec3::new(forward.x, forward.y, forward.z));
+
+        let mut uniform = ShadowUniform {
+            matrices: [view_projection; LAYERS],
+            texel_sizes: [0.0; LAYERS],
+            splits: [0.0; MAX_CASCADES],
+            view_position: Vec4::new(eye.x, eye.y, eye.z, 0.0),
+            view_forward: Vec4::new(forward.x, forward.y, forward.z, 0.0),
+            params: Vec4::new(settings.normal_bias, settings.pcf_radius as f32, 1.0 / self.size as f32, 0.0)
+        };
+        let mut layers = Vec::new();
+
+        if let (Some(direction), Some(inverse)) = (casters.directional, view_projection.inverse()) {
+            let count = casters.cascade_count();
+            let corners = frustum_corners(&inverse);
+            let depth = |point: Vec3| dot(point - eye, forward);
+            let shadow_distance = settings.cascade_splits[..count].iter().copied().fold(0.0, f32::max);
+
+            let mut near = 0.0;
+            for cascade in 0..count {
+                let far = settings.cascade_splits[cascade].max(near);
+                let mut slice = Vec::with_capacity(8);
+                for (near_corner, far_corner) in corners.iter() {
+                    let (near_depth, far_depth) = (depth(*near_corner), depth(*far_corner));
+                    let along = |distance: f32| ((distance - near_depth) / (far_depth - near_depth).max(0.0001)).clamp(0.0, 1.0);
+                    slice.push(*near_corner + (*far_corner - *near_corner) * along(near));
+                    slice.push(*near_corner + (*far_corner - *near_corner) * along(far));
+                }
+
+                let (matrix, texel_size) = fit_cascade(&slice, direction, shadow_distance, self.size);
+                uniform.matrices[cascade] = matrix;
+                uniform.texel_sizes[cascade] = texel_size;
+                uniform.splits[cascade] = far;
+                layers.push(cascade);
+                near = far;
+            }
+            uniform.view_position.w = count as f32;
+        }
+
+        for (index, (matrix, texel_size)) in casters.spots.iter().enumerate() {
+            let layer = MAX_CASCADES + index;
+            uniform.matrices[layer] = *matrix;
+            uniform.texel_sizes[layer] = *texel_size;
+            layers.push(layer);
+        }
+
+        queue.write_buffer(&self.uniform, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform) });
+        for layer in layers.iter() {
+            queue.write_buffer(&self.layer_bindings[*layer].0, 0, unsafe { mesh_builder::any_as_u8_slice(&uniform.matrices[*layer]) });
+        }
+        layers
+    }
+}
+
+fn create_uniform_buffer(device: &wgpu::Device, label: &str, size: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false
+    })
+}
+
+// The four edges of the view frustum as pairs of near and far corners in world space.
+fn frustum_corners(inverse_view_projection: &Mat4) -> [(Vec3, Vec3); 4] {
+    let corner = |x: f32, y: f32, z: f32| {
+        let point = *inverse_view_projection * Vec4::new(x, y, z, 1.0);
+        Vec3::new(point.x, point.y, point.z) / point.w
+    };
+    [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| (corner(x, y, 0.0), corner(x, y, 1.0)))
+}
+
+// Bounds the slice with a sphere so the cascade keeps its size while the camera turns, and snaps it
+// to whole texels so it doesn't shimmer while the camera moves. Casters up to shadow_distance
+// behind the slice still land in the map.
+fn fit_cascade(slice: &[Vec3], direction: Vec3, shadow_distance: f32, size: u32) -> (Mat4, f32) {
+    let center = slice.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, corner| sum + *corner) / slice.len() as f32;
+    let radius = slice.iter().map(|corner| length(*corner - center)).fold(0.0, f32::max);
+    let radius = ((radius * 16.0).ceil() / 16.0).max(0.0625);
+
+    let near = 0.0;
+    let far = radius * 2.0 + shadow_distance;
+    let view = look_at(center - direction * (radius + shadow_distance), center, up_for(direction));
+    let mut projection = mat4(
+        1.0 / radius, 0.0, 0.0, 0.0,
+        0.0, 1.0 / radius, 0.0, 0.0,
+        0.0, 0.0, 1.0 / (near - far), 0.0,
+        0.0, 0.0, near / (near - far), 1.0
+    );
+
+    let half_size = size as f32 * 0.5;
+    let origin = (projection * view) * Vec4::new(0.0, 0.0, 0.0, 1.0);
+    projection.c3.x += ((origin.x * half_size).round() - origin.x * half_size) / half_size;
+    projection.c3.y += ((origin.y * half_size).round() - origin.y * half_size) / half_size;
+
+    (projection * view, radius * 2.0 / size as f32)
+}
+
+// Depth-only pipelines for both vertex layouts, rebuilt when the bias settings change.
+pub struct ShadowPipelines {
+    pub unlit: wgpu::RenderPipeline,
+    pub lit: wgpu::RenderPipeline,
+    bias: (i32, f32)
+}
+
+impl ShadowPipelines {
+    pub fn new(device: &wgpu::Device, layer_layout: &wgpu::BindGroupLayout, depth_bias: i32, slope_bias: f32) -> Self {
+        let mut builder = pipeline::Builder::new(device);
+        builder.set_shader_module("shadow.wgsl", "vs_main", "");
+        builder.set_depth_format(texture::DEPTH_FORMAT);
+        builder.set_depth_only();
+        builder.set_depth_bias(depth_bias, slope_bias);
+
+        builder.add_vertex_buffer_layout(mesh_builder::Vertex::get_layout());
+        builder.add_vertex_buffer_layout(instancing::InstanceData::get_layout());
+        builder.add_bind_group_layout(layer_layout);
+        let unlit = builder.build_pipeline("Shadow pipeline");
+
+        builder.add_vertex_buffer_layout(mesh_builder::LitVertex::get_layout());
+        builder.add_vertex_buffer_layout(instancing::InstanceData::get_layout());
+        builder.add_bind_group_layout(layer_layout);
+        let lit = builder.build_pipeline("Lit shadow pipeline");
+
+        ShadowPipelines {
+            unlit,
+            lit,
+            bias: (depth_bias, slope_bias)
+        }
+    }
+
+    pub fn has_bias(&self, depth_bias: i32, slope_bias: f32) -> bool {
+        self.bias == (depth_bias, slope_bias)
+    }
+}
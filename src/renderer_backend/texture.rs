@@ -0,0 +1,87 @@
+use super::bind_group;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// An offscreen color texture that passes draw into and later passes sample from.
+pub struct RenderTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+    pub depth_view: Option<wgpu::TextureView>
+}
+
+impl RenderTarget {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, label: &str) -> Self {
+        Self::with_usage(device, width, height, format, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING, label)
+    }
+
+    pub fn with_usage(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages, label: &str) -> Self {
+
+        let width = width.max(1);
+        let height = height.max(1);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            min_filter: wgpu::FilterMode::Linear,
+            mag_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        RenderTarget {
+            texture,
+            view,
+            sampler,
+            format,
+            depth_view: None
+        }
+    }
+
+    // Keeps a depth buffer next to the color texture, so it outlives the pass that fills it.
+    pub fn with_depth(mut self, device: &wgpu::Device) -> Self {
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Render target depth"),
+            size: self.texture.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[]
+        });
+        self.depth_view = Some(depth.create_view(&wgpu::TextureViewDescriptor::default()));
+        self
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.texture.width(), self.texture.height())
+    }
+
+    // Binds the target like a material texture, for the layouts built with add_material.
+    pub fn create_bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, label: &str) -> wgpu::BindGroup {
+        let mut builder = bind_group::Builder::new(device);
+        builder.set_layout(layout);
+        builder.add_material(&self.view, &self.sampler);
+        builder.build(label)
+    }
+}
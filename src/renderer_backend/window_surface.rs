@@ -0,0 +1,155 @@
+use anyhow::{bail, Result};
+use tracing::warn;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use super::camera::CameraBinding;
+use super::lighting::ViewLighting;
+use crate::app::{WindowConfig, VsyncMode};
+use crate::scene::{Camera, Viewport};
+
+pub struct WindowSurface<'lifetime_1> {
+    pub surface: wgpu::Surface<'lifetime_1>,
+    pub config: wgpu::SurfaceConfiguration,
+    pub camera: Camera,
+    pub camera_binding: CameraBinding,
+    pub lighting: Option<ViewLighting>,
+    pub clear_color: Option<wgpu::Color>,
+    present_modes: Vec<wgpu::PresentMode>,
+    minimized: bool,
+    occluded: bool,
+    scale_factor: f64,
+    render_scale: f32
+}
+
+pub fn preferred_format(capabilities: &wgpu::SurfaceCapabilities) -> wgpu::TextureFormat {
+    capabilities
+        .formats
+        .iter()
+        .copied()
+        .filter(|f| f.is_srgb())
+        .next()
+        .unwrap_or(capabilities.formats[0])
+}
+
+impl<'lifetime_1> WindowSurface<'lifetime_1> {
+    pub fn new(window: &Window, surface: wgpu::Surface<'lifetime_1>, adapter: &wgpu::Adapter, device: &wgpu::Device,
+        format: wgpu::TextureFormat, window_config: &WindowConfig, camera_layout: &wgpu::BindGroupLayout) -> Result<Self> {
+
+        let size = window.inner_size();
+        let surface_capabilities = surface.get_capabilities(adapter);
+
+        // Every pipeline is built for one format, so all windows have to share it.
+        if !surface_capabilities.formats.contains(&format) {
+            bail!("Surface for window {:?} does not support {:?}, the format every window is drawn in", window.id(), format);
+        }
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: window_config.vsync.select(&surface_capabilities.present_modes),
+            alpha_mode: surface_capabilities.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: window_config.max_frame_latency
+        };
+        surface.configure(device, &config);
+
+        Ok(WindowSurface {
+            surface,
+            config,
+            camera: window_config.camera,
+            camera_binding: CameraBinding::new(device, camera_layout),
+            lighting: None,
+            clear_color: window_config.clear_color,
+            present_modes: surface_capabilities.present_modes,
+            minimized: size.width == 0 || size.height == 0,
+            occluded: false,
+            scale_factor: window.scale_factor(),
+            render_scale: clamp_render_scale(window_config.render_scale)
+        })
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        Viewport::new((self.config.width, self.config.height), self.scale_factor)
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = clamp_render_scale(render_scale);
+    }
+
+    // The size the scene is drawn at before it gets scaled to the window.
+    pub fn render_size(&self) -> (u32, u32) {
+        (
+            ((self.config.width as f32 * self.render_scale).round() as u32).max(1),
+            ((self.config.height as f32 * self.render_scale).round() as u32).max(1)
+        )
+    }
+
+    // Minimized and fully covered windows have nothing to present to.
+    pub fn is_suspended(&self) -> bool {
+        self.minimized || self.occluded
+    }
+
+    pub fn set_occluded(&mut self, occluded: bool) {
+        self.occluded = occluded;
+    }
+
+    // Returns None when the frame should be skipped, errors only when rendering cannot go on.
+    pub fn acquire(&mut self, device: &wgpu::Device) -> Result<Option<wgpu::SurfaceTexture>, wgpu::SurfaceError> {
+        match self.surface.get_current_texture() {
+            Ok(drawable) => Ok(Some(drawable)),
+            Err(wgpu::SurfaceError::Lost) | Err(wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(device, &self.config);
+                Ok(None)
+            },
+            Err(wgpu::SurfaceError::Timeout) => {
+                warn!("Timed out waiting for the next frame, skipping it");
+                Ok(None)
+            },
+            Err(e) => Err(e)
+        }
+    }
+
+    // Rebuilds everything tied to the device, the surface itself belongs to the instance.
+    pub fn recreate(&mut self, device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout) {
+        self.surface.configure(device, &self.config);
+        self.camera_binding = CameraBinding::new(device, camera_layout);
+        self.lighting = None;
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, new_size: PhysicalSize<u32>) {
+        self.minimized = new_size.width == 0 || new_size.height == 0;
+        if !self.minimized {
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    pub fn set_vsync(&mut self, device: &wgpu::Device, vsync: VsyncMode) {
+        self.config.present_mode = vsync.select(&self.present_modes);
+        self.surface.configure(device, &self.config);
+    }
+
+    pub fn set_max_frame_latency(&mut self, device: &wgpu::Device, latency: u32) {
+        self.config.desired_maximum_frame_latency = latency;
+        self.surface.configure(device, &self.config);
+    }
+}
+
+fn clamp_render_scale(render_scale: f32) -> f32 {
+    if render_scale.is_finite() {
+        render_scale.clamp(0.1, 2.0)
+    } else {
+        1.0
+    }
+}
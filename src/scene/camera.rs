@@ -0,0 +1,140 @@
+use glm::*;
+use glm::ext::{look_at, perspective};
+use super::transform::identity;
+
+// glm follows the OpenGL clip space convention, wgpu expects depth in 0..1.
+pub fn opengl_to_wgpu() -> Mat4 {
+    mat4(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.5, 0.0,
+        0.0, 0.0, 0.5, 1.0
+    )
+}
+
+// Screen maps logical pixels with the origin at the top left, so UI keeps its size on any display.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Clip,
+    Orthographic { height: f32, near: f32, far: f32 },
+    Perspective { fov_y: f32, near: f32, far: f32 },
+    Screen { near: f32, far: f32 }
+}
+
+// The window area a camera draws to, in physical pixels plus the factor to logical ones.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub physical_size: (u32, u32),
+    pub scale_factor: f64
+}
+
+impl Viewport {
+    pub fn new(physical_size: (u32, u32), scale_factor: f64) -> Self {
+        Viewport {
+            physical_size,
+            scale_factor
+        }
+    }
+
+    pub fn logical_size(&self) -> (f32, f32) {
+        let scale_factor = self.scale_factor as f32;
+        (self.physical_size.0 as f32 / scale_factor, self.physical_size.1 as f32 / scale_factor)
+    }
+
+    pub fn aspect(&self) -> f32 {
+        std::cmp::max(self.physical_size.0, 1) as f32 / std::cmp::max(self.physical_size.1, 1) as f32
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub projection: Projection
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 1.0),
+            target: Vec3::new(0.0, 0.0, 0.0),
+            up: Vec3::new(0.0, 1.0, 0.0),
+            projection: Projection::Clip
+        }
+    }
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Self {
+        Camera {
+            projection: Projection::Perspective { fov_y, near, far },
+            ..Default::default()
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Camera {
+            projection: Projection::Orthographic { height, near, far },
+            ..Default::default()
+        }
+    }
+
+    pub fn screen(near: f32, far: f32) -> Self {
+        Camera {
+            position: Vec3::new(0.0, 0.0, 0.0),
+            target: Vec3::new(0.0, 0.0, -1.0),
+            projection: Projection::Screen { near, far },
+            ..Default::default()
+        }
+    }
+
+    pub fn looking_at(mut self, position: Vec3, target: Vec3) -> Self {
+        self.position = position;
+        self.target = target;
+        self
+    }
+
+    pub fn view(&self) -> Mat4 {
+        match self.projection {
+            Projection::Clip | Projection::Screen { .. } => identity(),
+            _ => look_at(self.position, self.target, self.up)
+        }
+    }
+
+    pub fn projection(&self, viewport: &Viewport) -> Mat4 {
+        let aspect = viewport.aspect();
+        match self.projection {
+            Projection::Clip => identity(),
+
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * aspect;
+                mat4(
+                    1.0 / half_width, 0.0, 0.0, 0.0,
+                    0.0, 1.0 / half_height, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (near - far), 0.0,
+                    0.0, 0.0, near / (near - far), 1.0
+                )
+            },
+
+            Projection::Perspective { fov_y, near, far } => {
+                opengl_to_wgpu() * perspective(fov_y, aspect, near, far)
+            },
+
+            Projection::Screen { near, far } => {
+                let (width, height) = viewport.logical_size();
+                mat4(
+                    2.0 / width, 0.0, 0.0, 0.0,
+                    0.0, -2.0 / height, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (near - far), 0.0,
+                    -1.0, 1.0, near / (near - far), 1.0
+                )
+            }
+        }
+    }
+
+    pub fn view_projection(&self, viewport: &Viewport) -> Mat4 {
+        self.projection(viewport) * self.view()
+    }
+}
@@ -0,0 +1,308 @@
+use glm::*;
+use super::{Transform, Renderable};
+use super::transform::identity;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32
+}
+
+pub struct Node {
+    pub name: String,
+    pub renderable: Option<Renderable>,
+    pub visible: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    local: Transform,
+    world: Mat4,
+    dirty: bool
+}
+
+impl Node {
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn local_transform(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn world_transform(&self) -> Mat4 {
+        self.world
+    }
+}
+
+struct Slot {
+    generation: u32,
+    node: Option<Node>
+}
+
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    root: NodeId
+}
+
+impl Default for SceneGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        let mut graph = SceneGraph {
+            slots: Vec::new(),
+            free: Vec::new(),
+            root: NodeId { index: 0, generation: 0 }
+        };
+        graph.root = graph.allocate(Node {
+            name: "Root".to_string(),
+            renderable: None,
+            visible: true,
+            parent: None,
+            children: Vec::new(),
+            local: Transform::default(),
+            world: identity(),
+            dirty: false
+        });
+        graph
+    }
+
+    fn allocate(&mut self, node: Node) -> NodeId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.node = Some(node);
+                NodeId { index, generation: slot.generation }
+            },
+            None => {
+                self.slots.push(Slot { generation: 0, node: Some(node) });
+                NodeId { index: self.slots.len() - 1, generation: 0 }
+            }
+        }
+    }
+
+    pub fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.slots.get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.slots.get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn add_node(&mut self, parent: NodeId, name: &str, transform: Transform) -> NodeId {
+        assert!(self.contains(parent), "Parent node does not exist");
+
+        let id = self.allocate(Node {
+            name: name.to_string(),
+            renderable: None,
+            visible: true,
+            parent: Some(parent),
+            children: Vec::new(),
+            local: transform,
+            world: identity(),
+            dirty: true
+        });
+        self.get_mut(parent).unwrap().children.push(id);
+        id
+    }
+
+    pub fn add_renderable(&mut self, parent: NodeId, name: &str, transform: Transform, renderable: Renderable) -> NodeId {
+        let id = self.add_node(parent, name, transform);
+        self.get_mut(id).unwrap().renderable = Some(renderable);
+        id
+    }
+
+    pub fn remove_node(&mut self, id: NodeId) {
+        if id == self.root || !self.contains(id) {
+            return;
+        }
+
+        if let Some(parent) = self.get(id).unwrap().parent {
+            self.get_mut(parent).unwrap().children.retain(|child| *child != id);
+        }
+
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            let slot = &mut self.slots[current.index];
+            if let Some(node) = slot.node.take() {
+                stack.extend(node.children);
+                slot.generation += 1;
+                self.free.push(current.index);
+            }
+        }
+    }
+
+    pub fn set_parent(&mut self, id: NodeId, parent: NodeId) {
+        assert!(id != self.root, "The root node can't be reparented");
+        assert!(self.contains(id) && self.contains(parent), "Node does not exist");
+
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            assert!(current != id, "Reparenting would create a cycle");
+            ancestor = self.get(current).unwrap().parent;
+        }
+
+        if let Some(old_parent) = self.get(id).unwrap().parent {
+            self.get_mut(old_parent).unwrap().children.retain(|child| *child != id);
+        }
+        self.get_mut(parent).unwrap().children.push(id);
+
+        let node = self.get_mut(id).unwrap();
+        node.parent = Some(parent);
+        node.dirty = true;
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, transform: Transform) {
+        if let Some(node) = self.get_mut(id) {
+            node.local = transform;
+            node.dirty = true;
+        }
+    }
+
+    pub fn local_transform_mut(&mut self, id: NodeId) -> Option<&mut Transform> {
+        self.get_mut(id).map(|node| {
+            node.dirty = true;
+            &mut node.local
+        })
+    }
+
+    pub fn world_transform(&self, id: NodeId) -> Option<Mat4> {
+        self.get(id).map(|node| node.world)
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        let mut found = None;
+        self.traverse(|id, node| {
+            if found.is_none() && node.name == name {
+                found = Some(id);
+            }
+        });
+        found
+    }
+
+    pub fn update_world_transforms(&mut self) {
+        let mut stack = vec![(self.root, identity(), false)];
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = self.get_mut(id).unwrap();
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.local.to_matrix();
+                node.dirty = false;
+            }
+
+            let world = node.world;
+            for child in node.children.iter().rev() {
+                stack.push((*child, world, changed));
+            }
+        }
+    }
+
+    pub fn traverse<F: FnMut(NodeId, &Node)>(&self, mut visit: F) {
+        let mut stack = vec![self.root];
+
+        while let Some(id) = stack.pop() {
+            let node = self.get(id).unwrap();
+            visit(id, node);
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    pub fn collect_renderables(&self) -> Vec<(Renderable, Mat4)> {
+        let mut renderables = Vec::new();
+        let mut stack = vec![self.root];
+
+        while let Some(id) = stack.pop() {
+            let node = self.get(id).unwrap();
+            if !node.visible {
+                continue;
+            }
+            if let Some(renderable) = node.renderable {
+                renderables.push((renderable, node.world));
+            }
+            stack.extend(node.children.iter().rev());
+        }
+
+        renderables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_position(graph: &SceneGraph, id: NodeId) -> Vec3 {
+        let point = graph.world_transform(id).unwrap() * Vec4::new(0.0, 0.0, 0.0, 1.0);
+        Vec3::new(point.x, point.y, point.z)
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_node(graph.root(), "parent", Transform::from_translation(Vec3::new(1.0, 0.0, 0.0)));
+        let child = graph.add_node(parent, "child", Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)));
+        graph.update_world_transforms();
+        assert_eq!(world_position(&graph, child), Vec3::new(1.0, 2.0, 0.0));
+
+        // Only the parent changed, its dirty flag carries down to the child.
+        graph.set_local_transform(parent, Transform::from_translation(Vec3::new(0.0, 0.0, -3.0)));
+        graph.update_world_transforms();
+        assert_eq!(world_position(&graph, parent), Vec3::new(0.0, 0.0, -3.0));
+        assert_eq!(world_position(&graph, child), Vec3::new(0.0, 2.0, -3.0));
+
+        let other = graph.add_node(graph.root(), "other", Transform::from_translation(Vec3::new(5.0, 0.0, 0.0)));
+        graph.set_parent(child, other);
+        graph.update_world_transforms();
+        assert_eq!(world_position(&graph, child), Vec3::new(5.0, 2.0, 0.0));
+        assert!(graph.get(parent).unwrap().children().is_empty());
+        assert_eq!(graph.get(child).unwrap().parent(), Some(other));
+    }
+
+    #[test]
+    fn removed_ids_go_stale() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_node(graph.root(), "parent", Transform::default());
+        let child = graph.add_node(parent, "child", Transform::default());
+        graph.remove_node(parent);
+        assert!(!graph.contains(parent));
+        assert!(!graph.contains(child));
+        assert!(graph.get(graph.root()).unwrap().children().is_empty());
+
+        // The slot is reused under a new generation, the old ids still miss.
+        let reused = graph.add_node(graph.root(), "reused", Transform::default());
+        assert_ne!(reused, parent);
+        assert_ne!(reused, child);
+        assert!(graph.get(parent).is_none() && graph.get(child).is_none());
+        assert_eq!(graph.find("reused"), Some(reused));
+        assert_eq!(graph.find("child"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn reparenting_under_a_descendant_panics() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add_node(graph.root(), "parent", Transform::default());
+        let child = graph.add_node(parent, "child", Transform::default());
+        let grandchild = graph.add_node(child, "grandchild", Transform::default());
+        graph.set_parent(parent, grandchild);
+    }
+}
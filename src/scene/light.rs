@@ -0,0 +1,73 @@
+use glm::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    // Angles in radians from the light's direction, the light fades out between the two.
+    Spot { inner_angle: f32, outer_angle: f32 }
+}
+
+// Placed by the entity's Transform, lights shine down their local -Z like cameras do.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    // Only directional and spot lights cast shadows, see ShadowSettings for the limits.
+    pub shadows: bool
+}
+
+impl Light {
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            range: f32::INFINITY,
+            shadows: false
+        }
+    }
+
+    pub fn point(color: Vec3, intensity: f32, range: f32) -> Self {
+        Light {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            shadows: false
+        }
+    }
+
+    pub fn spot(color: Vec3, intensity: f32, range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Light {
+            kind: LightKind::Spot { inner_angle, outer_angle },
+            color,
+            intensity,
+            range,
+            shadows: false
+        }
+    }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.shadows = true;
+        self
+    }
+}
+
+// World resource, lights every lit surface evenly on top of the scene's lights.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AmbientLight {
+    pub color: Vec3,
+    pub intensity: f32
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        AmbientLight {
+            color: Vec3::new(1.0, 1.0, 1.0),
+            intensity: 0.03
+        }
+    }
+}
@@ -0,0 +1,37 @@
+pub mod transform;
+pub mod graph;
+pub mod camera;
+pub mod light;
+
+pub use transform::{Transform, PreviousTransform};
+pub use graph::{SceneGraph, Node, NodeId};
+pub use camera::{Camera, Projection, Viewport};
+pub use light::{Light, LightKind, AmbientLight};
+
+use glm::Vec4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshHandle(pub usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialHandle(pub usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RenderTargetHandle(pub usize);
+
+#[derive(Copy, Clone, Debug)]
+pub struct Renderable {
+    pub mesh: MeshHandle,
+    pub material: MaterialHandle,
+    pub tint: Vec4
+}
+
+impl Renderable {
+    pub fn new(mesh: MeshHandle, material: MaterialHandle) -> Self {
+        Renderable {
+            mesh,
+            material,
+            tint: Vec4::new(1.0, 1.0, 1.0, 1.0)
+        }
+    }
+}
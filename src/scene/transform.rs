@@ -0,0 +1,89 @@
+use glm::*;
+use glm::ext::{translate, rotate, scale};
+
+pub fn identity() -> Mat4 {
+    mat4(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0
+    )
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Vec3,
+    pub scale: Vec3
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: Vec3::new(0.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Vec3) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        let mut matrix = translate(&identity(), self.translation);
+        matrix = rotate(&matrix, self.rotation.z, Vec3::new(0.0, 0.0, 1.0));
+        matrix = rotate(&matrix, self.rotation.y, Vec3::new(0.0, 1.0, 0.0));
+        matrix = rotate(&matrix, self.rotation.x, Vec3::new(1.0, 0.0, 0.0));
+        scale(&matrix, self.scale)
+    }
+
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            translation: self.translation + (other.translation - self.translation) * t,
+            rotation: self.rotation + (other.rotation - self.rotation) * t,
+            scale: self.scale + (other.scale - self.scale) * t
+        }
+    }
+}
+
+// Transform at the start of the last fixed tick, lets rendering blend between ticks.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct PreviousTransform(pub Transform);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_blends_every_component() {
+        let from = Transform::from_translation(Vec3::new(0.0, 2.0, 0.0));
+        let to = Transform::from_translation(Vec3::new(4.0, 2.0, -2.0))
+            .with_rotation(Vec3::new(0.0, 1.0, 0.0))
+            .with_scale(Vec3::new(3.0, 1.0, 1.0));
+
+        assert_eq!(from.lerp(&to, 0.0), from);
+        assert_eq!(from.lerp(&to, 1.0), to);
+
+        let half = from.lerp(&to, 0.5);
+        assert_eq!(half.translation, Vec3::new(2.0, 2.0, -1.0));
+        assert_eq!(half.rotation, Vec3::new(0.0, 0.5, 0.0));
+        assert_eq!(half.scale, Vec3::new(2.0, 1.0, 1.0));
+    }
+}
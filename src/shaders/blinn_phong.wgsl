@@ -0,0 +1,78 @@
+struct PhongMaterial {
+    diffuse: vec4<f32>,
+    specular: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> material: PhongMaterial;
+@group(0) @binding(1) var diffuseTexture: texture_2d<f32>;
+@group(0) @binding(2) var diffuseSampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(8) texCoord: vec2<f32>,
+    @location(9) tangent: vec4<f32>,
+};
+
+struct Instance {
+    @location(2) model_0: vec4<f32>,
+    @location(3) model_1: vec4<f32>,
+    @location(4) model_2: vec4<f32>,
+    @location(5) model_3: vec4<f32>,
+    @location(6) tint: vec4<f32>,
+    @location(7) custom: vec4<f32>,
+};
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) worldPosition: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) texCoord: vec2<f32>,
+    @location(3) tint: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: Vertex, instance: Instance) -> VertexPayload {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+
+    var out: VertexPayload;
+    out.position = camera.view_projection * world_position;
+    out.worldPosition = world_position.xyz;
+    out.normal = (model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.texCoord = vertex.texCoord;
+    out.tint = instance.tint;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let diffuse = textureSample(diffuseTexture, diffuseSampler, in.texCoord) * material.diffuse * in.tint;
+    let n = normalize(in.normal);
+    let v = normalize(camera.position.xyz - in.worldPosition);
+
+    var color = ambient_light() * diffuse.rgb;
+    if has_environment() {
+        color = environment_irradiance(n) * diffuse.rgb;
+    }
+    let cluster = cluster_index(in.position, in.worldPosition);
+    for (var i = 0u; i < cluster_light_count(cluster); i++) {
+        let light = sample_light(cluster_light(cluster, i), in.worldPosition, n);
+        let n_dot_l = max(dot(n, light.direction), 0.0);
+        if n_dot_l <= 0.0 {
+            continue;
+        }
+
+        let h = normalize(light.direction + v);
+        let specular = pow(max(dot(n, h), 0.0), material.specular.w) * material.specular.rgb;
+        color += (diffuse.rgb * n_dot_l + specular) * light.radiance;
+    }
+    return vec4<f32>(color, diffuse.a);
+}
@@ -0,0 +1,106 @@
+// Bins the frame's lights into the view's froxels, one invocation per cluster.
+// Follows cluster_lights in clustering.rs step by step.
+
+struct Light {
+    position: vec4<f32>,
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    cone: vec4<f32>,
+};
+
+struct Lights {
+    ambient: vec4<f32>,
+    count: u32,
+    directional_count: u32,
+    environment: vec2<f32>,
+    lights: array<Light>,
+};
+
+struct Clusters {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    grid: vec4<u32>,
+    screen: vec4<f32>,
+    logarithmic: vec4<u32>,
+};
+
+@group(0) @binding(0) var<storage, read> lights: Lights;
+
+@group(1) @binding(0) var<uniform> clusters: Clusters;
+@group(1) @binding(1) var<storage, read_write> clusterCounts: array<u32>;
+@group(1) @binding(2) var<storage, read_write> clusterIndices: array<u32>;
+
+fn slice_depth(slice: u32) -> f32 {
+    let near = clusters.screen.z;
+    let far = clusters.screen.w;
+    let t = f32(slice) / f32(clusters.grid.z);
+    if clusters.logarithmic.x != 0u {
+        return near * pow(far / near, t);
+    }
+    return near + (far - near) * t;
+}
+
+fn view_point(ndc: vec2<f32>, ndc_depth: f32) -> vec3<f32> {
+    let point = clusters.inverse_projection * vec4<f32>(ndc, ndc_depth, 1.0);
+    return point.xyz / point.w;
+}
+
+// Where the ray through ndc reaches the given view depth.
+fn point_at_depth(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let near = view_point(ndc, 0.0);
+    let far = view_point(ndc, 1.0);
+    let span = near.z - far.z;
+    var t = 0.0;
+    if abs(span) > 1.1920929e-7 {
+        t = (depth + near.z) / span;
+    }
+    return near + (far - near) * t;
+}
+
+fn sphere_touches_box(center: vec3<f32>, radius: f32, box_min: vec3<f32>, box_max: vec3<f32>) -> bool {
+    let offset = clamp(center, box_min, box_max) - center;
+    return dot(offset, offset) <= radius * radius;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let grid = clusters.grid;
+    let cluster = id.x;
+    if cluster >= grid.x * grid.y * grid.z {
+        return;
+    }
+
+    let x = cluster % grid.x;
+    let y = (cluster / grid.x) % grid.y;
+    let z = cluster / (grid.x * grid.y);
+
+    let left = f32(x) / f32(grid.x) * 2.0 - 1.0;
+    let right = f32(x + 1u) / f32(grid.x) * 2.0 - 1.0;
+    let top = 1.0 - f32(y) / f32(grid.y) * 2.0;
+    let bottom = 1.0 - f32(y + 1u) / f32(grid.y) * 2.0;
+
+    var box_min = vec3<f32>(3.4028235e38);
+    var box_max = vec3<f32>(-3.4028235e38);
+    let corners = array<vec2<f32>, 4>(vec2<f32>(left, bottom), vec2<f32>(right, bottom), vec2<f32>(left, top), vec2<f32>(right, top));
+    for (var slice = z; slice <= z + 1u; slice++) {
+        let depth = slice_depth(slice);
+        for (var corner = 0u; corner < 4u; corner++) {
+            let point = point_at_depth(corners[corner], depth);
+            box_min = min(box_min, point);
+            box_max = max(box_max, point);
+        }
+    }
+
+    // Directional lights reach every cluster and are left out, they come first in the buffer.
+    let count = min(lights.count, arrayLength(&lights.lights));
+    var binned = 0u;
+    for (var i = min(lights.directional_count, count); i < count; i++) {
+        let light = lights.lights[i];
+        let center = (clusters.view * vec4<f32>(light.position.xyz, 1.0)).xyz;
+        if binned < grid.w && sphere_touches_box(center, light.direction.w, box_min, box_max) {
+            clusterIndices[cluster * grid.w + binned] = i;
+            binned += 1u;
+        }
+    }
+    clusterCounts[cluster] = binned;
+}
@@ -0,0 +1,105 @@
+// Shared by the passes that bake environment maps, each one draws a single face of a cubemap mip.
+// The baking shader's own source is appended below it.
+struct BakeParams {
+    face: u32,
+    roughness: f32,
+    // Mip of the source sampled by passes that don't pick their own.
+    source_lod: f32,
+    source_size: f32,
+};
+
+@group(0) @binding(0) var<uniform> bake: BakeParams;
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texCoord: vec2<f32>,
+};
+
+const PI: f32 = 3.14159265;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexPayload {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexPayload;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.texCoord = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+// World direction through uv on the face being drawn, faces go +X, -X, +Y, -Y, +Z, -Z.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3<f32>(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3<f32>(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3<f32>(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-st.x, -st.y, -1.0)); }
+    }
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// Halfway vector around n for a GGX lobe of the given roughness.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    var up = vec3<f32>(1.0, 0.0, 0.0);
+    if abs(n.z) < 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Image based lighting uses k = a / 2 where direct lights use (r + 1)^2 / 8.
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// Scale and bias to F0 of the split sum, n dot v across and roughness down.
+@fragment
+fn fs_brdf(in: VertexPayload) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.texCoord.x, 0.001);
+    let roughness = in.texCoord.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+
+    let count = 256u;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < count; i++) {
+        let h = importance_sample_ggx(hammersley(i, count), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let g = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    return vec4<f32>(scale / f32(count), bias / f32(count), 0.0, 1.0);
+}
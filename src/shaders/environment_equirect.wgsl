@@ -0,0 +1,10 @@
+// Turns an equirectangular panorama into cubemap faces, appended to environment_common.wgsl.
+@group(1) @binding(0) var panoramaTexture: texture_2d<f32>;
+@group(1) @binding(1) var panoramaSampler: sampler;
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let direction = face_direction(bake.face, in.texCoord);
+    let uv = vec2<f32>(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    return vec4<f32>(textureSampleLevel(panoramaTexture, panoramaSampler, uv, 0.0).rgb, 1.0);
+}
@@ -0,0 +1,63 @@
+// Filters the environment cubemap into its mips and the maps lit surfaces sample, appended to environment_common.wgsl.
+@group(1) @binding(0) var sourceTexture: texture_cube<f32>;
+@group(1) @binding(1) var sourceSampler: sampler;
+
+// The source view only holds the mip above, sampling between four of its texels averages them.
+@fragment
+fn fs_downsample(in: VertexPayload) -> @location(0) vec4<f32> {
+    let direction = face_direction(bake.face, in.texCoord);
+    return vec4<f32>(textureSampleLevel(sourceTexture, sourceSampler, direction, 0.0).rgb, 1.0);
+}
+
+// Cosine weighted average over the hemisphere around the direction, read from a mip about as coarse as the steps.
+@fragment
+fn fs_irradiance(in: VertexPayload) -> @location(0) vec4<f32> {
+    let n = face_direction(bake.face, in.texCoord);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(n.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    let step = 0.05;
+    var irradiance = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += step) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += step) {
+            let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = tangent.x * right + tangent.y * up + tangent.z * n;
+            irradiance += textureSampleLevel(sourceTexture, sourceSampler, direction, bake.source_lod).rgb * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+// GGX prefiltered radiance for one roughness, samples read from coarser mips as they spread out.
+@fragment
+fn fs_specular(in: VertexPayload) -> @location(0) vec4<f32> {
+    let n = face_direction(bake.face, in.texCoord);
+    if bake.roughness == 0.0 {
+        return vec4<f32>(textureSampleLevel(sourceTexture, sourceSampler, n, 0.0).rgb, 1.0);
+    }
+
+    let count = 128u;
+    let texel_solid_angle = 4.0 * PI / (6.0 * bake.source_size * bake.source_size);
+    var radiance = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < count; i++) {
+        let h = importance_sample_ggx(hammersley(i, count), n, bake.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, bake.roughness) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(count) * pdf);
+            let lod = max(0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0, 0.0);
+            radiance += textureSampleLevel(sourceTexture, sourceSampler, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(radiance / max(weight, 0.0001), 1.0);
+}
@@ -0,0 +1,47 @@
+@group(0) @binding(0) var myTexture: texture_2d<f32>;
+@group(0) @binding(1) var mySampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct Instance {
+    @location(2) model_0: vec4<f32>,
+    @location(3) model_1: vec4<f32>,
+    @location(4) model_2: vec4<f32>,
+    @location(5) model_3: vec4<f32>,
+    @location(6) tint: vec4<f32>,
+    @location(7) custom: vec4<f32>,
+};
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) texCoord: vec2<f32>,
+    @location(2) custom: vec4<f32>,
+};
+
+@vertex
+fn vs_main(vertex: Vertex, instance: Instance) -> VertexPayload {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+
+    var out: VertexPayload;
+    out.position = camera.view_projection * model * vec4<f32>(vertex.position, 1.0);
+    out.color = vec4<f32>(vertex.color, 1.0) * instance.tint;
+    out.texCoord = vec2<f32>(0.5 * (vertex.position.x + 1f), -0.5 * (vertex.position.y + 1f));
+    out.custom = instance.custom;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    return in.color * textureSample(myTexture, mySampler, in.texCoord);
+}
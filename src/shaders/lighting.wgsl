@@ -0,0 +1,196 @@
+// Put in front of the lit shaders, which bind the frame's lights and environment maps at group 2 and
+// the view's shadow maps and light clusters at group 3.
+
+struct Light {
+    position: vec4<f32>,
+    direction: vec4<f32>,
+    color: vec4<f32>,
+    cone: vec4<f32>,
+};
+
+struct Lights {
+    ambient: vec4<f32>,
+    count: u32,
+    directional_count: u32,
+    // Intensity, zero without an environment map, and the mip of the roughest specular level.
+    environment: vec2<f32>,
+    lights: array<Light>,
+};
+
+@group(2) @binding(0) var<storage, read> lights: Lights;
+@group(2) @binding(1) var irradianceTexture: texture_cube<f32>;
+@group(2) @binding(2) var irradianceSampler: sampler;
+@group(2) @binding(3) var specularTexture: texture_cube<f32>;
+@group(2) @binding(4) var specularSampler: sampler;
+@group(2) @binding(5) var brdfTexture: texture_2d<f32>;
+@group(2) @binding(6) var brdfSampler: sampler;
+
+// Cascades come first in the matrices, then one layer per shadowed spot light.
+struct Shadows {
+    matrices: array<mat4x4<f32>, 8>,
+    texel_sizes: array<vec4<f32>, 2>,
+    splits: vec4<f32>,
+    view_position: vec4<f32>,
+    view_forward: vec4<f32>,
+    params: vec4<f32>,
+};
+
+@group(3) @binding(0) var<uniform> shadows: Shadows;
+@group(3) @binding(1) var shadowTexture: texture_depth_2d_array;
+@group(3) @binding(2) var shadowSampler: sampler_comparison;
+
+// The view is split into a grid of froxels, each listing the lights that reach into it.
+struct Clusters {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    grid: vec4<u32>,
+    screen: vec4<f32>,
+    logarithmic: vec4<u32>,
+};
+
+@group(3) @binding(3) var<uniform> clusters: Clusters;
+@group(3) @binding(4) var<storage, read> clusterCounts: array<u32>;
+@group(3) @binding(5) var<storage, read> clusterIndices: array<u32>;
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct LightSample {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+};
+
+// The cluster a fragment falls in, from its framebuffer position and where it is in the world.
+fn cluster_index(frag_position: vec4<f32>, world_position: vec3<f32>) -> u32 {
+    let grid = clusters.grid.xyz;
+    let tile = vec2<u32>(clamp(frag_position.xy / clusters.screen.xy * vec2<f32>(grid.xy), vec2<f32>(0.0), vec2<f32>(grid.xy - 1u)));
+
+    let near = clusters.screen.z;
+    let far = clusters.screen.w;
+    let depth = -(clusters.view * vec4<f32>(world_position, 1.0)).z;
+    var slice: f32;
+    if clusters.logarithmic.x != 0u {
+        slice = log(max(depth, near) / near) / log(far / near);
+    } else {
+        slice = (depth - near) / (far - near);
+    }
+    let z = u32(clamp(slice * f32(grid.z), 0.0, f32(grid.z - 1u)));
+
+    return (z * grid.y + tile.y) * grid.x + tile.x;
+}
+
+// Directional lights reach every cluster, the rest only the ones they were binned into.
+fn cluster_light_count(cluster: u32) -> u32 {
+    let directional = min(lights.directional_count, arrayLength(&lights.lights));
+    return directional + min(clusterCounts[cluster], clusters.grid.w);
+}
+
+fn cluster_light(cluster: u32, i: u32) -> Light {
+    let directional = min(lights.directional_count, arrayLength(&lights.lights));
+    if i < directional {
+        return lights.lights[i];
+    }
+    return lights.lights[clusterIndices[cluster * clusters.grid.w + i - directional]];
+}
+
+fn ambient_light() -> vec3<f32> {
+    return lights.ambient.rgb * lights.ambient.a;
+}
+
+fn has_environment() -> bool {
+    return lights.environment.x > 0.0;
+}
+
+// Cosine weighted light arriving from the environment around the normal.
+fn environment_irradiance(normal: vec3<f32>) -> vec3<f32> {
+    return textureSampleLevel(irradianceTexture, irradianceSampler, normal, 0.0).rgb * lights.environment.x;
+}
+
+// Split sum specular from the environment, f0 is the surface's reflectance head on.
+fn environment_specular(normal: vec3<f32>, view: vec3<f32>, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let n_dot_v = max(dot(normal, view), 0.0);
+    let prefiltered = textureSampleLevel(specularTexture, specularSampler, reflect(-view, normal), roughness * lights.environment.y).rgb;
+    let scale_bias = textureSampleLevel(brdfTexture, brdfSampler, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    return prefiltered * (f0 * scale_bias.x + scale_bias.y) * lights.environment.x;
+}
+
+// Inverse square falloff that reaches exactly zero at the light's range.
+fn range_attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / range;
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// How much of the light reaches world_position past the shadow casters, 1 is fully lit.
+fn shadow_factor(light: Light, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if light.cone.z < 0.0 {
+        return 1.0;
+    }
+
+    var layer = u32(light.cone.z);
+    if u32(light.position.w) == LIGHT_DIRECTIONAL {
+        let depth = dot(world_position - shadows.view_position.xyz, shadows.view_forward.xyz);
+        let count = u32(shadows.view_position.w);
+        var cascade = 0u;
+        while cascade < count && depth > shadows.splits[cascade] {
+            cascade += 1u;
+        }
+        if cascade == count {
+            return 1.0;
+        }
+        layer += cascade;
+    }
+
+    // Pushing the point off the surface by a few texels keeps it from shadowing itself.
+    let matrix = shadows.matrices[layer];
+    let texel_size = shadows.texel_sizes[layer / 4u][layer % 4u];
+    let unbiased = matrix * vec4<f32>(world_position, 1.0);
+    let offset = normal * shadows.params.x * texel_size * unbiased.w;
+    let clip = matrix * vec4<f32>(world_position + offset, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    let radius = i32(shadows.params.y);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y++) {
+        for (var x = -radius; x <= radius; x++) {
+            let tap = uv + vec2<f32>(f32(x), f32(y)) * shadows.params.z;
+            lit += textureSampleCompareLevel(shadowTexture, shadowSampler, tap, layer, ndc.z);
+        }
+    }
+    let width = f32(2 * radius + 1);
+    return lit / (width * width);
+}
+
+// Direction towards the light and the radiance arriving at world_position, normal is the surface's geometric normal.
+fn sample_light(light: Light, world_position: vec3<f32>, normal: vec3<f32>) -> LightSample {
+    var out: LightSample;
+    let kind = u32(light.position.w);
+    let radiance = light.color.rgb * light.color.a * shadow_factor(light, world_position, normal);
+
+    if kind == LIGHT_DIRECTIONAL {
+        out.direction = -light.direction.xyz;
+        out.radiance = radiance;
+        return out;
+    }
+
+    let to_light = light.position.xyz - world_position;
+    let distance = length(to_light);
+    out.direction = to_light / max(distance, 0.0001);
+
+    var attenuation = range_attenuation(distance, light.direction.w);
+    if kind == LIGHT_SPOT {
+        let cos_angle = dot(-out.direction, light.direction.xyz);
+        let cone = clamp((cos_angle - light.cone.y) / max(light.cone.x - light.cone.y, 0.0001), 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    out.radiance = radiance * attenuation;
+    return out;
+}
@@ -0,0 +1,63 @@
+@group(0) @binding(0) var myTexture: texture_2d<f32>;
+@group(0) @binding(1) var mySampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct MorphDelta {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+};
+
+struct MorphWeights {
+    vertex_count: u32,
+    target_count: u32,
+    weights: array<vec4<f32>, 16>,
+};
+
+@group(2) @binding(0) var<uniform> morph: MorphWeights;
+@group(2) @binding(1) var<storage, read> deltas: array<MorphDelta>;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) texCoord: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+@vertex
+fn vs_main(vertex: Vertex, @builtin(vertex_index) i: u32) -> VertexPayload {
+    var position = vertex.position;
+    var normal = vertex.normal;
+
+    for (var t: u32 = 0u; t < morph.target_count; t = t + 1u) {
+        let weight = morph.weights[t / 4u][t % 4u];
+        let delta = deltas[t * morph.vertex_count + i];
+        position = position + weight * delta.position.xyz;
+        normal = normal + weight * delta.normal.xyz;
+    }
+
+    var out: VertexPayload;
+    out.position = camera.view_projection * vec4<f32>(position, 1.0);
+    out.color = vertex.color;
+    out.texCoord = vec2<f32>(0.5 * (vertex.position.x + 1f), -0.5 * (vertex.position.y + 1f));
+    out.normal = normalize(normal);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.3, 0.5, 1.0));
+    let shade = 0.4 + 0.6 * max(dot(normalize(in.normal), light), 0.0);
+    return vec4<f32>(in.color * shade, 1.0) * textureSample(myTexture, mySampler, in.texCoord);
+}
@@ -0,0 +1,155 @@
+struct PbrMaterial {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    metallic: f32,
+    roughness: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+};
+
+@group(0) @binding(0) var<uniform> material: PbrMaterial;
+@group(0) @binding(1) var baseColorTexture: texture_2d<f32>;
+@group(0) @binding(2) var baseColorSampler: sampler;
+@group(0) @binding(3) var metallicRoughnessTexture: texture_2d<f32>;
+@group(0) @binding(4) var metallicRoughnessSampler: sampler;
+@group(0) @binding(5) var normalTexture: texture_2d<f32>;
+@group(0) @binding(6) var normalSampler: sampler;
+@group(0) @binding(7) var occlusionTexture: texture_2d<f32>;
+@group(0) @binding(8) var occlusionSampler: sampler;
+@group(0) @binding(9) var emissiveTexture: texture_2d<f32>;
+@group(0) @binding(10) var emissiveSampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(8) texCoord: vec2<f32>,
+    @location(9) tangent: vec4<f32>,
+};
+
+struct Instance {
+    @location(2) model_0: vec4<f32>,
+    @location(3) model_1: vec4<f32>,
+    @location(4) model_2: vec4<f32>,
+    @location(5) model_3: vec4<f32>,
+    @location(6) tint: vec4<f32>,
+    @location(7) custom: vec4<f32>,
+};
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) worldPosition: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tangent: vec4<f32>,
+    @location(3) texCoord: vec2<f32>,
+    @location(4) tint: vec4<f32>,
+};
+
+const PI: f32 = 3.14159265;
+
+@vertex
+fn vs_main(vertex: Vertex, instance: Instance) -> VertexPayload {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let world_position = model * vec4<f32>(vertex.position, 1.0);
+
+    // Fine as long as the scale is uniform, the normals are normalized again per fragment.
+    var out: VertexPayload;
+    out.position = camera.view_projection * world_position;
+    out.worldPosition = world_position.xyz;
+    out.normal = (model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.tangent = vec4<f32>((model * vec4<f32>(vertex.tangent.xyz, 0.0)).xyz, vertex.tangent.w);
+    out.texCoord = vertex.texCoord;
+    out.tint = instance.tint;
+    return out;
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Rough surfaces reflect less of the environment at grazing angles than smooth ones.
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// Light from the environment map when there is one, the flat ambient light otherwise.
+fn ambient(n: vec3<f32>, v: vec3<f32>, albedo: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+    if !has_environment() {
+        return ambient_light() * albedo;
+    }
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let f = fresnel_schlick_roughness(max(dot(n, v), 0.0), f0, roughness);
+    let diffuse = (1.0 - f) * (1.0 - metallic) * environment_irradiance(n) * albedo;
+    return diffuse + environment_specular(n, v, f0, roughness);
+}
+
+// Cook-Torrance specular plus Lambert diffuse for light arriving from light_direction.
+fn brdf(n: vec3<f32>, v: vec3<f32>, light_direction: vec3<f32>, radiance: vec3<f32>,
+    albedo: vec3<f32>, metallic: f32, roughness: f32) -> vec3<f32> {
+
+    let l = normalize(light_direction);
+    let h = normalize(v + l);
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let d = distribution_ggx(max(dot(n, h), 0.0), roughness);
+    let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+    let f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+
+    let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    let diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+fn surface_normal(in: VertexPayload) -> vec3<f32> {
+    let normal = normalize(in.normal);
+    let tangent = normalize(in.tangent.xyz - normal * dot(normal, in.tangent.xyz));
+    let bitangent = cross(normal, tangent) * in.tangent.w;
+
+    var mapped = textureSample(normalTexture, normalSampler, in.texCoord).xyz * 2.0 - 1.0;
+    mapped = vec3<f32>(mapped.xy * material.normal_scale, mapped.z);
+    return normalize(mat3x3<f32>(tangent, bitangent, normal) * mapped);
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let base_color = textureSample(baseColorTexture, baseColorSampler, in.texCoord) * material.base_color * in.tint;
+    let metallic_roughness = textureSample(metallicRoughnessTexture, metallicRoughnessSampler, in.texCoord);
+    let occlusion = textureSample(occlusionTexture, occlusionSampler, in.texCoord).r;
+    let emissive = textureSample(emissiveTexture, emissiveSampler, in.texCoord).rgb * material.emissive.rgb;
+    let n = surface_normal(in);
+
+    let metallic = clamp(material.metallic * metallic_roughness.b, 0.0, 1.0);
+    let roughness = clamp(material.roughness * metallic_roughness.g, 0.04, 1.0);
+    let ao = 1.0 + material.occlusion_strength * (occlusion - 1.0);
+    let v = normalize(camera.position.xyz - in.worldPosition);
+
+    var color = ambient(n, v, base_color.rgb, metallic, roughness) * ao;
+    let cluster = cluster_index(in.position, in.worldPosition);
+    for (var i = 0u; i < cluster_light_count(cluster); i++) {
+        let light = sample_light(cluster_light(cluster, i), in.worldPosition, normalize(in.normal));
+        color += brdf(n, v, light.direction, light.radiance, base_color.rgb, metallic, roughness);
+    }
+    color += emissive;
+    return vec4<f32>(color, base_color.a);
+}
@@ -0,0 +1,94 @@
+// param 0: threshold, param 1: soft knee, param 2: filter radius in texels, param 3: intensity.
+// The level being added in by the upsample, or the bloom for the composite.
+@group(2) @binding(0) var addTexture: texture_2d<f32>;
+@group(2) @binding(1) var addSampler: sampler;
+
+// 13 taps, weighted so the boxes overlap evenly (Jimenez, Next Generation Post Processing in Call of Duty).
+fn downsample(uv: vec2<f32>) -> array<vec3<f32>, 5> {
+    let texel = 1.0 / resolution();
+    let a = sample_source(uv + texel * vec2<f32>(-2.0, -2.0)).rgb;
+    let b = sample_source(uv + texel * vec2<f32>(0.0, -2.0)).rgb;
+    let c = sample_source(uv + texel * vec2<f32>(2.0, -2.0)).rgb;
+    let d = sample_source(uv + texel * vec2<f32>(-2.0, 0.0)).rgb;
+    let e = sample_source(uv).rgb;
+    let f = sample_source(uv + texel * vec2<f32>(2.0, 0.0)).rgb;
+    let g = sample_source(uv + texel * vec2<f32>(-2.0, 2.0)).rgb;
+    let h = sample_source(uv + texel * vec2<f32>(0.0, 2.0)).rgb;
+    let i = sample_source(uv + texel * vec2<f32>(2.0, 2.0)).rgb;
+    let j = sample_source(uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+    let k = sample_source(uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+    let l = sample_source(uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+    let m = sample_source(uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+
+    return array<vec3<f32>, 5>(
+        (a + b + d + e) * 0.25,
+        (b + c + e + f) * 0.25,
+        (d + e + g + h) * 0.25,
+        (e + f + h + i) * 0.25,
+        (j + k + l + m) * 0.25
+    );
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Weighting each box by its brightness keeps single very bright pixels from flickering.
+fn karis_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + luma(color));
+}
+
+fn soft_threshold(color: vec3<f32>) -> vec3<f32> {
+    let threshold = param(0u);
+    let knee = threshold * param(1u) + 0.00001;
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+@fragment
+fn fs_prefilter(in: VertexPayload) -> @location(0) vec4<f32> {
+    let boxes = downsample(in.texCoord);
+    var color = vec3<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var index = 0; index < 5; index++) {
+        let box_weight = select(0.125, 0.5, index == 4);
+        let weight = box_weight * karis_weight(boxes[index]);
+        color += boxes[index] * weight;
+        weight_sum += weight;
+    }
+    return vec4<f32>(soft_threshold(color / weight_sum), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexPayload) -> @location(0) vec4<f32> {
+    let boxes = downsample(in.texCoord);
+    let color = (boxes[0] + boxes[1] + boxes[2] + boxes[3]) * 0.125 + boxes[4] * 0.5;
+    return vec4<f32>(color, 1.0);
+}
+
+// 3x3 tent over the smaller level, added on top of this level's downsample.
+@fragment
+fn fs_upsample(in: VertexPayload) -> @location(0) vec4<f32> {
+    let offset = param(2u) / resolution();
+    let uv = in.texCoord;
+
+    var color = sample_source(uv).rgb * 4.0;
+    color += (sample_source(uv + vec2<f32>(0.0, -offset.y)).rgb + sample_source(uv + vec2<f32>(-offset.x, 0.0)).rgb
+        + sample_source(uv + vec2<f32>(offset.x, 0.0)).rgb + sample_source(uv + vec2<f32>(0.0, offset.y)).rgb) * 2.0;
+    color += sample_source(uv - offset).rgb + sample_source(uv + vec2<f32>(offset.x, -offset.y)).rgb
+        + sample_source(uv + vec2<f32>(-offset.x, offset.y)).rgb + sample_source(uv + offset).rgb;
+
+    let level = textureSampleLevel(addTexture, addSampler, uv, 0.0).rgb;
+    return vec4<f32>(level + color / 16.0, 1.0);
+}
+
+// Energy conserving, the bloom takes its share of the light instead of adding more.
+@fragment
+fn fs_composite(in: VertexPayload) -> @location(0) vec4<f32> {
+    let scene = sample_source(in.texCoord);
+    let bloom = textureSampleLevel(addTexture, addSampler, in.texCoord, 0.0).rgb;
+    return vec4<f32>(mix(scene.rgb, bloom, param(3u)), scene.a);
+}
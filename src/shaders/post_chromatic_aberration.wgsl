@@ -0,0 +1,9 @@
+// param 0: strength, how far the red and blue channels drift apart towards the edges.
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let offset = (in.texCoord - 0.5) * param(0u);
+    let center = sample_source(in.texCoord);
+    let red = sample_source(in.texCoord + offset).r;
+    let blue = sample_source(in.texCoord - offset).b;
+    return vec4<f32>(red, center.g, blue, center.a);
+}
@@ -0,0 +1,14 @@
+// param 0: intensity, param 1: table size.
+@group(2) @binding(0) var lutTexture: texture_3d<f32>;
+@group(2) @binding(1) var lutSampler: sampler;
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let color = sample_source(in.texCoord);
+    let size = param(1u);
+
+    // Keeps the lookup on texel centers so the ends of the table are not blended with the border.
+    let coord = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)) * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(lutTexture, lutSampler, coord, 0.0).rgb;
+    return vec4<f32>(mix(color.rgb, graded, param(0u)), color.a);
+}
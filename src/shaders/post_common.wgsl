@@ -0,0 +1,36 @@
+// Shared by every post-process effect, the effect's own source is appended below it.
+struct PostUniform {
+    params: array<vec4<f32>, 2>,
+    time: f32,
+};
+
+@group(0) @binding(0) var sourceTexture: texture_2d<f32>;
+@group(0) @binding(1) var sourceSampler: sampler;
+@group(1) @binding(0) var<uniform> post: PostUniform;
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texCoord: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexPayload {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexPayload;
+    out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.texCoord = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn param(index: u32) -> f32 {
+    return post.params[index / 4u][index % 4u];
+}
+
+fn resolution() -> vec2<f32> {
+    return vec2<f32>(textureDimensions(sourceTexture));
+}
+
+fn sample_source(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(sourceTexture, sourceSampler, uv, 0.0);
+}
@@ -0,0 +1,12 @@
+// param 0: intensity.
+fn hash(p: vec2<f32>) -> f32 {
+    return fract(sin(dot(p, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let color = sample_source(in.texCoord);
+    let seed = floor(in.texCoord * resolution()) + fract(post.time) * 1000.0;
+    let grain = (hash(seed) - 0.5) * param(0u);
+    return vec4<f32>(color.rgb + grain, color.a);
+}
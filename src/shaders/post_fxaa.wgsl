@@ -0,0 +1,40 @@
+// param 0: edge threshold, param 1: minimum edge threshold, param 2: subpixel blending.
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let texel = 1.0 / resolution();
+    let uv = in.texCoord;
+
+    let center = sample_source(uv);
+    let luma_nw = luma(sample_source(uv + vec2<f32>(-1.0, -1.0) * texel).rgb);
+    let luma_ne = luma(sample_source(uv + vec2<f32>(1.0, -1.0) * texel).rgb);
+    let luma_sw = luma(sample_source(uv + vec2<f32>(-1.0, 1.0) * texel).rgb);
+    let luma_se = luma(sample_source(uv + vec2<f32>(1.0, 1.0) * texel).rgb);
+    let luma_m = luma(center.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if (luma_max - luma_min < max(param(1u), luma_max * param(0u))) {
+        return center;
+    }
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se)
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * param(2u) * 0.125, 1.0 / 128.0);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-8.0), vec2<f32>(8.0)) * texel;
+
+    let color_a = 0.5 * (sample_source(uv + dir * (1.0 / 3.0 - 0.5)).rgb + sample_source(uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    let color_b = color_a * 0.5 + 0.25 * (sample_source(uv - dir * 0.5).rgb + sample_source(uv + dir * 0.5).rgb);
+
+    let luma_b = luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(color_a, center.a);
+    }
+    return vec4<f32>(color_b, center.a);
+}
@@ -0,0 +1,6 @@
+// param 0: gamma.
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let color = sample_source(in.texCoord);
+    return vec4<f32>(pow(max(color.rgb, vec3<f32>(0.0)), vec3<f32>(1.0 / param(0u))), color.a);
+}
@@ -0,0 +1,8 @@
+// param 0: size of a pixel in screen pixels.
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let size = resolution();
+    let cell = max(param(0u), 1.0);
+    let pixel = (floor(in.texCoord * size / cell) + 0.5) * cell;
+    return textureLoad(sourceTexture, vec2<i32>(min(pixel, size - 1.0)), 0);
+}
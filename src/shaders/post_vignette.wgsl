@@ -0,0 +1,8 @@
+// param 0: intensity, param 1: radius, param 2: softness.
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let color = sample_source(in.texCoord);
+    let distance_to_center = distance(in.texCoord, vec2<f32>(0.5)) * 1.41421356;
+    let falloff = smoothstep(param(1u), param(1u) - max(param(2u), 0.0001), distance_to_center);
+    return vec4<f32>(color.rgb * mix(1.0, falloff, param(0u)), color.a);
+}
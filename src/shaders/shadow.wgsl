@@ -0,0 +1,19 @@
+struct ShadowLayer {
+    view_projection: mat4x4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> layer: ShadowLayer;
+
+struct Instance {
+    @location(2) model_0: vec4<f32>,
+    @location(3) model_1: vec4<f32>,
+    @location(4) model_2: vec4<f32>,
+    @location(5) model_3: vec4<f32>,
+};
+
+// Both vertex layouts keep the position at location 0, so one shader draws either kind of mesh.
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, instance: Instance) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return layer.view_projection * model * vec4<f32>(position, 1.0);
+}
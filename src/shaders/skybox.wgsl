@@ -0,0 +1,50 @@
+// Drawn after the scene's meshes, the cube sits at the far plane so only the background shows it.
+struct Skybox {
+    intensity: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> skybox: Skybox;
+@group(0) @binding(1) var environmentTexture: texture_cube<f32>;
+@group(0) @binding(2) var environmentSampler: sampler;
+
+struct Camera {
+    view_projection: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+
+struct VertexPayload {
+    @builtin(position) position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+// Two triangles per face of a cube around the camera, wound to face inwards.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexPayload {
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0)
+    );
+    let corner = corners[index % 6u];
+    let face = index / 6u;
+    let sign = select(1.0, -1.0, face % 2u == 1u);
+    var direction: vec3<f32>;
+    switch face / 2u {
+        case 0u: { direction = vec3<f32>(sign, corner.y, sign * corner.x); }
+        case 1u: { direction = vec3<f32>(corner.x, sign, sign * corner.y); }
+        default: { direction = vec3<f32>(-sign * corner.x, corner.y, sign); }
+    }
+
+    let clip = camera.view_projection * vec4<f32>(camera.position.xyz + direction, 1.0);
+    var out: VertexPayload;
+    out.position = clip.xyww;
+    out.direction = direction;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexPayload) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(environmentTexture, environmentSampler, in.direction, 0.0).rgb;
+    return vec4<f32>(color * skybox.intensity.x, 1.0);
+}